@@ -4,7 +4,7 @@
 extern crate rand;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use flat_files_decoder::read_blocks_from_reader;
+use flat_files_decoder::{read_blocks_from_reader, Compression};
 use std::{
     fs::{self, File},
     io::BufReader,
@@ -31,7 +31,7 @@ fn bench(c: &mut Criterion) {
 
             b.iter(|| {
                 let reader = BufReader::new(File::open(path.as_os_str()).unwrap());
-                read_blocks_from_reader(black_box(reader), false.into())
+                read_blocks_from_reader(black_box(reader), Compression::None)
             });
         }
     });
@@ -7,7 +7,7 @@ use std::{
 };
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use flat_files_decoder::read_block_from_reader;
+use flat_files_decoder::{decode_block_from_bytes, read_block_from_reader, MmapDbinFile};
 use prost::Message;
 
 const ITERS_PER_FILE: usize = 10;
@@ -45,6 +45,50 @@ fn read_decode_check_bench(c: &mut Criterion) {
         }
     });
 
+    group.bench_function("read-message-mmap", |b| {
+        let files = fs::read_dir("tests/benchmark_files/pre_merge").expect("Failed to read dir");
+        for file in files {
+            let path = file.expect("Failed to get path").path();
+            match path.extension() {
+                None => continue,
+                Some(ext) => {
+                    if ext != "dbin" {
+                        continue;
+                    }
+                }
+            }
+            let file = MmapDbinFile::open(&path).expect("Failed to open file");
+
+            b.iter(|| {
+                for message in file.messages() {
+                    black_box(message.unwrap());
+                }
+            });
+        }
+    });
+
+    group.bench_function("decode-block-mmap", |b| {
+        let files = fs::read_dir("tests/benchmark_files/pre_merge").expect("Failed to read dir");
+        for file in files {
+            let path = file.expect("Failed to get path").path();
+            match path.extension() {
+                None => continue,
+                Some(ext) => {
+                    if ext != "dbin" {
+                        continue;
+                    }
+                }
+            }
+            let file = MmapDbinFile::open(&path).expect("Failed to open file");
+            for message in file.messages() {
+                let message = message.unwrap();
+                b.iter(|| {
+                    black_box(decode_block_from_bytes(message)).unwrap();
+                });
+            }
+        }
+    });
+
     group.bench_function("decode-bstream", |b| {
         let files = fs::read_dir("tests/benchmark_files/pre_merge").expect("Failed to read dir");
         for file in files {
@@ -59,13 +103,7 @@ fn read_decode_check_bench(c: &mut Criterion) {
             }
             let file = File::open(&path).expect("Failed to open file");
             let mut reader = BufReader::new(file);
-            loop {
-                let message = match read_block_from_reader(&mut reader) {
-                    Ok(message) => message,
-                    Err(_) => {
-                        break;
-                    }
-                };
+            while let Ok(message) = read_block_from_reader(&mut reader) {
                 b.iter(|| {
                     black_box(firehose_protos::BstreamBlock::decode(message.as_slice())).unwrap();
                 });
@@ -87,13 +125,7 @@ fn read_decode_check_bench(c: &mut Criterion) {
             }
             let file = File::open(&path).expect("Failed to open file");
             let mut reader = BufReader::new(file);
-            loop {
-                let message = match read_block_from_reader(&mut reader) {
-                    Ok(message) => message,
-                    Err(_) => {
-                        break;
-                    }
-                };
+            while let Ok(message) = read_block_from_reader(&mut reader) {
                 let block_stream =
                     firehose_protos::BstreamBlock::decode(message.as_slice()).unwrap();
                 b.iter(|| {
@@ -120,13 +152,7 @@ fn read_decode_check_bench(c: &mut Criterion) {
             }
             let file = File::open(&path).expect("Failed to open file");
             let mut reader = BufReader::new(file);
-            loop {
-                let message = match read_block_from_reader(&mut reader) {
-                    Ok(message) => message,
-                    Err(_) => {
-                        break;
-                    }
-                };
+            while let Ok(message) = read_block_from_reader(&mut reader) {
                 let block_stream =
                     firehose_protos::BstreamBlock::decode(message.as_slice()).unwrap();
                 let block =
@@ -153,13 +179,7 @@ fn read_decode_check_bench(c: &mut Criterion) {
             }
             let file = File::open(&path).expect("Failed to open file");
             let mut reader = BufReader::new(file);
-            loop {
-                let message = match read_block_from_reader(&mut reader) {
-                    Ok(message) => message,
-                    Err(_) => {
-                        break;
-                    }
-                };
+            while let Ok(message) = read_block_from_reader(&mut reader) {
                 let block_stream =
                     firehose_protos::BstreamBlock::decode(message.as_slice()).unwrap();
                 let block =
@@ -2,19 +2,22 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    fs::{self, DirEntry, File},
+    fs::{self, File},
     io::{self, BufReader, BufWriter, Write},
     process::ExitCode,
+    time::Duration,
 };
 
 use alloy_primitives::B256;
 use clap::{Parser, Subcommand};
-use firehose_protos::{BlockHeader, EthBlock as Block};
+use firehose_protos::{BlockHeader, EthBlock as Block, HeaderOnlyBlock, ProtosError};
 use flat_files_decoder::{
-    read_blocks_from_reader, stream_blocks, Compression, DecoderError, Reader,
+    decode_dir_parallel, follow_blocks, follow_headers, read_blocks_from_reader, stream_blocks,
+    stream_headers, Compression, DecoderError, DecompressionOptions, FileError, FollowOptions,
+    ParallelOptions, Reader, DEFAULT_ZSTD_WINDOW_LOG_MAX,
 };
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, level_filters::LevelFilter, subscriber::set_global_default, trace};
+use tracing::{error, info, level_filters::LevelFilter, subscriber::set_global_default};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 fn main() -> ExitCode {
@@ -63,20 +66,40 @@ enum Commands {
         #[clap(short, long)]
         output: Option<String>,
 
-        /// Enables decompression for zstd-compressed flat files
-        #[clap(short, long, default_value = "false")]
+        /// Compression of the flat files: auto, zstd, gzip, bzip2, xz, lz4 or none
+        #[clap(short, long, default_value = "auto")]
         compression: Compression,
     },
 
     /// Stream data continuously
     Stream {
-        /// Decompresses .dbin files if they are zstd-compressed
-        #[clap(short, long, default_value = "false")]
+        /// Compression of the .dbin data: auto, zstd, gzip, bzip2, xz, lz4 or none
+        #[clap(short, long, default_value = "auto")]
         compression: Compression,
 
+        /// Maximum zstd window size accepted, as a power of two, bounding decompression memory
+        #[clap(long, default_value_t = DEFAULT_ZSTD_WINDOW_LOG_MAX)]
+        zstd_window_log_max: u32,
+
         /// Block number to end the streaming process
         #[clap(short, long)]
         end_block: Option<u64>,
+
+        /// Waits for more data at the end of stdin instead of stopping, like `tail -f`
+        #[clap(short, long)]
+        follow: bool,
+
+        /// Maximum delay in milliseconds between polls for more data when following
+        #[clap(long, default_value = "1000")]
+        max_poll_interval_ms: u64,
+
+        /// Stops following after this many seconds without new data
+        #[clap(long)]
+        idle_timeout_secs: Option<u64>,
+
+        /// Decodes only the block headers, skipping transaction traces and verification
+        #[clap(long)]
+        headers_only: bool,
     },
 }
 
@@ -88,20 +111,41 @@ fn run() -> Result<(), DecoderError> {
     match cli.command {
         Stream {
             compression,
+            zstd_window_log_max,
             end_block,
+            follow,
+            max_poll_interval_ms,
+            idle_timeout_secs,
+            headers_only,
         } => {
-            let blocks = stream_blocks(Reader::StdIn(compression), end_block.into())?;
+            let reader = Reader::StdIn(DecompressionOptions {
+                compression,
+                zstd_window_log_max,
+            });
+            let follow = follow.then(|| FollowOptions {
+                max_poll_interval: Duration::from_millis(max_poll_interval_ms),
+                idle_timeout: idle_timeout_secs.map(Duration::from_secs),
+                ..Default::default()
+            });
 
             let mut writer = BufWriter::new(io::stdout().lock());
 
-            for block in blocks {
-                let header_record_with_number = HeaderRecordWithNumber::try_from(&block)?;
-                let header_record_bin = bincode::serialize(&header_record_with_number)?;
-
-                let size = header_record_bin.len() as u32;
-                writer.write_all(&size.to_be_bytes())?;
-                writer.write_all(&header_record_bin)?;
-                writer.flush()?;
+            if headers_only {
+                let headers = match follow {
+                    Some(options) => follow_headers(reader, end_block.into(), options)?,
+                    None => stream_headers(reader, end_block.into())?,
+                };
+                for block in headers {
+                    write_header_record(&mut writer, &HeaderRecordWithNumber::try_from(&block?)?)?;
+                }
+            } else {
+                let blocks = match follow {
+                    Some(options) => follow_blocks(reader, end_block.into(), options)?,
+                    None => stream_blocks(reader, end_block.into())?,
+                };
+                for block in blocks {
+                    write_header_record(&mut writer, &HeaderRecordWithNumber::try_from(&block?)?)?;
+                }
             }
 
             Ok(())
@@ -126,12 +170,27 @@ fn run() -> Result<(), DecoderError> {
     }
 }
 
+/// Writes a header record to the stream output, prefixed with its length.
+fn write_header_record(
+    writer: &mut impl Write,
+    header_record_with_number: &HeaderRecordWithNumber,
+) -> Result<(), DecoderError> {
+    let header_record_bin = bincode::serialize(header_record_with_number)?;
+
+    let size = header_record_bin.len() as u32;
+    writer.write_all(&size.to_be_bytes())?;
+    writer.write_all(&header_record_bin)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
 /// Decodes and optionally verifies block flat files from a given directory or single file.
 ///
 /// This function processes input which can be a file or a directory containing multiple `.dbin` files.
 /// If `headers_dir` is provided, it verifies the block headers against the files found in this directory.
 /// These header files must be in JSON format and named after the block number they represent (e.g., `block-<block number>.json`).
-/// it can also handle `zstd` compressed flat files.
+/// it can also handle compressed flat files.
 ///
 /// # Arguments
 ///
@@ -140,7 +199,7 @@ fn run() -> Result<(), DecoderError> {
 ///             If `None`, decoded blocks are not written to disk.
 /// * `json_headers_dir`: An [`Option<&str>`] specifying the directory containing header files for verification.
 ///                  Must be a directory if provided.
-/// * `compression`: A [`Compression`] enum specifying if it is necessary to decompress from zstd.
+/// * `compression`: A [`Compression`] enum specifying the compression of the flat files, if any.
 fn decode_flat_files(
     input_path: &str,
     output_path: Option<&str>,
@@ -174,10 +233,6 @@ fn decode_flat_files(
     Ok(blocks)
 }
 
-fn create_read_dir(input_path: &str) -> io::Result<fs::ReadDir> {
-    fs::read_dir(input_path)
-}
-
 fn check_block_against_json(block: &Block, headers_dir: &str) -> Result<(), DecoderError> {
     let header_file_path = format!("{}/{}.json", headers_dir, block.number);
     let header_file = File::open(header_file_path)?;
@@ -206,7 +261,7 @@ fn write_block_to_json(block: &Block, output: &str) -> Result<(), DecoderError>
 /// Decodes and verifies block flat files from a single file.
 ///
 /// This function decodes and verifies blocks contained within flat files.
-/// Additionally, the function supports handling `zstd` compressed flat files if decompression is required.
+/// Additionally, the function supports handling compressed flat files if decompression is required.
 fn read_flat_file(path: &str, compression: Compression) -> Result<Vec<Block>, DecoderError> {
     let reader = BufReader::new(File::open(path)?);
 
@@ -215,42 +270,19 @@ fn read_flat_file(path: &str, compression: Compression) -> Result<Vec<Block>, De
     Ok(blocks)
 }
 
-/// Dbin file type extension
-const EXTENSION: &str = "dbin";
-
-/// Checks if the file extension is `.dbin`.
-fn dir_entry_extension_is_dbin(entry: &DirEntry) -> bool {
-    entry
-        .path()
-        .extension()
-        .map_or(false, |ext| ext == EXTENSION)
-}
-
+/// Decodes and verifies all block flat files of a directory, in parallel.
 fn read_flat_files(path: &str, compression: Compression) -> Result<Vec<Block>, DecoderError> {
-    let read_dir = create_read_dir(path)?;
-
-    let mut blocks: Vec<Block> = vec![];
-
-    for path in read_dir {
-        let path = path?;
-
-        if !dir_entry_extension_is_dbin(&path) {
-            continue;
-        }
-
-        trace!("Processing file: {}", path.path().display());
-
-        match read_flat_file(path.path().to_str().unwrap(), compression) {
-            Ok(blocks_vec) => {
-                blocks.extend(blocks_vec);
-            }
-            Err(e) => {
-                return Err(e);
-            }
-        }
-    }
-
-    Ok(blocks)
+    let options = ParallelOptions {
+        decompression: compression.into(),
+        ..Default::default()
+    };
+
+    decode_dir_parallel(path, options)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            error!("Failed to read flat file: {e}");
+            FileError::into_error(e)
+        })
 }
 
 /// A struct to hold the block hash, block number, and total difficulty of a block.
@@ -279,6 +311,27 @@ impl TryFrom<&Block> for HeaderRecordWithNumber {
     }
 }
 
+impl TryFrom<&HeaderOnlyBlock> for HeaderRecordWithNumber {
+    type Error = DecoderError;
+
+    fn try_from(block: &HeaderOnlyBlock) -> Result<Self, Self::Error> {
+        let header = block
+            .header
+            .as_ref()
+            .ok_or(ProtosError::BlockHeaderMissing)?;
+        Ok(HeaderRecordWithNumber {
+            block_hash: header.hash.clone(),
+            block_number: header.number,
+            total_difficulty: header
+                .total_difficulty
+                .as_ref()
+                .ok_or(Self::Error::TotalDifficultyInvalid)?
+                .bytes
+                .clone(),
+        })
+    }
+}
+
 /// A struct to hold the receipt and transactions root for an [`Block`].
 /// This struct is used to compare the receipt and transactions roots of a block
 /// with the receipt and transactions roots of another block.
@@ -29,7 +29,8 @@ fn main() {
 
     let blocks: Vec<Block> = stream_blocks(Reader::Buf(reader), EndBlock::MergeBlock)
         .unwrap()
-        .collect();
+        .collect::<Result<_, _>>()
+        .unwrap();
 
     assert_eq!(blocks.len(), 2);
     println!("read_blocks.rs done");
@@ -0,0 +1,1067 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{
+        ArrayRef, BinaryArray, BooleanArray, FixedSizeBinaryArray, Int32Array, RecordBatch,
+        StringArray, TimestampSecondArray, UInt32Array, UInt64Array,
+    },
+    error::ArrowError,
+};
+use firehose_protos::{BigInt, EthBlock as Block};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+
+use crate::error::DecoderError;
+
+/// The default number of blocks in each Parquet file.
+pub const DEFAULT_BLOCKS_PER_FILE: u64 = 10_000;
+
+/// The default number of blocks buffered before they are written as record batches.
+pub const DEFAULT_BLOCKS_PER_BATCH: usize = 100;
+
+/// The maximum number of rows buffered by each Parquet writer before it writes a row group.
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Size of an Ethereum address, in bytes.
+const ADDRESS_SIZE: i32 = 20;
+
+/// Size of a hash, a storage key or value, or a `BigInt`, in bytes.
+const WORD_SIZE: i32 = 32;
+
+/// A table of the columnar export of blocks.
+///
+/// Addresses are stored as 20-byte fixed binary columns, and hashes, storage slots and `BigInt`
+/// values as 32-byte fixed binary columns, with `BigInt` values big-endian and left-padded.
+/// Every table has a `block_number` column, and rows that come from a transaction have its
+/// `transaction_index`, so that rows can be joined back together. The ordinals of the
+/// transactions, calls, logs and changes are kept, so that their execution order within the
+/// block can be reconstructed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExportTable {
+    /// One row per block, from the block header.
+    Blocks,
+    /// One row per transaction trace.
+    Transactions,
+    /// One row per transaction receipt.
+    Receipts,
+    /// One row per receipt log.
+    Logs,
+    /// One row per call of a transaction, and per system call of the block.
+    Calls,
+    /// One row per balance change of the block or of a call.
+    BalanceChanges,
+    /// One row per storage change of a call.
+    StorageChanges,
+}
+
+impl ExportTable {
+    /// Every table, in export order.
+    pub const ALL: [ExportTable; 7] = [
+        ExportTable::Blocks,
+        ExportTable::Transactions,
+        ExportTable::Receipts,
+        ExportTable::Logs,
+        ExportTable::Calls,
+        ExportTable::BalanceChanges,
+        ExportTable::StorageChanges,
+    ];
+
+    /// Get the name of the table, used as the name of its directory.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportTable::Blocks => "blocks",
+            ExportTable::Transactions => "transactions",
+            ExportTable::Receipts => "receipts",
+            ExportTable::Logs => "logs",
+            ExportTable::Calls => "calls",
+            ExportTable::BalanceChanges => "balance_changes",
+            ExportTable::StorageChanges => "storage_changes",
+        }
+    }
+
+    /// Flatten blocks into a record batch of the table.
+    pub fn record_batch(&self, blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+        match self {
+            ExportTable::Blocks => blocks_batch(blocks),
+            ExportTable::Transactions => transactions_batch(blocks),
+            ExportTable::Receipts => receipts_batch(blocks),
+            ExportTable::Logs => logs_batch(blocks),
+            ExportTable::Calls => calls_batch(blocks),
+            ExportTable::BalanceChanges => balance_changes_batch(blocks),
+            ExportTable::StorageChanges => storage_changes_batch(blocks),
+        }
+    }
+}
+
+/// Set how blocks are exported to Parquet files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParquetExportOptions {
+    /// The number of blocks in each file. Files cover aligned block ranges, so the first file
+    /// of an export that does not start at a multiple of this number holds fewer blocks.
+    pub blocks_per_file: u64,
+    /// The number of blocks buffered before they are flattened and written, which bounds the
+    /// memory used by the export.
+    pub blocks_per_batch: usize,
+}
+
+impl Default for ParquetExportOptions {
+    fn default() -> Self {
+        Self {
+            blocks_per_file: DEFAULT_BLOCKS_PER_FILE,
+            blocks_per_batch: DEFAULT_BLOCKS_PER_BATCH,
+        }
+    }
+}
+
+/// Writes blocks as Parquet files of each [`ExportTable`], partitioned by block range.
+///
+/// Files are written to `<dir>/<table>/<first block>-<last block>.parquet`, with block numbers
+/// padded to ten digits, and compressed with zstd.
+pub struct ParquetExporter {
+    dir: PathBuf,
+    options: ParquetExportOptions,
+    buffer: Vec<Block>,
+    partition: Option<u64>,
+    writers: Vec<(ExportTable, ArrowWriter<File>)>,
+    last_block: Option<u64>,
+    files: Vec<PathBuf>,
+}
+
+impl ParquetExporter {
+    /// Export to the given directory, creating it if needed.
+    pub fn new<P: AsRef<Path>>(
+        dir: P,
+        options: ParquetExportOptions,
+    ) -> Result<Self, DecoderError> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            options: ParquetExportOptions {
+                blocks_per_file: options.blocks_per_file.max(1),
+                blocks_per_batch: options.blocks_per_batch.max(1),
+            },
+            buffer: Vec::new(),
+            partition: None,
+            writers: Vec::new(),
+            last_block: None,
+            files: Vec::new(),
+        })
+    }
+
+    /// Add a block to the export. Blocks must be added in increasing block number order.
+    pub fn write_block(&mut self, block: Block) -> Result<(), DecoderError> {
+        if let Some(previous_block) = self.last_block.filter(|&last| block.number <= last) {
+            return Err(DecoderError::BlockOutOfOrder {
+                block_number: block.number,
+                previous_block,
+            });
+        }
+        self.last_block = Some(block.number);
+
+        let partition = block.number / self.options.blocks_per_file;
+        if self.partition != Some(partition) {
+            self.close_partition()?;
+            self.partition = Some(partition);
+        }
+
+        self.buffer.push(block);
+        if self.buffer.len() >= self.options.blocks_per_batch {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered blocks and close the files, returning the paths of every file
+    /// written by the export.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>, DecoderError> {
+        self.close_partition()?;
+        Ok(self.files)
+    }
+
+    fn flush(&mut self) -> Result<(), DecoderError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        for table in ExportTable::ALL {
+            let batch = table.record_batch(&self.buffer)?;
+            let writer = match self.writers.iter().position(|(t, _)| *t == table) {
+                Some(index) => &mut self.writers[index].1,
+                None => {
+                    let writer = self.create_writer(table, &batch)?;
+                    self.writers.push((table, writer));
+                    &mut self.writers.last_mut().expect("writer was just added").1
+                }
+            };
+            writer.write(&batch)?;
+        }
+
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn close_partition(&mut self) -> Result<(), DecoderError> {
+        self.flush()?;
+        for (_, writer) in self.writers.drain(..) {
+            writer.close()?;
+        }
+        Ok(())
+    }
+
+    fn create_writer(
+        &mut self,
+        table: ExportTable,
+        batch: &RecordBatch,
+    ) -> Result<ArrowWriter<File>, DecoderError> {
+        let first_block = self.partition.unwrap_or_default() * self.options.blocks_per_file;
+        let last_block = first_block + self.options.blocks_per_file - 1;
+        let dir = self.dir.join(table.name());
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{first_block:010}-{last_block:010}.parquet"));
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+        let writer = ArrowWriter::try_new(File::create(&path)?, batch.schema(), Some(properties))?;
+        self.files.push(path);
+        Ok(writer)
+    }
+}
+
+/// Export blocks to Parquet files of each [`ExportTable`], partitioned by block range.
+///
+/// Blocks are consumed one at a time, such as from [`stream_blocks`](crate::stream_blocks) or
+/// [`FlatFileStore::blocks`](crate::FlatFileStore::blocks), so memory use is bounded by
+/// [`ParquetExportOptions::blocks_per_batch`] rather than the number of blocks. Returns the
+/// paths of the files written.
+pub fn export_parquet<I, P>(
+    blocks: I,
+    dir: P,
+    options: ParquetExportOptions,
+) -> Result<Vec<PathBuf>, DecoderError>
+where
+    I: IntoIterator<Item = Result<Block, DecoderError>>,
+    P: AsRef<Path>,
+{
+    let mut exporter = ParquetExporter::new(dir, options)?;
+    for block in blocks {
+        exporter.write_block(block?)?;
+    }
+    exporter.finish()
+}
+
+fn blocks_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let headers: Vec<_> = blocks
+        .iter()
+        .map(|block| (block, block.header.clone().unwrap_or_default()))
+        .collect();
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(headers.iter().map(|(b, _)| b.number)),
+            false,
+        ),
+        (
+            "block_hash",
+            fixed(headers.iter().map(|(b, _)| &b.hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "parent_hash",
+            fixed(headers.iter().map(|(_, h)| &h.parent_hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "uncle_hash",
+            fixed(headers.iter().map(|(_, h)| &h.uncle_hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "coinbase",
+            fixed(headers.iter().map(|(_, h)| &h.coinbase), ADDRESS_SIZE)?,
+            true,
+        ),
+        (
+            "state_root",
+            fixed(headers.iter().map(|(_, h)| &h.state_root), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "transactions_root",
+            fixed(headers.iter().map(|(_, h)| &h.transactions_root), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "receipt_root",
+            fixed(headers.iter().map(|(_, h)| &h.receipt_root), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "logs_bloom",
+            binaries(headers.iter().map(|(_, h)| &h.logs_bloom)),
+            false,
+        ),
+        (
+            "difficulty",
+            big_ints(headers.iter().map(|(_, h)| big_int(&h.difficulty)))?,
+            true,
+        ),
+        (
+            "total_difficulty",
+            big_ints(headers.iter().map(|(_, h)| big_int(&h.total_difficulty)))?,
+            true,
+        ),
+        (
+            "gas_limit",
+            u64s(headers.iter().map(|(_, h)| h.gas_limit)),
+            false,
+        ),
+        (
+            "gas_used",
+            u64s(headers.iter().map(|(_, h)| h.gas_used)),
+            false,
+        ),
+        (
+            "timestamp",
+            Arc::new(
+                TimestampSecondArray::from_iter(
+                    headers
+                        .iter()
+                        .map(|(_, h)| h.timestamp.as_ref().map(|timestamp| timestamp.seconds)),
+                )
+                .with_timezone("UTC"),
+            ),
+            true,
+        ),
+        (
+            "extra_data",
+            binaries(headers.iter().map(|(_, h)| &h.extra_data)),
+            false,
+        ),
+        (
+            "mix_hash",
+            fixed(headers.iter().map(|(_, h)| &h.mix_hash), WORD_SIZE)?,
+            true,
+        ),
+        ("nonce", u64s(headers.iter().map(|(_, h)| h.nonce)), false),
+        (
+            "base_fee_per_gas",
+            big_ints(headers.iter().map(|(_, h)| big_int(&h.base_fee_per_gas)))?,
+            true,
+        ),
+        (
+            "withdrawals_root",
+            fixed(headers.iter().map(|(_, h)| &h.withdrawals_root), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "blob_gas_used",
+            optional_u64s(headers.iter().map(|(_, h)| h.blob_gas_used)),
+            true,
+        ),
+        (
+            "excess_blob_gas",
+            optional_u64s(headers.iter().map(|(_, h)| h.excess_blob_gas)),
+            true,
+        ),
+        (
+            "parent_beacon_root",
+            fixed(
+                headers.iter().map(|(_, h)| &h.parent_beacon_root),
+                WORD_SIZE,
+            )?,
+            true,
+        ),
+        ("size", u64s(headers.iter().map(|(b, _)| b.size)), false),
+        (
+            "uncle_count",
+            u32s(headers.iter().map(|(b, _)| b.uncles.len() as u32)),
+            false,
+        ),
+        (
+            "transaction_count",
+            u32s(
+                headers
+                    .iter()
+                    .map(|(b, _)| b.transaction_traces.len() as u32),
+            ),
+            false,
+        ),
+    ])
+}
+
+fn transactions_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<_> = blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .transaction_traces
+                .iter()
+                .map(move |trace| (block, trace))
+        })
+        .collect();
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(rows.iter().map(|(b, _)| b.number)),
+            false,
+        ),
+        (
+            "transaction_index",
+            u32s(rows.iter().map(|(_, t)| t.index)),
+            false,
+        ),
+        (
+            "transaction_hash",
+            fixed(rows.iter().map(|(_, t)| &t.hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "from",
+            fixed(rows.iter().map(|(_, t)| &t.from), ADDRESS_SIZE)?,
+            true,
+        ),
+        (
+            "to",
+            fixed(rows.iter().map(|(_, t)| &t.to), ADDRESS_SIZE)?,
+            true,
+        ),
+        ("nonce", u64s(rows.iter().map(|(_, t)| t.nonce)), false),
+        (
+            "gas_price",
+            big_ints(rows.iter().map(|(_, t)| big_int(&t.gas_price)))?,
+            true,
+        ),
+        (
+            "gas_limit",
+            u64s(rows.iter().map(|(_, t)| t.gas_limit)),
+            false,
+        ),
+        (
+            "gas_used",
+            u64s(rows.iter().map(|(_, t)| t.gas_used)),
+            false,
+        ),
+        (
+            "value",
+            big_ints(rows.iter().map(|(_, t)| big_int(&t.value)))?,
+            true,
+        ),
+        ("input", binaries(rows.iter().map(|(_, t)| &t.input)), false),
+        ("v", binaries(rows.iter().map(|(_, t)| &t.v)), false),
+        ("r", binaries(rows.iter().map(|(_, t)| &t.r)), false),
+        ("s", binaries(rows.iter().map(|(_, t)| &t.s)), false),
+        ("type", i32s(rows.iter().map(|(_, t)| t.r#type)), false),
+        (
+            "max_fee_per_gas",
+            big_ints(rows.iter().map(|(_, t)| big_int(&t.max_fee_per_gas)))?,
+            true,
+        ),
+        (
+            "max_priority_fee_per_gas",
+            big_ints(
+                rows.iter()
+                    .map(|(_, t)| big_int(&t.max_priority_fee_per_gas)),
+            )?,
+            true,
+        ),
+        ("status", i32s(rows.iter().map(|(_, t)| t.status)), false),
+        (
+            "return_data",
+            binaries(rows.iter().map(|(_, t)| &t.return_data)),
+            false,
+        ),
+        (
+            "blob_gas",
+            optional_u64s(rows.iter().map(|(_, t)| t.blob_gas)),
+            true,
+        ),
+        (
+            "blob_gas_fee_cap",
+            big_ints(rows.iter().map(|(_, t)| big_int(&t.blob_gas_fee_cap)))?,
+            true,
+        ),
+        (
+            "begin_ordinal",
+            u64s(rows.iter().map(|(_, t)| t.begin_ordinal)),
+            false,
+        ),
+        (
+            "end_ordinal",
+            u64s(rows.iter().map(|(_, t)| t.end_ordinal)),
+            false,
+        ),
+    ])
+}
+
+fn receipts_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<_> = blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .transaction_traces
+                .iter()
+                .filter_map(move |trace| Some((block, trace, trace.receipt.as_ref()?)))
+        })
+        .collect();
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(rows.iter().map(|(b, _, _)| b.number)),
+            false,
+        ),
+        (
+            "transaction_index",
+            u32s(rows.iter().map(|(_, t, _)| t.index)),
+            false,
+        ),
+        (
+            "transaction_hash",
+            fixed(rows.iter().map(|(_, t, _)| &t.hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "state_root",
+            binaries(rows.iter().map(|(_, _, r)| &r.state_root)),
+            false,
+        ),
+        (
+            "cumulative_gas_used",
+            u64s(rows.iter().map(|(_, _, r)| r.cumulative_gas_used)),
+            false,
+        ),
+        (
+            "logs_bloom",
+            binaries(rows.iter().map(|(_, _, r)| &r.logs_bloom)),
+            false,
+        ),
+        (
+            "log_count",
+            u32s(rows.iter().map(|(_, _, r)| r.logs.len() as u32)),
+            false,
+        ),
+        (
+            "blob_gas_used",
+            optional_u64s(rows.iter().map(|(_, _, r)| r.blob_gas_used)),
+            true,
+        ),
+        (
+            "blob_gas_price",
+            big_ints(rows.iter().map(|(_, _, r)| big_int(&r.blob_gas_price)))?,
+            true,
+        ),
+    ])
+}
+
+fn logs_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<_> = blocks
+        .iter()
+        .flat_map(|block| {
+            block.transaction_traces.iter().flat_map(move |trace| {
+                trace.receipt.iter().flat_map(move |receipt| {
+                    receipt.logs.iter().map(move |log| (block, trace, log))
+                })
+            })
+        })
+        .collect();
+    let topic = |i: usize| {
+        fixed(
+            rows.iter()
+                .map(move |(_, _, l)| l.topics.get(i).map_or(&[][..], Vec::as_slice)),
+            WORD_SIZE,
+        )
+    };
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(rows.iter().map(|(b, _, _)| b.number)),
+            false,
+        ),
+        (
+            "transaction_index",
+            u32s(rows.iter().map(|(_, t, _)| t.index)),
+            false,
+        ),
+        (
+            "transaction_hash",
+            fixed(rows.iter().map(|(_, t, _)| &t.hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "log_index",
+            u32s(rows.iter().map(|(_, _, l)| l.index)),
+            false,
+        ),
+        (
+            "block_index",
+            u32s(rows.iter().map(|(_, _, l)| l.block_index)),
+            false,
+        ),
+        (
+            "address",
+            fixed(rows.iter().map(|(_, _, l)| &l.address), ADDRESS_SIZE)?,
+            true,
+        ),
+        ("topic0", topic(0)?, true),
+        ("topic1", topic(1)?, true),
+        ("topic2", topic(2)?, true),
+        ("topic3", topic(3)?, true),
+        (
+            "data",
+            binaries(rows.iter().map(|(_, _, l)| &l.data)),
+            false,
+        ),
+        (
+            "ordinal",
+            u64s(rows.iter().map(|(_, _, l)| l.ordinal)),
+            false,
+        ),
+    ])
+}
+
+fn calls_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<_> = blocks
+        .iter()
+        .flat_map(|block| {
+            let system_calls = block
+                .system_calls
+                .iter()
+                .map(move |call| (block, None, call));
+            let calls = block.transaction_traces.iter().flat_map(move |trace| {
+                trace
+                    .calls
+                    .iter()
+                    .map(move |call| (block, Some(trace), call))
+            });
+            system_calls.chain(calls)
+        })
+        .collect();
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(rows.iter().map(|(b, _, _)| b.number)),
+            false,
+        ),
+        (
+            "transaction_index",
+            optional_u32s(rows.iter().map(|(_, t, _)| t.map(|t| t.index))),
+            true,
+        ),
+        (
+            "transaction_hash",
+            fixed(
+                rows.iter().map(|(_, t, _)| t.map_or(&[][..], |t| &t.hash)),
+                WORD_SIZE,
+            )?,
+            true,
+        ),
+        (
+            "call_index",
+            u32s(rows.iter().map(|(_, _, c)| c.index)),
+            false,
+        ),
+        (
+            "parent_index",
+            u32s(rows.iter().map(|(_, _, c)| c.parent_index)),
+            false,
+        ),
+        ("depth", u32s(rows.iter().map(|(_, _, c)| c.depth)), false),
+        (
+            "call_type",
+            i32s(rows.iter().map(|(_, _, c)| c.call_type)),
+            false,
+        ),
+        (
+            "caller",
+            fixed(rows.iter().map(|(_, _, c)| &c.caller), ADDRESS_SIZE)?,
+            true,
+        ),
+        (
+            "address",
+            fixed(rows.iter().map(|(_, _, c)| &c.address), ADDRESS_SIZE)?,
+            true,
+        ),
+        (
+            "value",
+            big_ints(rows.iter().map(|(_, _, c)| big_int(&c.value)))?,
+            true,
+        ),
+        (
+            "gas_limit",
+            u64s(rows.iter().map(|(_, _, c)| c.gas_limit)),
+            false,
+        ),
+        (
+            "gas_consumed",
+            u64s(rows.iter().map(|(_, _, c)| c.gas_consumed)),
+            false,
+        ),
+        (
+            "input",
+            binaries(rows.iter().map(|(_, _, c)| &c.input)),
+            false,
+        ),
+        (
+            "return_data",
+            binaries(rows.iter().map(|(_, _, c)| &c.return_data)),
+            false,
+        ),
+        (
+            "executed_code",
+            bools(rows.iter().map(|(_, _, c)| c.executed_code)),
+            false,
+        ),
+        (
+            "suicide",
+            bools(rows.iter().map(|(_, _, c)| c.suicide)),
+            false,
+        ),
+        (
+            "status_failed",
+            bools(rows.iter().map(|(_, _, c)| c.status_failed)),
+            false,
+        ),
+        (
+            "status_reverted",
+            bools(rows.iter().map(|(_, _, c)| c.status_reverted)),
+            false,
+        ),
+        (
+            "failure_reason",
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|(_, _, c)| &c.failure_reason),
+            )),
+            false,
+        ),
+        (
+            "state_reverted",
+            bools(rows.iter().map(|(_, _, c)| c.state_reverted)),
+            false,
+        ),
+        (
+            "begin_ordinal",
+            u64s(rows.iter().map(|(_, _, c)| c.begin_ordinal)),
+            false,
+        ),
+        (
+            "end_ordinal",
+            u64s(rows.iter().map(|(_, _, c)| c.end_ordinal)),
+            false,
+        ),
+    ])
+}
+
+fn balance_changes_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<_> = blocks
+        .iter()
+        .flat_map(|block| {
+            let block_changes = block
+                .balance_changes
+                .iter()
+                .map(move |change| (block, None, None, change));
+            let call_changes = block.transaction_traces.iter().flat_map(move |trace| {
+                trace.calls.iter().flat_map(move |call| {
+                    call.balance_changes
+                        .iter()
+                        .map(move |change| (block, Some(trace), Some(call), change))
+                })
+            });
+            block_changes.chain(call_changes)
+        })
+        .collect();
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(rows.iter().map(|(b, _, _, _)| b.number)),
+            false,
+        ),
+        (
+            "transaction_index",
+            optional_u32s(rows.iter().map(|(_, t, _, _)| t.map(|t| t.index))),
+            true,
+        ),
+        (
+            "transaction_hash",
+            fixed(
+                rows.iter()
+                    .map(|(_, t, _, _)| t.map_or(&[][..], |t| &t.hash)),
+                WORD_SIZE,
+            )?,
+            true,
+        ),
+        (
+            "call_index",
+            optional_u32s(rows.iter().map(|(_, _, c, _)| c.map(|c| c.index))),
+            true,
+        ),
+        (
+            "address",
+            fixed(rows.iter().map(|(_, _, _, bc)| &bc.address), ADDRESS_SIZE)?,
+            true,
+        ),
+        (
+            "old_value",
+            big_ints(rows.iter().map(|(_, _, _, bc)| big_int(&bc.old_value)))?,
+            true,
+        ),
+        (
+            "new_value",
+            big_ints(rows.iter().map(|(_, _, _, bc)| big_int(&bc.new_value)))?,
+            true,
+        ),
+        (
+            "reason",
+            i32s(rows.iter().map(|(_, _, _, bc)| bc.reason)),
+            false,
+        ),
+        (
+            "ordinal",
+            u64s(rows.iter().map(|(_, _, _, bc)| bc.ordinal)),
+            false,
+        ),
+    ])
+}
+
+fn storage_changes_batch(blocks: &[Block]) -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<_> = blocks
+        .iter()
+        .flat_map(|block| {
+            block.transaction_traces.iter().flat_map(move |trace| {
+                trace.calls.iter().flat_map(move |call| {
+                    call.storage_changes
+                        .iter()
+                        .map(move |change| (block, trace, call, change))
+                })
+            })
+        })
+        .collect();
+
+    RecordBatch::try_from_iter_with_nullable([
+        (
+            "block_number",
+            u64s(rows.iter().map(|(b, _, _, _)| b.number)),
+            false,
+        ),
+        (
+            "transaction_index",
+            u32s(rows.iter().map(|(_, t, _, _)| t.index)),
+            false,
+        ),
+        (
+            "transaction_hash",
+            fixed(rows.iter().map(|(_, t, _, _)| &t.hash), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "call_index",
+            u32s(rows.iter().map(|(_, _, c, _)| c.index)),
+            false,
+        ),
+        (
+            "address",
+            fixed(rows.iter().map(|(_, _, _, sc)| &sc.address), ADDRESS_SIZE)?,
+            true,
+        ),
+        (
+            "key",
+            fixed(rows.iter().map(|(_, _, _, sc)| &sc.key), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "old_value",
+            fixed(rows.iter().map(|(_, _, _, sc)| &sc.old_value), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "new_value",
+            fixed(rows.iter().map(|(_, _, _, sc)| &sc.new_value), WORD_SIZE)?,
+            true,
+        ),
+        (
+            "ordinal",
+            u64s(rows.iter().map(|(_, _, _, sc)| sc.ordinal)),
+            false,
+        ),
+    ])
+}
+
+fn u64s(values: impl Iterator<Item = u64>) -> ArrayRef {
+    Arc::new(UInt64Array::from_iter_values(values))
+}
+
+fn optional_u64s(values: impl Iterator<Item = Option<u64>>) -> ArrayRef {
+    Arc::new(UInt64Array::from_iter(values))
+}
+
+fn u32s(values: impl Iterator<Item = u32>) -> ArrayRef {
+    Arc::new(UInt32Array::from_iter_values(values))
+}
+
+fn optional_u32s(values: impl Iterator<Item = Option<u32>>) -> ArrayRef {
+    Arc::new(UInt32Array::from_iter(values))
+}
+
+fn i32s(values: impl Iterator<Item = i32>) -> ArrayRef {
+    Arc::new(Int32Array::from_iter_values(values))
+}
+
+fn bools(values: impl Iterator<Item = bool>) -> ArrayRef {
+    Arc::new(BooleanArray::from_iter(values.map(Some)))
+}
+
+fn binaries<T: AsRef<[u8]>>(values: impl Iterator<Item = T>) -> ArrayRef {
+    Arc::new(BinaryArray::from_iter_values(values))
+}
+
+/// Builds a fixed-size binary column, where empty values are null. Values of any other size
+/// are an error.
+fn fixed<T: AsRef<[u8]>>(
+    values: impl Iterator<Item = T>,
+    size: i32,
+) -> Result<ArrayRef, ArrowError> {
+    let values = values.map(|value| Some(value).filter(|value| !value.as_ref().is_empty()));
+    Ok(Arc::new(
+        FixedSizeBinaryArray::try_from_sparse_iter_with_size(values, size)?,
+    ))
+}
+
+/// Gets the big-endian bytes of an optional `BigInt`.
+fn big_int(value: &Option<BigInt>) -> Option<&[u8]> {
+    value.as_ref().map(|value| value.bytes.as_slice())
+}
+
+/// Builds a 32-byte fixed-size binary column of big-endian `BigInt` values, left-padded with
+/// zeros.
+fn big_ints<'a>(values: impl Iterator<Item = Option<&'a [u8]>>) -> Result<ArrayRef, ArrowError> {
+    let values = values
+        .map(|value| {
+            value
+                .map(|bytes| {
+                    let mut word = [0; WORD_SIZE as usize];
+                    let start = word.len().checked_sub(bytes.len()).ok_or_else(|| {
+                        ArrowError::InvalidArgumentError(format!(
+                            "BigInt of {} bytes does not fit in {WORD_SIZE} bytes",
+                            bytes.len()
+                        ))
+                    })?;
+                    word[start..].copy_from_slice(bytes);
+                    Ok(word)
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, ArrowError>>()?;
+    Ok(Arc::new(
+        FixedSizeBinaryArray::try_from_sparse_iter_with_size(values.into_iter(), WORD_SIZE)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+    use arrow::{array::AsArray, datatypes::UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::test_utils::verified_block;
+
+    use super::*;
+
+    fn block_with_log(number: u64) -> Block {
+        let mut block = verified_block(number);
+        block.hash = B256::left_padding_from(&block.hash).to_vec();
+        block.transaction_traces.push(Default::default());
+        let trace = &mut block.transaction_traces[0];
+        trace.hash = vec![1; 32];
+        trace.from = vec![2; 20];
+        trace.value = Some(BigInt { bytes: vec![1, 0] });
+        trace.begin_ordinal = 1;
+        trace.end_ordinal = 3;
+        trace.receipt = Some(Default::default());
+        let receipt = trace.receipt.as_mut().unwrap();
+        receipt.logs.push(Default::default());
+        receipt.logs[0].address = vec![3; 20];
+        receipt.logs[0].topics = vec![vec![4; 32]];
+        receipt.logs[0].ordinal = 2;
+        block
+    }
+
+    fn read_column(path: &Path, column: &str) -> Vec<u64> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        reader
+            .flat_map(|batch| {
+                let batch = batch.unwrap();
+                let values = batch[column].as_primitive::<UInt64Type>();
+                values.values().to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_batch_columns() {
+        let blocks = vec![block_with_log(1), verified_block(2)];
+
+        let transactions = ExportTable::Transactions.record_batch(&blocks).unwrap();
+        assert_eq!(transactions.num_rows(), 1);
+        let value = transactions["value"].as_fixed_size_binary().value(0);
+        assert_eq!(value[29..], [0, 1, 0]);
+        assert!(transactions["to"].is_null(0));
+
+        let logs = ExportTable::Logs.record_batch(&blocks).unwrap();
+        assert_eq!(logs.num_rows(), 1);
+        assert_eq!(logs["topic0"].as_fixed_size_binary().value(0), [4; 32]);
+        assert!(logs["topic1"].is_null(0));
+
+        let mut invalid = block_with_log(3);
+        invalid.transaction_traces[0].from = vec![2; 19];
+        assert!(ExportTable::Transactions.record_batch(&[invalid]).is_err());
+    }
+
+    #[test]
+    fn test_export_parquet_partitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ParquetExportOptions {
+            blocks_per_file: 10,
+            blocks_per_batch: 3,
+        };
+        let blocks = (5..25).map(|number| Ok(block_with_log(number)));
+
+        let files = export_parquet(blocks, dir.path(), options).unwrap();
+        assert_eq!(files.len(), 3 * ExportTable::ALL.len());
+
+        let blocks_file = dir.path().join("blocks/0000000010-0000000019.parquet");
+        assert_eq!(
+            read_column(&blocks_file, "block_number"),
+            (10..20).collect::<Vec<_>>()
+        );
+        let logs_file = dir.path().join("logs/0000000000-0000000009.parquet");
+        assert_eq!(read_column(&logs_file, "ordinal"), vec![2; 5]);
+
+        let out_of_order = [Ok(block_with_log(2)), Ok(block_with_log(1))];
+        assert!(matches!(
+            export_parquet(out_of_order, dir.path(), options),
+            Err(DecoderError::BlockOutOfOrder {
+                block_number: 1,
+                previous_block: 2
+            })
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt,
+    io::{BufRead, Read},
+};
+
+use crate::error::DecoderError;
+
+/// The default maximum zstd window size accepted when decompressing, as a power of two.
+///
+/// This is 128 MiB, the largest window that zstd decoders accept unless told otherwise.
+pub const DEFAULT_ZSTD_WINDOW_LOG_MAX: u32 = 27;
+
+/// Magic number at the start of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Magic number at the start of a gzip member.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Magic number at the start of a bzip2 stream.
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+/// Magic number at the start of an xz stream.
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Magic number at the start of an lz4 frame.
+const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+
+/// Work with data compression, including zstd, gzip, bzip2, xz and lz4.
+///
+/// With [`Compression::Auto`], the default, the codec is detected from the magic number at the
+/// start of the data, and data that starts with none of the known magic numbers is read as
+/// uncompressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Detect the compression from the first bytes of the data.
+    #[default]
+    Auto,
+    /// Zstd compression.
+    Zstd,
+    /// Gzip compression.
+    Gzip,
+    /// Bzip2 compression.
+    Bzip2,
+    /// Xz compression.
+    Xz,
+    /// Lz4 frame compression.
+    Lz4,
+    /// No compression.
+    None,
+}
+
+impl From<&str> for Compression {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "true" | "1" | "zstd" | "zst" => Compression::Zstd,
+            "gzip" | "gz" => Compression::Gzip,
+            "bzip2" | "bz2" => Compression::Bzip2,
+            "xz" => Compression::Xz,
+            "lz4" => Compression::Lz4,
+            "false" | "0" | "none" => Compression::None,
+            _ => Compression::Auto,
+        }
+    }
+}
+
+impl From<bool> for Compression {
+    fn from(value: bool) -> Self {
+        match value {
+            true => Compression::Zstd,
+            false => Compression::None,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Compression::Auto => "auto",
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+            Compression::Lz4 => "lz4",
+            Compression::None => "none",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Compression {
+    /// Detect the compression of data from its first bytes.
+    ///
+    /// Returns [`Compression::None`] if the bytes start with none of the known magic numbers.
+    /// Six bytes are enough to tell every supported codec apart.
+    pub fn detect(bytes: &[u8]) -> Self {
+        [
+            (ZSTD_MAGIC, Compression::Zstd),
+            (GZIP_MAGIC, Compression::Gzip),
+            (BZIP2_MAGIC, Compression::Bzip2),
+            (XZ_MAGIC, Compression::Xz),
+            (LZ4_MAGIC, Compression::Lz4),
+        ]
+        .into_iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map_or(Compression::None, |(_, compression)| compression)
+    }
+
+    /// Get the file extension of the compression, such as `"zst"`, appended after `.dbin`.
+    ///
+    /// Returns `None` for [`Compression::Auto`] and [`Compression::None`], which write
+    /// uncompressed files.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::Zstd => Some("zst"),
+            Compression::Gzip => Some("gz"),
+            Compression::Bzip2 => Some("bz2"),
+            Compression::Xz => Some("xz"),
+            Compression::Lz4 => Some("lz4"),
+            Compression::Auto | Compression::None => None,
+        }
+    }
+
+    /// Resolves [`Compression::Auto`] by peeking at the buffered bytes of a source, without
+    /// consuming them.
+    pub(crate) fn resolve<R: BufRead>(self, read: &mut R) -> Result<Self, DecoderError> {
+        match self {
+            Compression::Auto => Ok(Compression::detect(read.fill_buf()?)),
+            compression => Ok(compression),
+        }
+    }
+}
+
+/// Set how flat file data is decompressed.
+///
+/// Data is always decompressed incrementally, as it is read, so memory use does not grow with
+/// the size of the decompressed file. For zstd, memory use is bounded by the window size of
+/// the frames, which is limited by [`DecompressionOptions::zstd_window_log_max`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecompressionOptions {
+    /// The compression applied to the data.
+    pub compression: Compression,
+    /// The maximum window size accepted when decompressing zstd data, as a power of two. Frames
+    /// that need a larger window fail to decompress with an I/O error.
+    pub zstd_window_log_max: u32,
+}
+
+impl Default for DecompressionOptions {
+    fn default() -> Self {
+        Compression::default().into()
+    }
+}
+
+impl From<Compression> for DecompressionOptions {
+    fn from(compression: Compression) -> Self {
+        Self {
+            compression,
+            zstd_window_log_max: DEFAULT_ZSTD_WINDOW_LOG_MAX,
+        }
+    }
+}
+
+impl DecompressionOptions {
+    /// Wraps a source in a streaming decoder for the compression.
+    ///
+    /// Concatenated frames, members or streams are decoded one after the other, so
+    /// concatenated compressed `.dbin` files can be read as a single source.
+    pub(crate) fn decompress<'a, R: BufRead + 'a>(
+        self,
+        mut read: R,
+    ) -> Result<Box<dyn Read + 'a>, DecoderError> {
+        Ok(match self.compression.resolve(&mut read)? {
+            Compression::Zstd => {
+                let mut decoder = zstd::stream::Decoder::with_buffer(read)?;
+                decoder.window_log_max(self.zstd_window_log_max)?;
+                Box::new(decoder)
+            }
+            Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(read)),
+            Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(read)),
+            Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(read)),
+            Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(read)),
+            Compression::None | Compression::Auto => Box::new(read),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Write};
+
+    use super::*;
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(vec![]);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::None | Compression::Auto => data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_detect_and_decompress() {
+        let data = b"dbin\x00ETH01".repeat(100);
+
+        for compression in [
+            Compression::Zstd,
+            Compression::Gzip,
+            Compression::Bzip2,
+            Compression::Xz,
+            Compression::Lz4,
+            Compression::None,
+        ] {
+            let compressed = compress(compression, &data);
+            assert_eq!(Compression::detect(&compressed), compression);
+
+            for requested in [compression, Compression::Auto] {
+                let mut decompressed = vec![];
+                DecompressionOptions::from(requested)
+                    .decompress(BufReader::new(compressed.as_slice()))
+                    .unwrap()
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+                assert_eq!(decompressed, data, "{requested} for {compression}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_concatenated() {
+        for compression in [Compression::Zstd, Compression::Gzip, Compression::Bzip2] {
+            let mut compressed = compress(compression, b"first");
+            compressed.extend(compress(compression, b"second"));
+
+            let mut decompressed = vec![];
+            DecompressionOptions::default()
+                .decompress(compressed.as_slice())
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, b"firstsecond", "{compression}");
+        }
+    }
+
+    #[test]
+    fn test_zstd_window_log_max() {
+        let data = b"dbin".repeat(1000);
+        let mut encoder = zstd::stream::Encoder::new(vec![], 0).unwrap();
+        encoder.window_log(24).unwrap();
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let decompress = |zstd_window_log_max| {
+            let options = DecompressionOptions {
+                compression: Compression::Auto,
+                zstd_window_log_max,
+            };
+            let mut decompressed = vec![];
+            options
+                .decompress(compressed.as_slice())?
+                .read_to_end(&mut decompressed)?;
+            Ok::<_, DecoderError>(decompressed)
+        };
+
+        assert_eq!(decompress(24).unwrap(), data);
+        assert!(matches!(decompress(20), Err(DecoderError::Io(_))));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Compression::from("true"), Compression::Zstd);
+        assert_eq!(Compression::from("gz"), Compression::Gzip);
+        assert_eq!(Compression::from("false"), Compression::None);
+        assert_eq!(Compression::from("auto"), Compression::Auto);
+    }
+}
@@ -1,9 +1,16 @@
 // Copyright 2024-, Semiotic AI, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::io::{self, Read};
+use std::{
+    io::{self, Read, Write},
+    ops::RangeInclusive,
+};
 
-use crate::error::DecoderError;
+use firehose_protos::BstreamBlock;
+use prost::Message;
+use tracing::warn;
+
+use crate::{error::DecoderError, Compression};
 
 /// The bytes of a dbin file minus the header
 type DbinMessages = Vec<DbinMessage>;
@@ -18,7 +25,7 @@ const MAGIC_BYTES: &[u8; 4] = b"dbin";
 type MagicBytes = [u8; 4];
 
 /// The size of the length prefix in bytes
-const PREFIX_SIZE: usize = 4;
+pub(crate) const PREFIX_SIZE: usize = 4;
 
 /// The size of the header version in bytes
 const HEADER_VERSION_SIZE: usize = 1;
@@ -29,9 +36,19 @@ const HEADER_CONTENT_TYPE_SIZE: usize = 3;
 /// The size of the header content version in bytes
 const HEADER_CONTENT_VERSION_SIZE: usize = 2;
 
+/// The size of the whole header in bytes, including the magic bytes
+pub(crate) const HEADER_SIZE: usize =
+    PREFIX_SIZE + HEADER_VERSION_SIZE + HEADER_CONTENT_TYPE_SIZE + HEADER_CONTENT_VERSION_SIZE;
+
 /// The supported version of the dbin file format
 const SUPPORTED_DBIN_VERSION: u8 = 0;
 
+/// The content type of dbin files of Ethereum blocks
+pub(crate) const ETH_CONTENT_TYPE: &str = "ETH";
+
+/// The xz compression preset used by [`DbinWriter`], the same as the `xz` command line default
+const XZ_PRESET: u32 = 6;
+
 /// Work with a `.dbin` flat file.
 ///
 /// Developed by StreamingFast, dbin is a simple file storage format to pack a stream of protobuffer messages.
@@ -48,71 +65,274 @@ impl DbinFile {
         &self.header.content_type
     }
 
+    /// Get the header of the `.dbin` file.
+    pub fn header(&self) -> &DbinHeader {
+        &self.header
+    }
+
     /// Read and parse a `.dbin` file from a `Read` source.
-    pub fn try_from_read<R: Read>(mut read: R) -> Result<Self, DecoderError> {
+    pub fn try_from_read<R: Read>(read: R) -> Result<Self, DecoderError> {
+        let mut reader = DbinReader::new(read)?;
+        let messages = reader.by_ref().collect::<Result<DbinMessages, _>>()?;
+        Ok(Self {
+            header: reader.header,
+            messages,
+        })
+    }
+}
+
+/// implement iterator for DbinFile so that we can iterate over the messages
+impl IntoIterator for DbinFile {
+    type Item = Vec<u8>;
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.into_iter()
+    }
+}
+
+/// Read the messages of a `.dbin` flat file lazily, one at a time.
+///
+/// Unlike [`DbinFile`], which loads every message into memory up front, [`DbinReader`]
+/// only reads the header when created and then yields each message as it is read from the
+/// underlying source. Concatenated `.dbin` files are handled transparently: whenever the
+/// `dbin` magic bytes appear where a length prefix is expected, the new header is parsed
+/// and reading continues with the next message.
+#[derive(Debug)]
+pub struct DbinReader<R> {
+    read: R,
+    header: DbinHeader,
+    offset: u64,
+}
+
+impl<R: Read> DbinReader<R> {
+    /// Create a reader from a `Read` source, parsing and validating the `.dbin` header.
+    pub fn new(mut read: R) -> Result<Self, DecoderError> {
         let header = DbinHeader::try_from_read(&mut read)?;
         if !header.is_supported_version() {
             return Err(DecoderError::VersionUnsupported);
         }
-        let messages = Self::read_messages(&mut read)?;
-        Ok(Self { header, messages })
+        Ok(Self {
+            read,
+            header,
+            offset: HEADER_SIZE as u64,
+        })
+    }
+
+    /// Get the content type of the `.dbin` file being read, such as `"ETH"`.
+    pub fn content_type(&self) -> &str {
+        &self.header.content_type
+    }
+
+    /// Get the header of the `.dbin` file being read.
+    ///
+    /// When reading concatenated `.dbin` files, this is the header of the file that the most
+    /// recently yielded message belongs to.
+    pub fn header(&self) -> &DbinHeader {
+        &self.header
     }
 
-    /// Reads messages from a `Read` source following the Dbin format.
-    fn read_messages<R: Read>(read: &mut R) -> Result<DbinMessages, DecoderError> {
-        let mut messages = Vec::new();
+    /// Get the number of bytes consumed from the underlying source so far.
+    ///
+    /// Right after a message is yielded, the message starts at `offset() - message.len()`.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
 
+    /// Consume the reader, returning the underlying `Read` source.
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+
+    /// Reads the next message, returning `None` if EOF is reached at the start of a message.
+    fn read_next_message(&mut self) -> Result<Option<DbinMessage>, DecoderError> {
         loop {
-            let bytes = match read_magic_bytes(read) {
+            let bytes = match read_magic_bytes(&mut self.read) {
                 Ok(bytes) => bytes,
-                // Break loop gracefully if EOF is reached at the start of a new message.
-                Err(DecoderError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                // Stop gracefully if EOF is reached at the start of a new message.
+                Err(DecoderError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(None)
+                }
                 Err(e) => return Err(e),
             };
+            self.offset += PREFIX_SIZE as u64;
 
-            let message_length = u32::from_be_bytes(bytes) as usize;
-
-            match read_message(read, message_length) {
-                Ok(message) => messages.push(message),
-                // Return error if EOF occurs in the middle of a message
-                Err(DecoderError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                    return Err(DecoderError::Io(e))
+            match Prefix::from(bytes) {
+                Prefix::Header => {
+                    self.header = read_header(&mut self.read)?;
+                    self.offset += (HEADER_SIZE - PREFIX_SIZE) as u64;
+                }
+                Prefix::Message(message_length) => {
+                    // EOF in the middle of a message is an error
+                    let message = read_message(&mut self.read, message_length)?;
+                    self.offset += message_length as u64;
+                    return Ok(Some(message));
                 }
-                Err(e) => return Err(e),
             }
         }
+    }
+}
 
-        Ok(messages)
+impl<R: Read> Iterator for DbinReader<R> {
+    type Item = Result<DbinMessage, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_next_message().transpose()
     }
 }
 
-/// implement iterator for DbinFile so that we can iterate over the messages
-impl IntoIterator for DbinFile {
-    type Item = Vec<u8>;
-    type IntoIter = std::vec::IntoIter<Self::Item>;
+/// Write messages to a `.dbin` flat file.
+///
+/// The header (magic bytes, version, content type and content version) is written when the
+/// writer is created, and every message appended with [`DbinWriter::write_message`] is
+/// length-prefixed as 4 bytes big-endian uint32. With a compression codec, the whole file is
+/// wrapped in a stream of that codec, so the output can be read back by
+/// [`read_blocks_from_reader`](crate::read_blocks_from_reader) with the same compression, or
+/// with [`Compression::Auto`]. [`Compression::Auto`] and [`Compression::None`] both write an
+/// uncompressed file.
+pub struct DbinWriter<W: Write> {
+    write: DbinWrite<W>,
+}
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.messages.into_iter()
+/// The destination of a [`DbinWriter`], with or without compression.
+enum DbinWrite<W: Write> {
+    Plain(W),
+    Zstd(zstd::stream::Encoder<'static, W>),
+    Gzip(flate2::write::GzEncoder<W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+}
+
+impl<W: Write> DbinWriter<W> {
+    /// Create a writer and write the `.dbin` header to the given `Write` destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `write`: The destination of the file contents, implementing the [`Write`] trait.
+    /// * `content_type`: The 3-character content type of the messages, such as `"ETH"`.
+    /// * `content_version`: The 2-digit content version of the messages, such as `"01"`.
+    /// * `compression`: The compression to apply to the whole file, if any.
+    pub fn new(
+        write: W,
+        content_type: &str,
+        content_version: &str,
+        compression: Compression,
+    ) -> Result<Self, DecoderError> {
+        if content_type.len() != HEADER_CONTENT_TYPE_SIZE {
+            return Err(DecoderError::ContentTypeInvalid(content_type.to_string()));
+        }
+        if !content_version_valid(content_version) {
+            return Err(DecoderError::ContentVersionInvalid(
+                content_version.to_string(),
+            ));
+        }
+
+        let write = match compression {
+            Compression::Zstd => DbinWrite::Zstd(zstd::stream::Encoder::new(
+                write,
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+            )?),
+            Compression::Gzip => DbinWrite::Gzip(flate2::write::GzEncoder::new(
+                write,
+                flate2::Compression::default(),
+            )),
+            Compression::Bzip2 => DbinWrite::Bzip2(bzip2::write::BzEncoder::new(
+                write,
+                bzip2::Compression::default(),
+            )),
+            Compression::Xz => DbinWrite::Xz(xz2::write::XzEncoder::new(write, XZ_PRESET)),
+            Compression::Lz4 => DbinWrite::Lz4(lz4_flex::frame::FrameEncoder::new(write)),
+            Compression::Auto | Compression::None => DbinWrite::Plain(write),
+        };
+
+        let mut writer = Self { write };
+        writer.write_all(MAGIC_BYTES)?;
+        writer.write_all(&[SUPPORTED_DBIN_VERSION])?;
+        writer.write_all(content_type.as_bytes())?;
+        writer.write_all(content_version.as_bytes())?;
+
+        Ok(writer)
+    }
+
+    /// Append a single length-prefixed message.
+    pub fn write_message(&mut self, message: &[u8]) -> Result<(), DecoderError> {
+        let length = u32::try_from(message.len()).map_err(|_| DecoderError::BytesInvalid)?;
+        self.write_all(&length.to_be_bytes())?;
+        self.write_all(message)
+    }
+
+    /// Flush all pending bytes, finishing the compressed stream if compressed, and return the
+    /// underlying `Write` destination.
+    pub fn finish(self) -> Result<W, DecoderError> {
+        let mut write = match self.write {
+            DbinWrite::Plain(write) => write,
+            DbinWrite::Zstd(encoder) => encoder.finish()?,
+            DbinWrite::Gzip(encoder) => encoder.finish()?,
+            DbinWrite::Bzip2(encoder) => encoder.finish()?,
+            DbinWrite::Xz(encoder) => encoder.finish()?,
+            DbinWrite::Lz4(encoder) => encoder.finish().map_err(io::Error::from)?,
+        };
+        write.flush()?;
+        Ok(write)
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), DecoderError> {
+        match &mut self.write {
+            DbinWrite::Plain(write) => write.write_all(bytes)?,
+            DbinWrite::Zstd(encoder) => encoder.write_all(bytes)?,
+            DbinWrite::Gzip(encoder) => encoder.write_all(bytes)?,
+            DbinWrite::Bzip2(encoder) => encoder.write_all(bytes)?,
+            DbinWrite::Xz(encoder) => encoder.write_all(bytes)?,
+            DbinWrite::Lz4(encoder) => encoder.write_all(bytes)?,
+        }
+        Ok(())
     }
 }
 
 /// Header of a `.dbin` file, containing metadata such as version, content type, and content version.
-#[derive(Debug)]
-struct DbinHeader {
-    /// File format version, the next single byte after the 4 [`DbinMagicBytes`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DbinHeader {
+    /// File format version, the next single byte after the 4 [`MagicBytes`]
     version: u8,
     /// Content type like 'ETH', 'EOS', or something else; the next 3 bytes
     content_type: String,
+    /// Content version, represented as 10-based string, ranges in '00'-'99'; the next 2 bytes
+    content_version: String,
 }
 
 impl DbinHeader {
+    /// Get the file format version.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Get the content type, such as `"ETH"`.
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// Get the content version, a 2-digit string in the range `"00"`-`"99"`.
+    pub fn content_version(&self) -> &str {
+        &self.content_version
+    }
+
     /// Checks if the version is supported.
     fn is_supported_version(&self) -> bool {
         is_supported_version(self.version)
     }
 
+    /// Checks that the content type is `"ETH"`, failing with
+    /// [`DecoderError::ContentTypeInvalid`] otherwise.
+    pub(crate) fn check_eth(&self) -> Result<(), DecoderError> {
+        if self.content_type != ETH_CONTENT_TYPE {
+            return Err(DecoderError::ContentTypeInvalid(self.content_type.clone()));
+        }
+        Ok(())
+    }
+
     /// Reads and validates the `.dbin` header from the given [`Read`] source.
-    fn try_from_read<R: Read>(read: &mut R) -> Result<Self, DecoderError> {
+    pub(crate) fn try_from_read<R: Read>(read: &mut R) -> Result<Self, DecoderError> {
         let magic_bytes = read_magic_bytes(read)?;
         if !magic_bytes_valid(&magic_bytes) {
             return Err(DecoderError::MagicBytesInvalid);
@@ -142,8 +362,27 @@ fn magic_bytes_valid(bytes: &MagicBytes) -> bool {
     bytes == MAGIC_BYTES
 }
 
+/// The 4 bytes read where a length prefix is expected.
+pub(crate) enum Prefix {
+    /// The magic bytes of a concatenated `.dbin` file, followed by the rest of its header.
+    Header,
+    /// The length of the next message.
+    Message(usize),
+}
+
+impl From<MagicBytes> for Prefix {
+    fn from(bytes: MagicBytes) -> Self {
+        if magic_bytes_valid(&bytes) {
+            // Each new occurrence of the magic bytes marks the start of a new .dbin file
+            Prefix::Header
+        } else {
+            Prefix::Message(u32::from_be_bytes(bytes) as usize)
+        }
+    }
+}
+
 /// Reads and constructs a [`DbinHeader`] from the remaining fields after the magic bytes.
-fn read_header<R: Read>(read: &mut R) -> Result<DbinHeader, DecoderError> {
+pub(crate) fn read_header<R: Read>(read: &mut R) -> Result<DbinHeader, DecoderError> {
     let version = match DbinHeader::read_version_field(read) {
         Ok(version) if is_supported_version(version) => version,
         Ok(_) => return Err(DecoderError::VersionUnsupported),
@@ -152,15 +391,24 @@ fn read_header<R: Read>(read: &mut R) -> Result<DbinHeader, DecoderError> {
 
     let content_type = DbinHeader::read_string_field(read, HEADER_CONTENT_TYPE_SIZE)?;
 
-    // Content version, represented as 10-based string, ranges in '00'-'99'; the next 2 bytes
-    let _content_version = DbinHeader::read_string_field(read, HEADER_CONTENT_VERSION_SIZE)?;
+    let content_version = DbinHeader::read_string_field(read, HEADER_CONTENT_VERSION_SIZE)?;
+    if !content_version_valid(&content_version) {
+        return Err(DecoderError::ContentVersionInvalid(content_version));
+    }
 
     Ok(DbinHeader {
         version,
         content_type,
+        content_version,
     })
 }
 
+/// Checks that the content version is a 10-based string in the range '00'-'99'.
+fn content_version_valid(content_version: &str) -> bool {
+    content_version.len() == HEADER_CONTENT_VERSION_SIZE
+        && content_version.bytes().all(|b| b.is_ascii_digit())
+}
+
 fn read_magic_bytes<R: Read>(read: &mut R) -> Result<MagicBytes, DecoderError> {
     let bytes = read_message(read, PREFIX_SIZE)?;
     match bytes.try_into() {
@@ -170,12 +418,226 @@ fn read_magic_bytes<R: Read>(read: &mut R) -> Result<MagicBytes, DecoderError> {
 }
 
 /// Reads a single message, assuming the size-prefix format defined by `.dbin`.
-fn read_message<R: Read>(read: &mut R, length: usize) -> Result<DbinMessage, DecoderError> {
+pub(crate) fn read_message<R: Read>(
+    read: &mut R,
+    length: usize,
+) -> Result<DbinMessage, DecoderError> {
     let mut message = vec![0; length];
     read.read_exact(&mut message)?;
     Ok(message)
 }
 
+/// A range of bytes of a `.dbin` file skipped by [`DbinRecoveryReader`] because it could not be
+/// read as messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorruptedRange {
+    start: u64,
+    end: u64,
+    previous_block: Option<u64>,
+    next_block: Option<u64>,
+}
+
+impl CorruptedRange {
+    /// Get the byte offset of the start of the range, from the start of the file.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Get the byte offset just past the end of the range, from the start of the file.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Get the number of the last block read before the range, if any.
+    pub fn previous_block(&self) -> Option<u64> {
+        self.previous_block
+    }
+
+    /// Get the number of the first block read after the range, if any.
+    pub fn next_block(&self) -> Option<u64> {
+        self.next_block
+    }
+
+    /// Get the numbers of the blocks lost in the range, assuming the file holds consecutive
+    /// blocks.
+    ///
+    /// Returns `None` if the range is at the start or end of the file, where the lost blocks
+    /// cannot be told from the surrounding ones. The returned range is empty if no block was
+    /// lost, such as when garbage bytes were inserted between two messages.
+    pub fn missing_blocks(&self) -> Option<RangeInclusive<u64>> {
+        match (self.previous_block, self.next_block) {
+            (Some(previous), Some(next)) => {
+                Some(previous.saturating_add(1)..=next.saturating_sub(1))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Read the messages of a possibly corrupted `.dbin` file, skipping the parts that cannot be read.
+///
+/// Unlike [`DbinReader`], which fails on the first bad length prefix, this reader skips bad
+/// headers of concatenated files and messages that do not decode as a [`BstreamBlock`] with an
+/// id, such as the empty messages of a zeroed region. After each of them, it scans forward, byte
+/// by byte, and resumes at the next valid `dbin` header or plausible message. A plausible message
+/// decodes as a [`BstreamBlock`] with an id, and is followed by the end of the file, a header, or
+/// a length prefix that fits in the file. The skipped byte ranges are recorded, with the numbers
+/// of the blocks around them, and can be inspected with [`DbinRecoveryReader::corruptions`].
+///
+/// The whole file is loaded into memory, since scanning may need to go back to any byte after a
+/// bad message. Only the header at the start of the file must be valid.
+#[derive(Debug)]
+pub struct DbinRecoveryReader {
+    bytes: Vec<u8>,
+    position: usize,
+    header: DbinHeader,
+    previous_block: Option<u64>,
+    pending: Option<(usize, usize)>,
+    corruptions: Vec<CorruptedRange>,
+}
+
+/// What was found at a position of a file read by [`DbinRecoveryReader`].
+enum RecoveryEntry {
+    /// A valid header of a concatenated file.
+    Header(DbinHeader),
+    /// A message of the given length, containing the given block, which has an id.
+    Message(usize, BstreamBlock),
+    /// Neither a header nor a message.
+    Invalid,
+}
+
+impl DbinRecoveryReader {
+    /// Create a reader from a `Read` source, reading it to the end and parsing the `.dbin` header.
+    pub fn new<R: Read>(mut read: R) -> Result<Self, DecoderError> {
+        let mut bytes = Vec::new();
+        read.read_to_end(&mut bytes)?;
+
+        let header = DbinHeader::try_from_read(&mut bytes.as_slice())?;
+
+        Ok(Self {
+            bytes,
+            position: HEADER_SIZE,
+            header,
+            previous_block: None,
+            pending: None,
+            corruptions: Vec::new(),
+        })
+    }
+
+    /// Get the header of the `.dbin` file that the most recently yielded message belongs to.
+    pub fn header(&self) -> &DbinHeader {
+        &self.header
+    }
+
+    /// Get the corrupted byte ranges skipped so far.
+    ///
+    /// A range is only recorded once the next message after it is read, or the end of the file
+    /// is reached, so this is complete once the iteration has ended.
+    pub fn corruptions(&self) -> &[CorruptedRange] {
+        &self.corruptions
+    }
+
+    /// Consume the reader, returning every corrupted byte range skipped.
+    pub fn into_corruptions(self) -> Vec<CorruptedRange> {
+        self.corruptions
+    }
+
+    fn entry_at(&self, position: usize) -> RecoveryEntry {
+        let Some(prefix) = self.bytes.get(position..position + PREFIX_SIZE) else {
+            return RecoveryEntry::Invalid;
+        };
+        let rest = &self.bytes[position + PREFIX_SIZE..];
+
+        if prefix == MAGIC_BYTES {
+            return match read_header(&mut &rest[..]) {
+                Ok(header) => RecoveryEntry::Header(header),
+                Err(_) => RecoveryEntry::Invalid,
+            };
+        }
+
+        // An empty message or a block without an id decodes from zeroed or garbage bytes, so
+        // neither is taken for a block
+        let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+        match rest.get(..length).map(BstreamBlock::decode) {
+            Some(Ok(block)) if length > 0 && !block.id.is_empty() => {
+                RecoveryEntry::Message(length, block)
+            }
+            _ => RecoveryEntry::Invalid,
+        }
+    }
+
+    /// Checks whether reading can plausibly resume at the given position after a corruption.
+    fn is_resume_point(&self, position: usize) -> bool {
+        match self.entry_at(position) {
+            RecoveryEntry::Header(_) => true,
+            RecoveryEntry::Message(length, _) => {
+                let next = position + PREFIX_SIZE + length;
+                match self.bytes.get(next..next + PREFIX_SIZE) {
+                    Some(prefix) => {
+                        prefix == MAGIC_BYTES
+                            || u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]])
+                                as usize
+                                <= self.bytes.len() - next - PREFIX_SIZE
+                    }
+                    None => next == self.bytes.len(),
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Records the pending corruption, if any, now that the block after it is known.
+    fn close_corruption(&mut self, next_block: Option<u64>) {
+        if let Some((start, end)) = self.pending.take() {
+            let corruption = CorruptedRange {
+                start: start as u64,
+                end: end as u64,
+                previous_block: self.previous_block,
+                next_block,
+            };
+            warn!(
+                "Skipped corrupted bytes {}..{} between blocks {:?} and {:?}",
+                corruption.start, corruption.end, corruption.previous_block, next_block
+            );
+            self.corruptions.push(corruption);
+        }
+    }
+}
+
+impl Iterator for DbinRecoveryReader {
+    type Item = DbinMessage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.bytes.len() {
+            match self.entry_at(self.position) {
+                RecoveryEntry::Header(header) => {
+                    self.header = header;
+                    self.position += HEADER_SIZE;
+                }
+                RecoveryEntry::Message(length, block) => {
+                    self.close_corruption(Some(block.number));
+                    self.previous_block = Some(block.number);
+
+                    let start = self.position + PREFIX_SIZE;
+                    self.position = start + length;
+                    return Some(self.bytes[start..self.position].to_vec());
+                }
+                RecoveryEntry::Invalid => {
+                    let start = self.pending.map_or(self.position, |(start, _)| start);
+                    let end = (self.position + 1..self.bytes.len())
+                        .find(|&position| self.is_resume_point(position))
+                        .unwrap_or(self.bytes.len());
+                    self.pending = Some((start, end));
+                    self.position = end;
+                }
+            }
+        }
+
+        self.close_corruption(None);
+        None
+    }
+}
+
 /// Read the next block from a flat file reader.
 pub fn read_block_from_reader<R: Read>(read: &mut R) -> Result<DbinMessage, DecoderError> {
     let mut magic_bytes = read_magic_bytes(read)?;
@@ -195,6 +657,7 @@ pub fn read_block_from_reader<R: Read>(read: &mut R) -> Result<DbinMessage, Deco
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::DecompressionOptions;
     use std::io::Cursor;
 
     #[test]
@@ -203,8 +666,18 @@ mod tests {
         let mut cursor = Cursor::new(data);
 
         let header = DbinHeader::try_from_read(&mut cursor).expect("Failed to parse header");
-        assert_eq!(header.version, SUPPORTED_DBIN_VERSION);
-        assert_eq!(header.content_type, "ETH");
+        assert_eq!(header.version(), SUPPORTED_DBIN_VERSION);
+        assert_eq!(header.content_type(), "ETH");
+        assert_eq!(header.content_version(), "01");
+    }
+
+    #[test]
+    fn test_invalid_content_version() {
+        let data = [b'd', b'b', b'i', b'n', 0u8, b'E', b'T', b'H', b'0', b'x'];
+        let mut cursor = Cursor::new(data);
+
+        let result = DbinHeader::try_from_read(&mut cursor);
+        assert!(matches!(result, Err(DecoderError::ContentVersionInvalid(ref v)) if v == "0x"));
     }
 
     #[test]
@@ -272,4 +745,212 @@ mod tests {
         assert_eq!(messages[0], b"test");
         assert_eq!(messages[1], b"123");
     }
+
+    #[test]
+    fn test_reader_yields_messages_lazily() {
+        let mut data = vec![];
+        data.extend_from_slice(&[b'd', b'b', b'i', b'n', 0u8, b'E', b'T', b'H', b'0', b'1']);
+        data.extend_from_slice(&(4u32.to_be_bytes())); // message length
+        data.extend_from_slice(b"test");
+        data.extend_from_slice(&(3u32.to_be_bytes())); // message length
+        data.extend_from_slice(b"123");
+
+        let mut reader = DbinReader::new(Cursor::new(data)).expect("Failed to read header");
+        assert_eq!(reader.content_type(), "ETH");
+
+        assert_eq!(reader.next().unwrap().unwrap(), b"test");
+        assert_eq!(reader.next().unwrap().unwrap(), b"123");
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_concatenated_files() {
+        let mut data = vec![];
+        data.extend_from_slice(&[b'd', b'b', b'i', b'n', 0u8, b'E', b'T', b'H', b'0', b'1']);
+        data.extend_from_slice(&(4u32.to_be_bytes())); // message length
+        data.extend_from_slice(b"test");
+        data.extend_from_slice(&[b'd', b'b', b'i', b'n', 0u8, b'E', b'T', b'H', b'0', b'1']);
+        data.extend_from_slice(&(3u32.to_be_bytes())); // message length
+        data.extend_from_slice(b"123");
+
+        let reader = DbinReader::new(Cursor::new(data)).expect("Failed to read header");
+        let messages: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(messages, vec![b"test".to_vec(), b"123".to_vec()]);
+    }
+
+    #[test]
+    fn test_reader_truncated_message() {
+        let mut data = vec![];
+        data.extend_from_slice(&[b'd', b'b', b'i', b'n', 0u8, b'E', b'T', b'H', b'0', b'1']);
+        data.extend_from_slice(&(4u32.to_be_bytes())); // message length
+        data.extend_from_slice(b"te");
+
+        let mut reader = DbinReader::new(Cursor::new(data)).expect("Failed to read header");
+        assert!(
+            matches!(reader.next(), Some(Err(DecoderError::Io(ref e))) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let mut writer =
+            DbinWriter::new(vec![], "ETH", "01", Compression::None).expect("Failed to write");
+        writer.write_message(b"test").unwrap();
+        writer.write_message(b"123").unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut expected = vec![];
+        expected.extend_from_slice(&[b'd', b'b', b'i', b'n', 0u8, b'E', b'T', b'H', b'0', b'1']);
+        expected.extend_from_slice(&(4u32.to_be_bytes()));
+        expected.extend_from_slice(b"test");
+        expected.extend_from_slice(&(3u32.to_be_bytes()));
+        expected.extend_from_slice(b"123");
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_writer_zstd_round_trip() {
+        let mut writer =
+            DbinWriter::new(vec![], "ETH", "01", Compression::Zstd).expect("Failed to write");
+        writer.write_message(b"test").unwrap();
+        let data = writer.finish().unwrap();
+
+        let decompressed = zstd::decode_all(Cursor::new(data)).unwrap();
+        let reader = DbinReader::new(Cursor::new(decompressed)).unwrap();
+        assert_eq!(reader.content_type(), "ETH");
+        let messages: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(messages, vec![b"test".to_vec()]);
+    }
+
+    #[test]
+    fn test_writer_compressed_round_trip() {
+        for compression in [
+            Compression::Gzip,
+            Compression::Bzip2,
+            Compression::Xz,
+            Compression::Lz4,
+        ] {
+            let mut writer =
+                DbinWriter::new(vec![], "ETH", "01", compression).expect("Failed to write");
+            writer.write_message(b"test").unwrap();
+            let data = writer.finish().unwrap();
+            assert_eq!(Compression::detect(&data), compression);
+
+            let read = DecompressionOptions::default()
+                .decompress(data.as_slice())
+                .unwrap();
+            let messages: Vec<_> = DbinReader::new(read)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(messages, vec![b"test".to_vec()], "{compression}");
+        }
+    }
+
+    #[test]
+    fn test_writer_invalid_content_type() {
+        let result = DbinWriter::new(vec![], "ETHEREUM", "01", Compression::None);
+        assert!(matches!(result, Err(DecoderError::ContentTypeInvalid(_))));
+    }
+
+    fn recovery_message(number: u64) -> Vec<u8> {
+        BstreamBlock {
+            number,
+            id: format!("block-{number}"),
+            payload_buffer: vec![0xab; 16],
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    fn recovery_file(numbers: &[u64]) -> (Vec<u8>, Vec<usize>) {
+        let mut writer = DbinWriter::new(vec![], "ETH", "01", Compression::None).unwrap();
+        let mut offsets = vec![];
+        let mut offset = HEADER_SIZE;
+        for &number in numbers {
+            let message = recovery_message(number);
+            offsets.push(offset);
+            offset += PREFIX_SIZE + message.len();
+            writer.write_message(&message).unwrap();
+        }
+        (writer.finish().unwrap(), offsets)
+    }
+
+    fn block_numbers(reader: &mut DbinRecoveryReader) -> Vec<u64> {
+        reader
+            .map(|message| BstreamBlock::decode(message.as_slice()).unwrap().number)
+            .collect()
+    }
+
+    #[test]
+    fn test_recovery_reader_skips_corrupted_message() {
+        let (mut data, offsets) = recovery_file(&[10, 11, 12, 13]);
+        // Corrupt the length prefix of block 11
+        data[offsets[1]..offsets[1] + PREFIX_SIZE].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let mut reader = DbinRecoveryReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(block_numbers(&mut reader), vec![10, 12, 13]);
+
+        let corruptions = reader.corruptions();
+        assert_eq!(corruptions.len(), 1);
+        assert_eq!(corruptions[0].start(), offsets[1] as u64);
+        assert_eq!(corruptions[0].end(), offsets[2] as u64);
+        assert_eq!(corruptions[0].previous_block(), Some(10));
+        assert_eq!(corruptions[0].next_block(), Some(12));
+        assert_eq!(corruptions[0].missing_blocks(), Some(11..=11));
+    }
+
+    #[test]
+    fn test_recovery_reader_skips_zeroed_region() {
+        let (mut data, offsets) = recovery_file(&[1, 2]);
+        data.splice(offsets[1]..offsets[1], [0; 64]);
+
+        let mut reader = DbinRecoveryReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(block_numbers(&mut reader), vec![1, 2]);
+
+        let corruptions = reader.corruptions();
+        assert_eq!(corruptions.len(), 1);
+        assert_eq!(corruptions[0].start(), offsets[1] as u64);
+        assert_eq!(corruptions[0].end(), offsets[1] as u64 + 64);
+        assert_eq!(corruptions[0].previous_block(), Some(1));
+        assert_eq!(corruptions[0].next_block(), Some(2));
+        assert!(corruptions[0].missing_blocks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recovery_reader_resumes_at_concatenated_header() {
+        let (mut data, offsets) = recovery_file(&[1, 2]);
+        // Garble the payload of block 2, then append another file
+        let end = data.len();
+        data[offsets[1] + PREFIX_SIZE..end].fill(0xff);
+        let (more, _) = recovery_file(&[3]);
+        data.extend(more);
+
+        let mut reader = DbinRecoveryReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(block_numbers(&mut reader), vec![1, 3]);
+        assert_eq!(
+            reader.into_corruptions(),
+            vec![CorruptedRange {
+                start: offsets[1] as u64,
+                end: end as u64,
+                previous_block: Some(1),
+                next_block: Some(3),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recovery_reader_truncated_end() {
+        let (data, offsets) = recovery_file(&[1, 2]);
+        let truncated = data[..data.len() - 3].to_vec();
+
+        let mut reader = DbinRecoveryReader::new(Cursor::new(truncated)).unwrap();
+        assert_eq!(block_numbers(&mut reader), vec![1]);
+
+        let corruptions = reader.corruptions();
+        assert_eq!(corruptions.len(), 1);
+        assert_eq!(corruptions[0].start(), offsets[1] as u64);
+        assert_eq!(corruptions[0].next_block(), None);
+        assert_eq!(corruptions[0].missing_blocks(), None);
+    }
 }
@@ -1,133 +1,155 @@
 // Copyright 2024-, Semiotic AI, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::io::{BufReader, Cursor, Read};
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read},
+};
 
-use firehose_protos::{BstreamBlock, EthBlock as Block};
-use prost::Message;
-use tracing::{error, info};
+use crate::{
+    compression::DecompressionOptions,
+    error::DecoderError,
+    stream::{FollowOptions, FollowRead},
+    DbinHeader, DbinReader, VerificationOptions,
+};
+use firehose_protos::{EthBlock as Block, HeaderOnlyBlock};
+use prost::{
+    encoding::{decode_key, decode_varint, skip_field, DecodeContext, WireType},
+    Message,
+};
 
-use crate::{dbin::read_block_from_reader, error::DecoderError, DbinFile};
-
-/// Work with data compression, including zstd.
-#[derive(Clone, Copy, Debug, Default)]
-pub enum Compression {
-    /// Zstd compression.
-    Zstd,
-    /// No compression.
-    #[default]
-    None,
-}
+/// Read blocks from a flat file reader.
+///
+/// This function processes flat files from any readable source, supporting both compressed and
+/// uncompressed data. Compressed data is decompressed incrementally as it is read, so the whole
+/// decompressed file is never held in memory. If the data is successfully decoded, it returns a
+/// vector of `Block` structs representing the blocks contained within the file. The number of
+/// blocks returned depends on the file's content and format, which may include one or more blocks.
+///
+/// # Arguments
+///
+/// * `reader`: A readable source of the file contents, implementing the [`Read`] trait.
+/// * `compression`: The compression type applied to the flat file's data, if any. Accepts
+///   [`Compression::Auto`] to detect it from the data, [`Compression::None`] for uncompressed
+///   data, or any of the other variants for data compressed with that codec. Pass
+///   [`DecompressionOptions`] instead to also limit the zstd window size.
+pub fn read_blocks_from_reader<R: Read>(
+    reader: R,
+    compression: impl Into<DecompressionOptions>,
+) -> Result<Vec<Block>, DecoderError> {
+    let blocks = decode_blocks_from_reader(reader, compression)?;
 
-impl From<&str> for Compression {
-    fn from(value: &str) -> Self {
-        match value.to_lowercase().as_str() {
-            "true" | "1" => Compression::Zstd,
-            _ => Compression::None,
-        }
+    if let Some(block) = blocks.iter().find(|block| !block_is_verified(block)) {
+        return Err(DecoderError::VerificationFailed {
+            block_number: block.number,
+        });
     }
+
+    Ok(blocks)
 }
 
-impl From<bool> for Compression {
-    fn from(value: bool) -> Self {
-        match value {
-            true => Compression::Zstd,
-            false => Compression::None,
-        }
-    }
+/// Decodes every block of a flat file reader, without verifying them.
+pub(crate) fn decode_blocks_from_reader<R: Read>(
+    reader: R,
+    compression: impl Into<DecompressionOptions>,
+) -> Result<Vec<Block>, DecoderError> {
+    decode_messages_from_reader(reader, compression, decode_block_from_message)
 }
 
-/// Read blocks from a flat file reader.
+/// Read block headers from a flat file reader, without decoding the rest of the blocks.
 ///
-/// This function processes flat files that are already loaded into memory, supporting both
-/// compressed (Zstd) and uncompressed data. If the data is successfully decoded, it returns a
-/// vector of `Block` structs representing the blocks contained within the file. The number of
-/// blocks returned depends on the file's content and format, which may include one or more blocks.
+/// Like [`read_blocks_from_reader`], but each block is decoded as a [`HeaderOnlyBlock`], which
+/// only holds the header of the block. The transaction traces, which make up most of a block,
+/// are skipped over instead of being decoded, so reading headers takes a fraction of the CPU
+/// time and memory of reading whole blocks. Without the traces, the blocks cannot be verified,
+/// so the headers are returned as is.
+///
+/// Convert the blocks with `Header::try_from` to build an `Epoch` of the `header_accumulator`
+/// crate, for instance.
 ///
 /// # Arguments
 ///
 /// * `reader`: A readable source of the file contents, implementing the [`Read`] trait.
-/// * `compression`: The compression type applied to the flat file's data, if any. Accepts [`Compression::Zstd`]
-///   for Zstd-compressed data, or [`Compression::None`] for uncompressed data.
-pub fn read_blocks_from_reader<R: Read>(
+/// * `compression`: The compression type applied to the flat file's data, if any, as for
+///   [`read_blocks_from_reader`].
+pub fn decode_headers_from_reader<R: Read>(
     reader: R,
-    compression: Compression,
-) -> Result<Vec<Block>, DecoderError> {
-    const CONTENT_TYPE: &str = "ETH";
+    compression: impl Into<DecompressionOptions>,
+) -> Result<Vec<HeaderOnlyBlock>, DecoderError> {
+    decode_messages_from_reader(reader, compression, decode_header_from_message)
+}
 
-    let mut file_contents: Box<dyn Read> = match compression {
-        Compression::Zstd => Box::new(Cursor::new(zstd::decode_all(reader)?)),
-        Compression::None => Box::new(reader),
-    };
+/// Decodes every message of a flat file reader with the given function.
+fn decode_messages_from_reader<R: Read, T>(
+    reader: R,
+    compression: impl Into<DecompressionOptions>,
+    decode: impl Fn(&DbinHeader, &[u8]) -> Result<T, DecoderError>,
+) -> Result<Vec<T>, DecoderError> {
+    let file_contents = compression.into().decompress(BufReader::new(reader))?;
 
-    let dbin_file = DbinFile::try_from_read(&mut file_contents)?;
-    if dbin_file.content_type() != CONTENT_TYPE {
-        return Err(DecoderError::ContentTypeInvalid(
-            dbin_file.content_type().to_string(),
-        ));
-    }
+    let mut dbin_reader = DbinReader::new(file_contents)?;
+    dbin_reader.header().check_eth()?;
 
-    dbin_file
-        .into_iter()
-        .map(|message| {
-            let block = decode_block_from_bytes(&message)?;
-            if !block_is_verified(&block) {
-                Err(DecoderError::VerificationFailed {
-                    block_number: block.number,
-                })
-            } else {
-                Ok(block)
-            }
-        })
-        .collect()
-}
-
-fn block_is_verified(block: &Block) -> bool {
-    if block.number != 0 {
-        if !block.receipt_root_is_verified() {
-            error!(
-                "Receipt root verification failed for block {}",
-                block.number
-            );
-            return false;
-        }
+    let mut decoded = Vec::new();
 
-        if !block.transaction_root_is_verified() {
-            error!(
-                "Transaction root verification failed for block {}",
-                block.number
-            );
-            return false;
-        }
+    while let Some(message) = dbin_reader.next() {
+        decoded.push(decode(dbin_reader.header(), &message?)?);
     }
-    true
+
+    Ok(decoded)
+}
+
+/// Verifies a block with the default [`VerificationOptions`], logging any failed checks.
+pub(crate) fn block_is_verified(block: &Block) -> bool {
+    let outcome = VerificationOptions::default().verify(block);
+    outcome.log_failures();
+    outcome.is_verified()
 }
 
 /// Reader enum to handle different types of readers
 ///
 /// - [`Reader::Buf`]: A [`BufReader`] that reads from a byte slice
+/// - [`Reader::File`]: A reader that reads from a file, with or without compression
 /// - [`Reader::StdIn`]: A reader that reads from standard input, with or without compression
 #[derive(Debug)]
 pub enum Reader {
     /// A [`BufReader`] that reads from a byte slice
     Buf(BufReader<Cursor<Vec<u8>>>),
+    /// A reader that reads from a file, with or without compression
+    File(File, DecompressionOptions),
     /// A reader that reads from standard input, with or without compression
-    StdIn(Compression),
+    StdIn(DecompressionOptions),
 }
 
 impl Reader {
     pub(crate) fn into_reader(self) -> Result<Box<dyn Read>, DecoderError> {
+        self.into_following_reader(None)
+    }
+
+    /// Opens the reader, following the raw source with the given options, if any.
+    ///
+    /// The source is followed beneath the decompressor, so that a message or zstd frame that is
+    /// only partially written when EOF is reached is resumed once more data is available.
+    pub(crate) fn into_following_reader(
+        self,
+        follow: Option<FollowOptions>,
+    ) -> Result<Box<dyn Read>, DecoderError> {
+        fn source<R: Read + 'static>(read: R, follow: Option<FollowOptions>) -> Box<dyn Read> {
+            match follow {
+                Some(options) => Box::new(FollowRead::new(read, options)),
+                None => Box::new(read),
+            }
+        }
+
         match self {
-            Reader::StdIn(compression) => match compression {
-                Compression::Zstd => Ok(Box::new(zstd::stream::Decoder::new(std::io::stdin())?)),
-                Compression::None => Ok(Box::new(BufReader::with_capacity(
-                    // Set buffer size to 128 MB (64 * 2 MB) for reading large data efficiently.
-                    // `(64 * 2) << 20` converts 128 MB to bytes (128 * 1,048,576 = 134,217,728 bytes).
-                    (64 * 2) << 20,
-                    std::io::stdin().lock(),
-                ))),
-            },
-            Reader::Buf(reader) => Ok(Box::new(reader)),
+            Reader::File(file, options) => options.decompress(BufReader::new(source(file, follow))),
+            Reader::StdIn(options) => options.decompress(BufReader::with_capacity(
+                // Set buffer size to 128 MB (64 * 2 MB) for reading large data efficiently.
+                // `(64 * 2) << 20` converts 128 MB to bytes (128 * 1,048,576 = 134,217,728 bytes).
+                (64 * 2) << 20,
+                source(std::io::stdin().lock(), follow),
+            )),
+            Reader::Buf(reader) => Ok(source(reader, follow)),
         }
     }
 }
@@ -152,7 +174,7 @@ pub enum EndBlock {
 }
 
 impl EndBlock {
-    fn block_number(&self) -> u64 {
+    pub(crate) fn block_number(&self) -> u64 {
         const LAST_PREMERGE_BLOCK: u64 = 15537393;
         match self {
             EndBlock::MergeBlock => LAST_PREMERGE_BLOCK,
@@ -167,60 +189,129 @@ impl From<Option<u64>> for EndBlock {
     }
 }
 
-/// Get an iterator of decoded, verified blocks from a reader.
-///
-/// Skips invalid blocks and returns an iterator of verified blocks.
-///
-/// # Arguments
+/// Decodes a block from a message, according to the content version of the `.dbin` file the
+/// message was read from.
+pub(crate) fn decode_block_from_message(
+    header: &DbinHeader,
+    bytes: &[u8],
+) -> Result<Block, DecoderError> {
+    header.check_eth()?;
+    match header.content_version() {
+        // `sf.ethereum.type.v2.Block` payloads wrapped in a `sf.bstream.v1.Block`
+        "00" | "01" => decode_block_from_bytes(bytes),
+        content_version => Err(DecoderError::ContentVersionUnsupported(
+            content_version.to_string(),
+        )),
+    }
+}
+
+/// Decodes a block from the bytes of a `.dbin` message.
 ///
-/// * `reader`: A [`Reader`] enum that specifies the source of the block data. The reader can be a
-///   [`BufReader`] or a `StdIn` reader with or without compression.
-/// * `end_block`: Specifies the block number at which to stop streaming. By default, this is set to
-///   block 15537393, the last block before the Ethereum merge.
-pub fn stream_blocks(
-    reader: Reader,
-    end_block: EndBlock,
-) -> Result<impl Iterator<Item = Block>, DecoderError> {
-    let mut current_block_number = 0;
-
-    let mut reader = reader.into_reader()?;
-    let end_block = end_block.block_number();
-
-    let mut blocks = Vec::new();
-
-    loop {
-        match read_block_from_reader(&mut reader) {
-            Ok(message) => {
-                match decode_block_from_bytes(&message) {
-                    Ok(block) => {
-                        current_block_number = block.number;
-
-                        if block_is_verified(&block) {
-                            blocks.push(block);
-                        } else {
-                            info!("Block verification failed, skipping block {}", block.number);
-                        }
-                    }
-                    Err(e) => return Err(e),
-                };
-            }
-            Err(DecoderError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                if current_block_number < end_block {
-                    info!("Reached end of file, waiting for more blocks");
-                    continue;
-                }
-                break;
+/// Only the `payload_buffer` field of the [`firehose_protos::BstreamBlock`] is needed, so the
+/// block is decoded straight from the borrowed payload instead of copying it out first.
+pub fn decode_block_from_bytes(bytes: &[u8]) -> Result<Block, DecoderError> {
+    let payload = bstream_payload(bytes)?;
+    let block = Block::decode(payload)?;
+    Ok(block)
+}
+
+/// Decodes the header of a block from a message, according to the content version of the
+/// `.dbin` file the message was read from.
+pub(crate) fn decode_header_from_message(
+    header: &DbinHeader,
+    bytes: &[u8],
+) -> Result<HeaderOnlyBlock, DecoderError> {
+    header.check_eth()?;
+    match header.content_version() {
+        "00" | "01" => decode_header_from_bytes(bytes),
+        content_version => Err(DecoderError::ContentVersionUnsupported(
+            content_version.to_string(),
+        )),
+    }
+}
+
+/// Decodes the header of a block from the bytes of a `.dbin` message, skipping over the rest of
+/// the block.
+pub fn decode_header_from_bytes(bytes: &[u8]) -> Result<HeaderOnlyBlock, DecoderError> {
+    let payload = bstream_payload(bytes)?;
+    let block = HeaderOnlyBlock::decode(payload)?;
+    Ok(block)
+}
+
+/// Tag of the `payload_buffer` field of a [`firehose_protos::BstreamBlock`].
+const BSTREAM_PAYLOAD_BUFFER_TAG: u32 = 8;
+
+/// Borrows the `payload_buffer` field of an encoded [`firehose_protos::BstreamBlock`] without
+/// copying it, skipping over every other field.
+fn bstream_payload(mut bytes: &[u8]) -> Result<&[u8], DecoderError> {
+    let mut payload: &[u8] = &[];
+
+    while !bytes.is_empty() {
+        let (tag, wire_type) = decode_key(&mut bytes)?;
+        if tag == BSTREAM_PAYLOAD_BUFFER_TAG && wire_type == WireType::LengthDelimited {
+            let length = decode_varint(&mut bytes)? as usize;
+            if length > bytes.len() {
+                return Err(DecoderError::BytesInvalid);
             }
-            Err(e) => return Err(e),
+            // As with any protobuf field, the last occurrence wins
+            (payload, bytes) = bytes.split_at(length);
+        } else {
+            skip_field(wire_type, tag, &mut bytes, DecodeContext::default())?;
         }
     }
 
-    Ok(blocks.into_iter())
+    Ok(payload)
 }
 
-/// Decodes a block from a byte slice.
-fn decode_block_from_bytes(bytes: &[u8]) -> Result<Block, DecoderError> {
-    let block_stream = BstreamBlock::decode(bytes)?;
-    let block = Block::decode(block_stream.payload_buffer.as_slice())?;
-    Ok(block)
+#[cfg(test)]
+mod tests {
+    use firehose_protos::BstreamBlock;
+
+    use super::*;
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression,
+    };
+
+    #[test]
+    fn test_bstream_payload_is_borrowed() {
+        let block = BstreamBlock {
+            number: 42,
+            id: "id".to_string(),
+            previous_id: "previous_id".to_string(),
+            lib_num: 41,
+            payload_version: 1,
+            payload_buffer: b"payload".to_vec(),
+            head_num: 43,
+            ..Default::default()
+        };
+        let bytes = block.encode_to_vec();
+
+        let payload = bstream_payload(&bytes).unwrap();
+        assert_eq!(payload, b"payload");
+        assert!(bytes.as_ptr_range().contains(&payload.as_ptr()));
+    }
+
+    #[test]
+    fn test_bstream_payload_truncated() {
+        let block = BstreamBlock {
+            payload_buffer: b"payload".to_vec(),
+            ..Default::default()
+        };
+        let bytes = block.encode_to_vec();
+
+        assert!(bstream_payload(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_headers_from_reader() {
+        let blocks: Vec<_> = (0..3).map(verified_block).collect();
+        let bytes = dbin_file(&blocks, Compression::Zstd);
+
+        let headers = decode_headers_from_reader(bytes.as_slice(), Compression::Auto).unwrap();
+
+        let expected: Vec<_> = blocks.into_iter().map(|block| block.header).collect();
+        let headers: Vec<_> = headers.into_iter().map(|block| block.header).collect();
+        assert_eq!(headers, expected);
+    }
 }
@@ -6,10 +6,40 @@ use thiserror::Error;
 /// Get custom error variants for issues with reading, decoding, and verifying flat files.
 #[derive(Debug, Error)]
 pub enum DecoderError {
+    /// [arrow] library error.
+    #[cfg(feature = "parquet")]
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] arrow::error::ArrowError),
+
     /// [bincode] library error.
     #[error("Bin code error: {0}")]
     Bincode(#[from] bincode::Error),
 
+    /// Block not found in flat file.
+    #[error("Block {block_number} not found")]
+    BlockNotFound {
+        /// Block number.
+        block_number: u64,
+    },
+
+    /// Block out of order.
+    #[error("Block {block_number} is out of order after block {previous_block}")]
+    BlockOutOfOrder {
+        /// Block number.
+        block_number: u64,
+        /// Number of the previous block.
+        previous_block: u64,
+    },
+
+    /// Flat file bundles missing from a store, for a range of consecutive blocks.
+    #[error("Bundles of blocks {start_block} to {end_block} are missing")]
+    BundleMissing {
+        /// The first block number of the missing range.
+        start_block: u64,
+        /// The last block number of the missing range.
+        end_block: u64,
+    },
+
     /// Flat file bytes invalid.
     #[error("Invalid flat file bytes")]
     BytesInvalid,
@@ -18,6 +48,18 @@ pub enum DecoderError {
     #[error("Invalid flat file content type: {0}")]
     ContentTypeInvalid(String),
 
+    /// Flat file content version invalid.
+    #[error("Invalid flat file content version: {0}")]
+    ContentVersionInvalid(String),
+
+    /// Flat file content version unsupported.
+    #[error("Unsupported flat file content version: {0}")]
+    ContentVersionUnsupported(String),
+
+    /// A cursor to resume from lacks the head block or the LIB.
+    #[error("Cursor is missing its head block or LIB")]
+    CursorIncomplete,
+
     /// [firehose_protos] library error.
     #[error("Protos error: {0}")]
     FirehoseProtosError(#[from] firehose_protos::ProtosError),
@@ -34,6 +76,11 @@ pub enum DecoderError {
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// [tokio] blocking task error.
+    #[cfg(feature = "async")]
+    #[error("Blocking task error: {0}")]
+    Join(#[from] tokio::task::JoinError),
+
     /// [serde_json] library error.
     #[error("{0}")]
     Json(#[from] serde_json::Error),
@@ -49,6 +96,16 @@ pub enum DecoderError {
         block_number: u64,
     },
 
+    /// [object_store] library error.
+    #[cfg(feature = "s3")]
+    #[error("Object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+
+    /// [parquet] library error.
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
     /// [prost] library decode error.
     #[error("Protobuf decode error: {0}")]
     ProtobufDecode(#[from] prost::DecodeError),
@@ -57,6 +114,18 @@ pub enum DecoderError {
     #[error("Invalid Receipt Root")]
     ReceiptRootInvalid,
 
+    /// Too many reversible blocks are buffered by a fork-aware stream, as the LIB does not
+    /// advance.
+    #[error("More than {max} reversible blocks")]
+    ReversibleBlocksExceeded {
+        /// The maximum number of reversible blocks buffered.
+        max: usize,
+    },
+
+    /// [rayon] thread pool error.
+    #[error("Thread pool error: {0}")]
+    ThreadPoolBuild(#[from] rayon::ThreadPoolBuildError),
+
     /// Invalid block header total difficulty.
     #[error("Invalid block header total difficulty")]
     TotalDifficultyInvalid,
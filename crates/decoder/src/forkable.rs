@@ -0,0 +1,520 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, VecDeque};
+
+use firehose_protos::{
+    BstreamBlock, BstreamBlockRef, BstreamCursor, BstreamForkStep, EthBlock as Block,
+};
+use prost::Message;
+
+use crate::error::DecoderError;
+
+/// The maximum number of reversible blocks a [`ForkAwareStream`] buffers by default.
+pub const DEFAULT_MAX_REVERSIBLE_BLOCKS: usize = 1_000;
+
+/// A step of a [`ForkAwareStream`], with the cursor to resume the stream after it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockEvent {
+    /// The block extends the chain, as its new head.
+    New {
+        /// The new block.
+        block: Block,
+        /// The cursor after the event.
+        cursor: BstreamCursor,
+    },
+    /// The block is no longer part of the chain, as a longer fork replaces it. Blocks are
+    /// undone from the head down.
+    Undo {
+        /// The undone block.
+        block: Block,
+        /// The cursor after the event.
+        cursor: BstreamCursor,
+    },
+    /// The block is now irreversible, as the LIB reached it.
+    Irreversible {
+        /// The irreversible block.
+        block: Block,
+        /// The cursor after the event.
+        cursor: BstreamCursor,
+    },
+}
+
+impl BlockEvent {
+    /// Get the block of the event.
+    pub fn block(&self) -> &Block {
+        match self {
+            Self::New { block, .. }
+            | Self::Undo { block, .. }
+            | Self::Irreversible { block, .. } => block,
+        }
+    }
+
+    /// Get the cursor to resume the stream after the event, with
+    /// [`ForkAwareStream::resume`].
+    pub fn cursor(&self) -> &BstreamCursor {
+        match self {
+            Self::New { cursor, .. }
+            | Self::Undo { cursor, .. }
+            | Self::Irreversible { cursor, .. } => cursor,
+        }
+    }
+
+    /// Get the fork step of the event.
+    pub fn step(&self) -> BstreamForkStep {
+        match self {
+            Self::New { .. } => BstreamForkStep::StepNew,
+            Self::Undo { .. } => BstreamForkStep::StepUndo,
+            Self::Irreversible { .. } => BstreamForkStep::StepIrreversible,
+        }
+    }
+}
+
+/// A reversible block buffered by a [`ForkAwareStream`].
+struct ForkBlock {
+    number: u64,
+    id: String,
+    previous_id: String,
+    lib_num: u64,
+    block: Block,
+}
+
+impl ForkBlock {
+    fn block_ref(&self) -> BstreamBlockRef {
+        BstreamBlockRef::new(self.number, &self.id)
+    }
+}
+
+/// Iterator of the [`BlockEvent`]s of a source of [`BstreamBlock`]s that may fork, such as a
+/// relayer or a reader of one-block files.
+///
+/// Blocks are linked by their `id` and `previous_id`, and the LIB, the last irreversible block,
+/// follows the `lib_num` of the head. The head is the highest block linked to the LIB: when a
+/// fork becomes longer than the chain, the blocks of the chain above the fork are undone, and
+/// the blocks of the fork are new. Blocks at or below the LIB, and blocks already seen, are
+/// ignored, and blocks whose parent is not known yet are buffered until it is.
+///
+/// Reversible blocks, above the LIB, are buffered up to a maximum, past which the stream yields
+/// [`DecoderError::ReversibleBlocksExceeded`]. A fresh stream takes the parent of its first
+/// block as the LIB.
+///
+/// Every event comes with a [`BstreamCursor`], whose `head_block` is the head of the chain
+/// after the event. A stream resumed from a cursor, with a source replaying the blocks after
+/// the LIB of the cursor, picks up the chain of the head of the cursor without emitting events
+/// for it again.
+pub struct ForkAwareStream<I> {
+    source: I,
+    max_reversible_blocks: usize,
+    /// The reversible blocks, by id.
+    blocks: HashMap<String, ForkBlock>,
+    /// The ids of the children of each block, by id.
+    children: HashMap<String, Vec<String>>,
+    /// The ids of the blocks of the chain above the LIB, from the lowest.
+    chain: VecDeque<String>,
+    lib: Option<BstreamBlockRef>,
+    /// The head of the cursor the stream resumed from, until it is seen.
+    resume_head: Option<BstreamBlockRef>,
+    /// The block undone by the event of the cursor the stream resumed from, which does not
+    /// become the head again unless a descendant of it does.
+    undone: Option<String>,
+    events: VecDeque<BlockEvent>,
+}
+
+impl<I> ForkAwareStream<I>
+where
+    I: Iterator<Item = Result<BstreamBlock, DecoderError>>,
+{
+    /// Track the forks of a source of blocks.
+    pub fn new(source: I) -> Self {
+        Self {
+            source,
+            max_reversible_blocks: DEFAULT_MAX_REVERSIBLE_BLOCKS,
+            blocks: HashMap::new(),
+            children: HashMap::new(),
+            chain: VecDeque::new(),
+            lib: None,
+            resume_head: None,
+            undone: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Resume a stream after the event of a cursor, with a source replaying the blocks after
+    /// the LIB of the cursor.
+    pub fn resume(source: I, cursor: &BstreamCursor) -> Result<Self, DecoderError> {
+        let (Some(head), Some(lib)) = (&cursor.head_block, &cursor.lib) else {
+            return Err(DecoderError::CursorIncomplete);
+        };
+
+        let mut stream = Self::new(source);
+        stream.lib = Some(lib.clone());
+        if head.id != lib.id {
+            stream.resume_head = Some(head.clone());
+        }
+        if cursor.step == BstreamForkStep::StepUndo as i32 {
+            stream.undone = cursor.block.as_ref().map(|block| block.id.clone());
+        }
+        Ok(stream)
+    }
+
+    /// Set the maximum number of reversible blocks buffered.
+    pub fn with_max_reversible_blocks(mut self, max_reversible_blocks: usize) -> Self {
+        self.max_reversible_blocks = max_reversible_blocks;
+        self
+    }
+
+    /// Get the head of the chain, once known.
+    pub fn head(&self) -> Option<BstreamBlockRef> {
+        match self.chain.back() {
+            Some(id) => Some(self.blocks[id].block_ref()),
+            None => self.lib.clone(),
+        }
+    }
+
+    /// Get the LIB, the last irreversible block, once known.
+    pub fn lib(&self) -> Option<&BstreamBlockRef> {
+        self.lib.as_ref()
+    }
+
+    /// Get the number of reversible blocks buffered, including blocks of forks.
+    pub fn reversible_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn push(&mut self, block: BstreamBlock) -> Result<(), DecoderError> {
+        let lib = self.lib.get_or_insert_with(|| {
+            BstreamBlockRef::new(block.number.saturating_sub(1), &block.previous_id)
+        });
+        if block.number <= lib.num || self.blocks.contains_key(&block.id) {
+            return Ok(());
+        }
+        if self.blocks.len() >= self.max_reversible_blocks {
+            return Err(DecoderError::ReversibleBlocksExceeded {
+                max: self.max_reversible_blocks,
+            });
+        }
+
+        let payload = Block::decode(block.payload_buffer.as_slice())?;
+        let id = block.id.clone();
+        self.children
+            .entry(block.previous_id.clone())
+            .or_default()
+            .push(id.clone());
+        self.blocks.insert(
+            id.clone(),
+            ForkBlock {
+                number: block.number,
+                id: block.id,
+                previous_id: block.previous_id,
+                lib_num: block.lib_num,
+                block: payload,
+            },
+        );
+
+        let tip = match self.resume_head.as_ref().map(|head| head.id == id) {
+            // Wait for the head of the cursor, whose chain the consumer already has
+            Some(false) => None,
+            Some(true) => match self.ancestry(&id) {
+                Some(chain) => {
+                    self.chain = chain;
+                    self.resume_head = None;
+                    let lib = self.lib.as_ref().map(|lib| lib.id.clone());
+                    lib.and_then(|lib| self.highest_descendant(&lib))
+                }
+                None => None,
+            },
+            None => self
+                .ancestry(&id)
+                .and_then(|_| self.highest_descendant(&id))
+                .filter(|tip| self.undone.as_ref() != Some(tip)),
+        };
+        if let Some(tip) = tip {
+            self.switch_head(&tip);
+        }
+        self.advance_lib();
+        Ok(())
+    }
+
+    /// Get the ids of the blocks from the LIB to a block, if it is linked to the LIB.
+    fn ancestry(&self, id: &str) -> Option<VecDeque<String>> {
+        let lib = self.lib.as_ref()?;
+        let mut chain = VecDeque::new();
+        let mut id = id;
+        while id != lib.id {
+            let block = self.blocks.get(id)?;
+            chain.push_front(block.id.clone());
+            id = &block.previous_id;
+        }
+        Some(chain)
+    }
+
+    /// Get the id of the highest buffered descendant of a block, or of the block itself.
+    fn highest_descendant(&self, id: &str) -> Option<String> {
+        let mut highest: Option<&ForkBlock> = self.blocks.get(id);
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            for child in self.children.get(id).into_iter().flatten() {
+                let Some(block) = self.blocks.get(child) else {
+                    continue;
+                };
+                if highest.map_or(true, |highest| block.number > highest.number) {
+                    highest = Some(block);
+                }
+                pending.push(child);
+            }
+        }
+        highest.map(|block| block.id.clone())
+    }
+
+    /// Make a block the head if it is higher than the current head, undoing the blocks of the
+    /// chain that are not its ancestors.
+    fn switch_head(&mut self, tip: &str) {
+        let head_number = self.head().map_or(0, |head| head.num);
+        if self.blocks[tip].number <= head_number {
+            return;
+        }
+        let Some(fork) = self.ancestry(tip) else {
+            return;
+        };
+
+        let common = self
+            .chain
+            .iter()
+            .zip(&fork)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while self.chain.len() > common {
+            let id = self.chain.pop_back().expect("chain is longer than common");
+            let block = self.blocks[&id].block.clone();
+            let cursor = self.cursor(&id, BstreamForkStep::StepUndo);
+            self.events.push_back(BlockEvent::Undo { block, cursor });
+        }
+        for id in fork.into_iter().skip(common) {
+            let block = self.blocks[&id].block.clone();
+            self.chain.push_back(id.clone());
+            let cursor = self.cursor(&id, BstreamForkStep::StepNew);
+            self.events.push_back(BlockEvent::New { block, cursor });
+        }
+    }
+
+    /// Move the LIB up to the `lib_num` of the head, making the blocks of the chain up to it
+    /// irreversible, and drop the blocks of forks below it.
+    fn advance_lib(&mut self) {
+        let Some(head) = self.chain.back().map(|id| &self.blocks[id]) else {
+            return;
+        };
+        let lib_num = head.lib_num.min(head.number);
+
+        while let Some(id) = self.chain.front() {
+            if self.blocks[id].number > lib_num {
+                break;
+            }
+            let id = self.chain.pop_front().expect("chain is not empty");
+            self.lib = Some(self.blocks[&id].block_ref());
+            let cursor = self.cursor(&id, BstreamForkStep::StepIrreversible);
+            let block = self
+                .blocks
+                .remove(&id)
+                .expect("chain blocks are buffered")
+                .block;
+            self.events
+                .push_back(BlockEvent::Irreversible { block, cursor });
+        }
+
+        let Some(lib) = &self.lib else {
+            return;
+        };
+        let lib_num = lib.num;
+        self.blocks.retain(|_, block| block.number > lib_num);
+        let blocks = &self.blocks;
+        self.children
+            .retain(|parent, _| parent == &lib.id || blocks.contains_key(parent));
+    }
+
+    fn cursor(&self, id: &str, step: BstreamForkStep) -> BstreamCursor {
+        BstreamCursor {
+            block: Some(self.blocks[id].block_ref()),
+            head_block: self.head(),
+            lib: self.lib.clone(),
+            step: step as i32,
+        }
+    }
+}
+
+impl<I> Iterator for ForkAwareStream<I>
+where
+    I: Iterator<Item = Result<BstreamBlock, DecoderError>>,
+{
+    type Item = Result<BlockEvent, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(Ok(event));
+            }
+            let pushed = match self.source.next()? {
+                Ok(block) => self.push(block),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = pushed {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use BstreamForkStep::{StepIrreversible as Irr, StepNew as New, StepUndo as Undo};
+
+    /// A block of a synthetic forking chain, identified by its number and fork letter.
+    fn fork_block(id: &str, previous_id: &str, lib_num: u64) -> Result<BstreamBlock, DecoderError> {
+        let number = id[..id.len() - 1].parse().unwrap();
+        Ok(BstreamBlock {
+            number,
+            id: id.to_string(),
+            previous_id: previous_id.to_string(),
+            lib_num,
+            payload_buffer: Block {
+                number,
+                hash: id.as_bytes().to_vec(),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+            ..Default::default()
+        })
+    }
+
+    fn steps(events: &[BlockEvent]) -> Vec<(BstreamForkStep, String)> {
+        events
+            .iter()
+            .map(|event| {
+                let id = String::from_utf8(event.block().hash.clone()).unwrap();
+                (event.step(), id)
+            })
+            .collect()
+    }
+
+    fn expected(steps: &[(BstreamForkStep, &str)]) -> Vec<(BstreamForkStep, String)> {
+        steps
+            .iter()
+            .map(|(step, id)| (*step, id.to_string()))
+            .collect()
+    }
+
+    /// A chain forking at block 3, where fork `b` overtakes fork `a`, and then finalizes.
+    fn forking_source() -> Vec<Result<BstreamBlock, DecoderError>> {
+        vec![
+            fork_block("1a", "0a", 0),
+            fork_block("2a", "1a", 0),
+            fork_block("3a", "2a", 0),
+            fork_block("3b", "2a", 0),
+            fork_block("4b", "3b", 0),
+            fork_block("2a", "1a", 0),
+            fork_block("5b", "4b", 4),
+        ]
+    }
+
+    #[test]
+    fn test_linear_chain() {
+        let source = (1..=5u64).map(|number| {
+            fork_block(
+                &format!("{number}a"),
+                &format!("{}a", number - 1),
+                number.saturating_sub(2),
+            )
+        });
+        let events: Vec<_> = ForkAwareStream::new(source)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(
+            steps(&events),
+            expected(&[
+                (New, "1a"),
+                (New, "2a"),
+                (New, "3a"),
+                (Irr, "1a"),
+                (New, "4a"),
+                (Irr, "2a"),
+                (New, "5a"),
+                (Irr, "3a"),
+            ])
+        );
+        let cursor = events.last().unwrap().cursor();
+        assert_eq!(cursor.head_block, Some(BstreamBlockRef::new(5, "5a")));
+        assert_eq!(cursor.lib, Some(BstreamBlockRef::new(3, "3a")));
+    }
+
+    #[test]
+    fn test_fork_undo_and_irreversible() {
+        let mut stream = ForkAwareStream::new(forking_source().into_iter());
+        let events: Vec<_> = stream.by_ref().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(
+            steps(&events),
+            expected(&[
+                (New, "1a"),
+                (New, "2a"),
+                (New, "3a"),
+                (Undo, "3a"),
+                (New, "3b"),
+                (New, "4b"),
+                (New, "5b"),
+                (Irr, "1a"),
+                (Irr, "2a"),
+                (Irr, "3b"),
+                (Irr, "4b"),
+            ])
+        );
+        assert_eq!(
+            events[3].cursor().head_block,
+            Some(BstreamBlockRef::new(2, "2a"))
+        );
+        assert_eq!(stream.head(), Some(BstreamBlockRef::new(5, "5b")));
+        assert_eq!(stream.lib(), Some(&BstreamBlockRef::new(4, "4b")));
+        assert_eq!(stream.reversible_blocks(), 1);
+    }
+
+    #[test]
+    fn test_resume_from_cursor() {
+        let events: Vec<_> = ForkAwareStream::new(forking_source().into_iter())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // Resume after the new 3b, then after the undone 3a
+        for (index, resumed) in [
+            (
+                4,
+                expected(&[
+                    (New, "4b"),
+                    (New, "5b"),
+                    (Irr, "1a"),
+                    (Irr, "2a"),
+                    (Irr, "3b"),
+                    (Irr, "4b"),
+                ]),
+            ),
+            (3, steps(&events[4..])),
+        ] {
+            let cursor = BstreamCursor::from_opaque(&events[index].cursor().to_opaque()).unwrap();
+            let stream = ForkAwareStream::resume(forking_source().into_iter(), &cursor).unwrap();
+            let events: Vec<_> = stream.collect::<Result<_, _>>().unwrap();
+            assert_eq!(steps(&events), resumed);
+        }
+    }
+
+    #[test]
+    fn test_reversible_blocks_bounded() {
+        let mut stream =
+            ForkAwareStream::new(forking_source().into_iter()).with_max_reversible_blocks(3);
+
+        let error = stream.find_map(Result::err).unwrap();
+        assert!(matches!(
+            error,
+            DecoderError::ReversibleBlocksExceeded { max: 3 }
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bincode::Options;
+use firehose_protos::BstreamBlock;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dbin::{read_message, DbinReader},
+    error::DecoderError,
+};
+
+/// File extension appended to a `.dbin` file path to get the path of its index sidecar file.
+const INDEX_EXTENSION: &str = "idx";
+
+/// Position of a single message within a `.dbin` file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbinIndexEntry {
+    block_number: u64,
+    offset: u64,
+    length: u32,
+}
+
+impl DbinIndexEntry {
+    /// Get the number of the block contained in the message, from [`BstreamBlock::number`].
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    /// Get the byte offset of the message within the `.dbin` file, after its length prefix.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Get the length of the message in bytes.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+/// Index of the messages of an uncompressed `.dbin` file, for random access by block number.
+///
+/// The index is built by scanning the file once with [`DbinIndex::build`], and can be persisted
+/// next to the file with [`DbinIndex::write_to`] and [`DbinIndex::sidecar_path`], so that
+/// [`DbinIndex::seek_to_block`] can later read a single message without decoding the
+/// messages before it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbinIndex {
+    entries: Vec<DbinIndexEntry>,
+}
+
+impl DbinIndex {
+    /// Build an index by scanning every message of a `.dbin` file from a `Read` source.
+    ///
+    /// Each message is decoded as a [`BstreamBlock`] to record its block number.
+    pub fn build<R: Read>(read: R) -> Result<Self, DecoderError> {
+        let mut reader = DbinReader::new(read)?;
+        let mut entries = Vec::new();
+
+        while let Some(message) = reader.next() {
+            let message = message?;
+            let block = BstreamBlock::decode(message.as_slice())?;
+            entries.push(DbinIndexEntry {
+                block_number: block.number,
+                offset: reader.offset() - message.len() as u64,
+                length: message.len() as u32,
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Get the path of the index sidecar file for a `.dbin` file, such as
+    /// `0000000000.dbin.idx` for `0000000000.dbin`.
+    pub fn sidecar_path(dbin_path: &Path) -> PathBuf {
+        let mut path = dbin_path.as_os_str().to_owned();
+        path.push(".");
+        path.push(INDEX_EXTENSION);
+        path.into()
+    }
+
+    /// Read a persisted index from a `Read` source.
+    ///
+    /// The whole source is read first, and decoding is limited to its length, so that a corrupt
+    /// or truncated index fails instead of allocating for entries it does not hold.
+    pub fn read_from<R: Read>(mut read: R) -> Result<Self, DecoderError> {
+        let mut bytes = Vec::new();
+        read.read_to_end(&mut bytes)?;
+        // The same encoding as `bincode::serialize_into`, used by `write_to`.
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(bytes.len() as u64);
+        Ok(options.deserialize(&bytes)?)
+    }
+
+    /// Persist the index to a `Write` destination.
+    pub fn write_to<W: Write>(&self, write: W) -> Result<(), DecoderError> {
+        Ok(bincode::serialize_into(write, self)?)
+    }
+
+    /// Get the indexed entries, in the order the messages appear in the file.
+    pub fn entries(&self) -> &[DbinIndexEntry] {
+        &self.entries
+    }
+
+    /// Get the entry for the given block number, if indexed.
+    pub fn entry(&self, block_number: u64) -> Option<&DbinIndexEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.block_number == block_number)
+    }
+
+    /// Read the message containing the given block number from the indexed `.dbin` file.
+    ///
+    /// The source must be the same uncompressed file the index was built from.
+    pub fn seek_to_block<R: Read + Seek>(
+        &self,
+        read: &mut R,
+        block_number: u64,
+    ) -> Result<Vec<u8>, DecoderError> {
+        let entry = self
+            .entry(block_number)
+            .ok_or(DecoderError::BlockNotFound { block_number })?;
+
+        read.seek(SeekFrom::Start(entry.offset))?;
+        read_message(read, entry.length as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use firehose_protos::EthBlock as Block;
+
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_seek_to_block() {
+        let data = dbin_file(&[100, 101, 102].map(verified_block), Compression::None);
+        let index = DbinIndex::build(Cursor::new(&data)).unwrap();
+        assert_eq!(index.entries().len(), 3);
+
+        let mut cursor = Cursor::new(&data);
+        let message = index.seek_to_block(&mut cursor, 101).unwrap();
+        let block = BstreamBlock::decode(message.as_slice()).unwrap();
+        assert_eq!(block.number, 101);
+        assert_eq!(
+            Block::decode(block.payload_buffer.as_slice()).unwrap(),
+            verified_block(101)
+        );
+
+        let result = index.seek_to_block(&mut cursor, 103);
+        assert!(matches!(
+            result,
+            Err(DecoderError::BlockNotFound { block_number: 103 })
+        ));
+    }
+
+    #[test]
+    fn test_index_persistence() {
+        let data = dbin_file(&[0, 1].map(verified_block), Compression::None);
+        let index = DbinIndex::build(Cursor::new(&data)).unwrap();
+
+        let mut persisted = vec![];
+        index.write_to(&mut persisted).unwrap();
+        let restored = DbinIndex::read_from(persisted.as_slice()).unwrap();
+        assert_eq!(index, restored);
+
+        // A corrupt entry count is not allocated for
+        let corrupted = u64::MAX.to_le_bytes();
+        assert!(matches!(
+            DbinIndex::read_from(corrupted.as_slice()),
+            Err(DecoderError::Bincode(_))
+        ));
+        let truncated = &persisted[..persisted.len() - 1];
+        assert!(matches!(
+            DbinIndex::read_from(truncated),
+            Err(DecoderError::Bincode(_))
+        ));
+
+        assert_eq!(
+            DbinIndex::sidecar_path(Path::new("blocks/0000000000.dbin")),
+            PathBuf::from("blocks/0000000000.dbin.idx")
+        );
+    }
+}
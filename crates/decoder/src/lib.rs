@@ -4,10 +4,44 @@
 #![deny(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "parquet")]
+mod columnar;
+mod compression;
 mod dbin;
 mod decoder;
 mod error;
+mod forkable;
+mod index;
+mod merger;
+mod mmap;
+mod parallel;
+#[cfg(feature = "s3")]
+mod s3;
+mod storage;
+mod store;
+mod stream;
+#[cfg(feature = "async")]
+mod stream_async;
+#[cfg(test)]
+mod test_utils;
+mod verification;
 
+#[cfg(feature = "parquet")]
+pub use columnar::*;
+pub use compression::*;
 pub use dbin::*;
 pub use decoder::*;
 pub use error::*;
+pub use forkable::*;
+pub use index::*;
+pub use merger::*;
+pub use mmap::*;
+pub use parallel::*;
+#[cfg(feature = "s3")]
+pub use s3::*;
+pub use storage::*;
+pub use store::*;
+pub use stream::*;
+#[cfg(feature = "async")]
+pub use stream_async::*;
+pub use verification::*;
@@ -0,0 +1,517 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use alloy_primitives::hex;
+use firehose_protos::BstreamBlock;
+use prost::Message;
+use tracing::warn;
+
+use crate::{
+    dbin::ETH_CONTENT_TYPE, error::DecoderError, Compression, DbinReader, DbinWriter,
+    DecompressionOptions, FlatFileBundle, BUNDLE_SIZE,
+};
+
+/// The number of digits of the block number that starts a one-block file name.
+const ONE_BLOCK_NUMBER_DIGITS: usize = 10;
+
+/// Merges a directory of Firehose one-block files into canonical merged-blocks bundles.
+///
+/// Firehose readers write each block they see to its own one-block file, named
+/// `{number:010}-{timestamp}-{id}-{previous_id}-{lib_num}-{suffix}.dbin`, optionally with a
+/// compression extension, so forked candidates can exist for the same height. The merger takes
+/// the highest last irreversible block (LIB) declared by the file names, finds the block at
+/// that height, and follows the previous ids down from it to resolve the canonical chain. Every
+/// complete bundle of [`BUNDLE_SIZE`] canonical blocks up to the LIB is written to the output
+/// directory, and the other blocks at the same heights are discarded as forks.
+///
+/// The chain is resolved from the file names alone, and only the files of the bundles being
+/// written are read, one bundle at a time. A bundle with a block whose files cannot be read,
+/// such as a file still being written, is left for a later merge rather than failing the
+/// merge.
+///
+/// Blocks above the LIB are still reversible, so they are left for a later merge, as are blocks
+/// of bundles that are not complete yet. One-block files are never deleted; the
+/// [`MergeReport`] lists the files that were merged or discarded, which can be.
+///
+/// ```no_run
+/// use flat_files_decoder::{Compression, Merger};
+///
+/// let report = Merger::new("merged-blocks")
+///     .with_compression(Compression::Zstd)
+///     .merge("one-blocks")?;
+/// for block in &report.discarded {
+///     println!("discarded forked block {}", block.number);
+/// }
+/// # Ok::<(), flat_files_decoder::DecoderError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Merger {
+    output: PathBuf,
+    compression: Compression,
+    decompression: DecompressionOptions,
+}
+
+impl Merger {
+    /// Create a merger writing uncompressed bundles to the given directory, which is created if
+    /// missing.
+    pub fn new<P: Into<PathBuf>>(output: P) -> Self {
+        Self {
+            output: output.into(),
+            compression: Compression::None,
+            decompression: DecompressionOptions::default(),
+        }
+    }
+
+    /// Set the compression of the written bundles, whose file names get the matching extension,
+    /// such as `.dbin.zst`.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set how the one-block files are decompressed.
+    pub fn with_decompression(mut self, decompression: DecompressionOptions) -> Self {
+        self.decompression = decompression;
+        self
+    }
+
+    /// Merge the one-block files of a directory. Other files and subdirectories are ignored.
+    pub fn merge<P: AsRef<Path>>(&self, input: P) -> Result<MergeReport, DecoderError> {
+        let mut files = fs::read_dir(input)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+
+        let mut blocks: HashMap<Vec<u8>, OneBlock> = HashMap::new();
+        for block in files.into_iter().filter_map(OneBlock::from_path) {
+            match blocks.get_mut(&block.hash) {
+                // The same block, written by another reader.
+                Some(existing) => existing.paths.extend(block.paths),
+                None => {
+                    blocks.insert(block.hash.clone(), block);
+                }
+            }
+        }
+
+        let Some(head) = blocks
+            .values()
+            .max_by_key(|block| (block.lib_num, block.number))
+        else {
+            return Ok(MergeReport::default());
+        };
+        let lib = head.lib_num;
+
+        // Walk down from the block declaring the highest LIB, keeping the blocks at or below
+        // the LIB, which are irreversible.
+        let mut canonical = BTreeMap::new();
+        let mut block = head;
+        loop {
+            if block.number <= lib {
+                canonical.insert(block.number, block);
+            }
+            match blocks.get(&block.parent_hash) {
+                Some(parent) if parent.number + 1 == block.number => block = parent,
+                _ => break,
+            }
+        }
+
+        let mut report = MergeReport {
+            lib: Some(lib),
+            ..Default::default()
+        };
+        let Some(&lowest) = canonical.keys().next() else {
+            return Ok(report);
+        };
+
+        let mut discarded: Vec<_> = blocks
+            .values()
+            .filter(|block| {
+                (lowest..=lib).contains(&block.number)
+                    && canonical
+                        .get(&block.number)
+                        .is_some_and(|canonical| canonical.hash != block.hash)
+            })
+            .map(|block| ForkedBlock {
+                number: block.number,
+                hash: block.hash.clone(),
+                paths: block.paths.clone(),
+            })
+            .collect();
+        discarded.sort_by(|a, b| (a.number, &a.hash).cmp(&(b.number, &b.hash)));
+        report.discarded = discarded;
+
+        let first_bundle = lowest.div_ceil(BUNDLE_SIZE) * BUNDLE_SIZE;
+        let bundles = (first_bundle..)
+            .step_by(BUNDLE_SIZE as usize)
+            .take_while(|start_block| start_block + BUNDLE_SIZE - 1 <= lib);
+        for start_block in bundles {
+            let bundle: Vec<_> = canonical
+                .range(start_block..start_block + BUNDLE_SIZE)
+                .map(|(_, block)| *block)
+                .collect();
+
+            let mut messages = Vec::with_capacity(bundle.len());
+            for block in &bundle {
+                match self.read_block(block, &mut report.skipped) {
+                    Some(message) => messages.push(message),
+                    None => break,
+                }
+            }
+            if messages.len() < bundle.len() {
+                warn!("Leaving bundle {start_block} for a later merge, as a block is unreadable");
+                continue;
+            }
+
+            report
+                .bundles
+                .push(self.write_bundle(start_block, &messages)?);
+            report
+                .merged
+                .extend(bundle.iter().flat_map(|block| block.paths.iter().cloned()));
+        }
+
+        Ok(report)
+    }
+
+    /// Reads the message of a block from the first of its files that can be read, recording
+    /// the files that cannot.
+    fn read_block(&self, block: &OneBlock, skipped: &mut Vec<PathBuf>) -> Option<OneBlockMessage> {
+        for path in &block.paths {
+            match self.read_one_block(path, block.number) {
+                Ok(message) => return Some(message),
+                Err(e) => {
+                    warn!("Skipping unreadable one-block file {}: {e}", path.display());
+                    skipped.push(path.clone());
+                }
+            }
+        }
+        None
+    }
+
+    fn read_one_block(&self, path: &Path, number: u64) -> Result<OneBlockMessage, DecoderError> {
+        let contents = self
+            .decompression
+            .decompress(BufReader::new(File::open(path)?))?;
+        let mut reader = DbinReader::new(contents)?;
+        reader.header().check_eth()?;
+        let content_version = reader.header().content_version().to_string();
+        let message = reader.next().ok_or(DecoderError::BytesInvalid)??;
+        if BstreamBlock::decode(message.as_slice())?.number != number {
+            return Err(DecoderError::BytesInvalid);
+        }
+
+        Ok(OneBlockMessage {
+            content_version,
+            message,
+        })
+    }
+
+    fn write_bundle(
+        &self,
+        start_block: u64,
+        messages: &[OneBlockMessage],
+    ) -> Result<PathBuf, DecoderError> {
+        let mut file_name = FlatFileBundle::file_name(start_block);
+        if let Some(extension) = self.compression.extension() {
+            file_name = format!("{file_name}.{extension}");
+        }
+        fs::create_dir_all(&self.output)?;
+        let path = self.output.join(file_name);
+
+        let mut writer = DbinWriter::new(
+            File::create(&path)?,
+            ETH_CONTENT_TYPE,
+            &messages[0].content_version,
+            self.compression,
+        )?;
+        for message in messages {
+            writer.write_message(&message.message)?;
+        }
+        writer.finish()?;
+
+        Ok(path)
+    }
+}
+
+/// The outcome of [`Merger::merge`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The highest LIB declared by the one-block files, up to which blocks are merged, or
+    /// `None` if there are no one-block files.
+    pub lib: Option<u64>,
+    /// The paths of the bundles written, in block order.
+    pub bundles: Vec<PathBuf>,
+    /// The one-block files merged into the bundles.
+    pub merged: Vec<PathBuf>,
+    /// The forked blocks at merged heights, which are not part of the canonical chain.
+    pub discarded: Vec<ForkedBlock>,
+    /// The one-block files of canonical blocks that could not be read, such as files still
+    /// being written. Bundles that lack a block because of them are left for a later merge.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// A block discarded by a [`Merger`], as another block at its height is canonical.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForkedBlock {
+    /// The block number.
+    pub number: u64,
+    /// The block hash.
+    pub hash: Vec<u8>,
+    /// The one-block files of the block.
+    pub paths: Vec<PathBuf>,
+}
+
+/// A block of one or more one-block files, as described by their file names.
+struct OneBlock {
+    number: u64,
+    hash: Vec<u8>,
+    parent_hash: Vec<u8>,
+    lib_num: u64,
+    paths: Vec<PathBuf>,
+}
+
+impl OneBlock {
+    /// Parses the name of a one-block file,
+    /// `{number:010}-{timestamp}-{id}-{previous_id}-{lib_num}-{suffix}.dbin`, optionally followed
+    /// by a compression extension. Returns `None` if the path is not a one-block file.
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        let (stem, extension) = file_name.split_once(".dbin")?;
+        if !(extension.is_empty() || extension.starts_with('.')) {
+            return None;
+        }
+
+        let mut parts = stem.splitn(6, '-');
+        let number = parts.next().filter(|number| {
+            number.len() == ONE_BLOCK_NUMBER_DIGITS && number.bytes().all(|b| b.is_ascii_digit())
+        })?;
+        let _timestamp = parts.next()?;
+        let hash = hex::decode(parts.next()?).ok()?;
+        let parent_hash = hex::decode(parts.next()?).ok()?;
+        let lib_num = parts.next()?.parse().ok()?;
+        let _suffix = parts.next()?;
+
+        Some(Self {
+            number: number.parse().ok()?,
+            hash,
+            parent_hash,
+            lib_num,
+            paths: vec![path],
+        })
+    }
+}
+
+/// The `.dbin` message of a one-block file, merged as is.
+struct OneBlockMessage {
+    content_version: String,
+    message: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use firehose_protos::{BlockHeader, EthBlock as Block};
+
+    use super::*;
+    use crate::decoder::decode_blocks_from_reader;
+
+    fn block(number: u64, fork: u8, parent_fork: u8) -> Block {
+        Block {
+            number,
+            hash: [number.to_be_bytes().as_slice(), &[fork]].concat(),
+            header: Some(BlockHeader {
+                number,
+                parent_hash: [
+                    number.wrapping_sub(1).to_be_bytes().as_slice(),
+                    &[parent_fork],
+                ]
+                .concat(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn one_block_path(dir: &Path, block: &Block, lib_num: u64, suffix: &str) -> PathBuf {
+        dir.join(format!(
+            "{:010}-20240101T000000.0-{}-{}-{lib_num}-{suffix}.dbin.zst",
+            block.number,
+            hex::encode(&block.hash),
+            hex::encode(&block.header.as_ref().unwrap().parent_hash),
+        ))
+    }
+
+    fn write_one_block(dir: &Path, block: &Block, lib_num: u64, suffix: &str) {
+        let path = one_block_path(dir, block, lib_num, suffix);
+        let message = BstreamBlock {
+            number: block.number,
+            lib_num,
+            payload_buffer: block.encode_to_vec(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let mut writer =
+            DbinWriter::new(File::create(path).unwrap(), "ETH", "01", Compression::Zstd).unwrap();
+        writer.write_message(&message).unwrap();
+        writer.finish().unwrap();
+    }
+
+    /// Writes the chain of blocks `0..=head` of fork 0, with a LIB two blocks behind.
+    fn write_chain(dir: &Path, head: u64) {
+        for number in 0..=head {
+            write_one_block(dir, &block(number, 0, 0), number.saturating_sub(2), "a");
+        }
+    }
+
+    fn read_bundle(path: &Path) -> Vec<Block> {
+        decode_blocks_from_reader(File::open(path).unwrap(), Compression::Auto).unwrap()
+    }
+
+    #[test]
+    fn test_merge_canonical_chain_discards_forks() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        write_chain(input.path(), 210);
+        // A fork at 50 and 51, abandoned by the canonical chain.
+        write_one_block(input.path(), &block(50, 1, 0), 48, "b");
+        write_one_block(input.path(), &block(51, 1, 1), 49, "b");
+        // The same canonical block, written by another reader.
+        write_one_block(input.path(), &block(60, 0, 0), 58, "b");
+        fs::write(input.path().join("README.md"), b"ignored").unwrap();
+
+        let report = Merger::new(output.path()).merge(input.path()).unwrap();
+
+        assert_eq!(report.lib, Some(208));
+        assert_eq!(
+            report.bundles,
+            vec![
+                output.path().join("0000000000.dbin"),
+                output.path().join("0000000100.dbin"),
+            ]
+        );
+        assert_eq!(report.merged.len(), 201);
+        let discarded: Vec<_> = report
+            .discarded
+            .iter()
+            .map(|block| (block.number, block.hash[8]))
+            .collect();
+        assert_eq!(discarded, vec![(50, 1), (51, 1)]);
+
+        let blocks = read_bundle(&report.bundles[0]);
+        assert_eq!(blocks.len(), 100);
+        assert!(blocks
+            .iter()
+            .enumerate()
+            .all(|(i, block)| block.number == i as u64 && block.hash[8] == 0));
+        let numbers: Vec<_> = read_bundle(&report.bundles[1])
+            .iter()
+            .map(|block| block.number)
+            .collect();
+        assert_eq!(numbers, (100..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_merge_follows_fork_that_became_canonical() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        write_chain(input.path(), 95);
+        // The chain forks after block 95, and the fork reaches a LIB of 103.
+        for number in 96..=105 {
+            let parent_fork = u8::from(number > 96);
+            write_one_block(
+                input.path(),
+                &block(number, 1, parent_fork),
+                number - 2,
+                "b",
+            );
+        }
+        for number in 96..=98 {
+            write_one_block(input.path(), &block(number, 0, 0), number - 2, "a");
+        }
+
+        let report = Merger::new(output.path())
+            .with_compression(Compression::Zstd)
+            .merge(input.path())
+            .unwrap();
+
+        assert_eq!(report.lib, Some(103));
+        assert_eq!(
+            report.bundles,
+            vec![output.path().join("0000000000.dbin.zst")]
+        );
+        let discarded: Vec<_> = report
+            .discarded
+            .iter()
+            .map(|block| (block.number, block.hash[8]))
+            .collect();
+        assert_eq!(discarded, vec![(96, 0), (97, 0), (98, 0)]);
+
+        let forks: Vec<_> = read_bundle(&report.bundles[0])[96..]
+            .iter()
+            .map(|block| block.hash[8])
+            .collect();
+        assert_eq!(forks, vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_merge_skips_incomplete_bundles() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        for number in 150..=310 {
+            write_one_block(input.path(), &block(number, 0, 0), number - 1, "a");
+        }
+
+        let report = Merger::new(output.path()).merge(input.path()).unwrap();
+
+        assert_eq!(report.lib, Some(309));
+        assert_eq!(report.bundles, vec![output.path().join("0000000200.dbin")]);
+        assert_eq!(report.merged.len(), 100);
+        assert!(report.discarded.is_empty());
+    }
+
+    #[test]
+    fn test_merge_skips_unreadable_files() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        write_chain(input.path(), 210);
+        // Block 150 is still being written, and its other copy can be read.
+        let partial = one_block_path(input.path(), &block(150, 0, 0), 148, "0");
+        fs::write(&partial, b"dbin").unwrap();
+        // Block 60 is still being written, and has no other copy.
+        let unreadable = one_block_path(input.path(), &block(60, 0, 0), 58, "a");
+        fs::write(&unreadable, b"").unwrap();
+        // Blocks above the LIB are not read.
+        fs::write(
+            one_block_path(input.path(), &block(211, 0, 0), 209, "a"),
+            b"",
+        )
+        .unwrap();
+
+        let report = Merger::new(output.path()).merge(input.path()).unwrap();
+
+        assert_eq!(report.lib, Some(209));
+        assert_eq!(report.bundles, vec![output.path().join("0000000100.dbin")]);
+        assert_eq!(report.merged.len(), 101);
+        assert_eq!(report.skipped, vec![unreadable, partial]);
+        let numbers: Vec<_> = read_bundle(&report.bundles[0])
+            .iter()
+            .map(|block| block.number)
+            .collect();
+        assert_eq!(numbers, (100..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_merge_empty_dir() {
+        let input = tempfile::tempdir().unwrap();
+        let report = Merger::new(input.path().join("merged"))
+            .merge(input.path())
+            .unwrap();
+        assert_eq!(report, MergeReport::default());
+    }
+}
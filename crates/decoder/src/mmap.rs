@@ -0,0 +1,174 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fs::File, io, path::Path};
+
+use firehose_protos::EthBlock as Block;
+use memmap2::Mmap;
+
+use crate::{
+    dbin::{read_header, DbinHeader, Prefix, HEADER_SIZE, PREFIX_SIZE},
+    decoder::{block_is_verified, decode_block_from_message},
+    error::DecoderError,
+};
+
+/// Work with an uncompressed `.dbin` flat file mapped into memory.
+///
+/// Messages are handed out as slices borrowed from the mapping, and blocks are decoded
+/// straight from those slices, so no intermediate buffers are allocated per message.
+/// Compressed files must be decompressed first, or read with
+/// [`read_blocks_from_reader`](crate::read_blocks_from_reader).
+#[derive(Debug)]
+pub struct MmapDbinFile {
+    mmap: Mmap,
+    header: DbinHeader,
+}
+
+impl MmapDbinFile {
+    /// Map the `.dbin` file at the given path into memory and parse its header.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DecoderError> {
+        let file = File::open(path)?;
+        // SAFETY: flat files are immutable once written; the mapping is only ever read, and
+        // modifying or truncating the file while it is mapped is not supported.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header = DbinSlices::new(&mmap)?.header;
+        Ok(Self { mmap, header })
+    }
+
+    /// Get the header of the `.dbin` file.
+    pub fn header(&self) -> &DbinHeader {
+        &self.header
+    }
+
+    /// Get an iterator over the messages of the file, borrowed from the mapping.
+    pub fn messages(&self) -> DbinSlices<'_> {
+        DbinSlices {
+            bytes: &self.mmap[HEADER_SIZE..],
+            header: self.header.clone(),
+        }
+    }
+
+    /// Decode and verify every block of the file.
+    ///
+    /// Like [`read_blocks_from_reader`](crate::read_blocks_from_reader), this fails if the
+    /// content type is not `ETH` or if any block fails verification.
+    pub fn read_blocks(&self) -> Result<Vec<Block>, DecoderError> {
+        self.header.check_eth()?;
+
+        let mut messages = self.messages();
+        let mut blocks = Vec::new();
+
+        while let Some(message) = messages.next() {
+            let block = decode_block_from_message(messages.header(), message?)?;
+            if !block_is_verified(&block) {
+                return Err(DecoderError::VerificationFailed {
+                    block_number: block.number,
+                });
+            }
+            blocks.push(block);
+        }
+
+        Ok(blocks)
+    }
+}
+
+/// Iterate over the messages of an in-memory `.dbin` file as borrowed slices.
+///
+/// Concatenated `.dbin` files are handled the same way as by [`DbinReader`](crate::DbinReader).
+/// A truncated length prefix or message yields an [`io::ErrorKind::UnexpectedEof`] error and
+/// ends the iteration.
+#[derive(Debug)]
+pub struct DbinSlices<'a> {
+    bytes: &'a [u8],
+    header: DbinHeader,
+}
+
+impl<'a> DbinSlices<'a> {
+    /// Parse the `.dbin` header at the start of the given bytes.
+    pub fn new(mut bytes: &'a [u8]) -> Result<Self, DecoderError> {
+        let header = DbinHeader::try_from_read(&mut bytes)?;
+        Ok(Self { bytes, header })
+    }
+
+    /// Get the header of the `.dbin` file that the most recently yielded message belongs to.
+    pub fn header(&self) -> &DbinHeader {
+        &self.header
+    }
+
+    fn next_message(&mut self) -> Result<Option<&'a [u8]>, DecoderError> {
+        loop {
+            if self.bytes.is_empty() {
+                return Ok(None);
+            }
+
+            let (prefix, mut rest) = split_at_checked(self.bytes, PREFIX_SIZE)?;
+            let prefix: [u8; PREFIX_SIZE] = prefix.try_into()?;
+
+            match Prefix::from(prefix) {
+                Prefix::Header => {
+                    self.header = read_header(&mut rest)?;
+                    self.bytes = rest;
+                }
+                Prefix::Message(message_length) => {
+                    let (message, rest) = split_at_checked(rest, message_length)?;
+                    self.bytes = rest;
+                    return Ok(Some(message));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for DbinSlices<'a> {
+    type Item = Result<&'a [u8], DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.next_message();
+        if message.is_err() {
+            self.bytes = &[];
+        }
+        message.transpose()
+    }
+}
+
+fn split_at_checked(bytes: &[u8], mid: usize) -> Result<(&[u8], &[u8]), DecoderError> {
+    if mid > bytes.len() {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(bytes.split_at(mid))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compression, DbinWriter};
+
+    use super::*;
+
+    #[test]
+    fn test_slices_concatenated_files() {
+        let mut data = vec![];
+        for message in [b"test".as_slice(), b"123".as_slice()] {
+            let mut writer = DbinWriter::new(vec![], "ETH", "01", Compression::None).unwrap();
+            writer.write_message(message).unwrap();
+            data.extend(writer.finish().unwrap());
+        }
+
+        let slices = DbinSlices::new(&data).unwrap();
+        assert_eq!(slices.header().content_type(), "ETH");
+        let messages: Vec<_> = slices.collect::<Result<_, _>>().unwrap();
+        assert_eq!(messages, vec![b"test".as_slice(), b"123".as_slice()]);
+    }
+
+    #[test]
+    fn test_slices_truncated_message() {
+        let mut writer = DbinWriter::new(vec![], "ETH", "01", Compression::None).unwrap();
+        writer.write_message(b"test").unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut slices = DbinSlices::new(&data[..data.len() - 1]).unwrap();
+        assert!(
+            matches!(slices.next(), Some(Err(DecoderError::Io(ref e))) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+        assert!(slices.next().is_none());
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use firehose_protos::EthBlock as Block;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use thiserror::Error;
+
+use crate::{
+    decoder::decode_blocks_from_reader, error::DecoderError, DecompressionOptions,
+    VerificationOptions,
+};
+
+/// Dbin file type extension
+const EXTENSION: &str = "dbin";
+
+/// Set how a directory of flat files is decoded and verified by [`decode_dir_parallel`].
+#[derive(Clone, Debug)]
+pub struct ParallelOptions {
+    /// How the flat files are decompressed.
+    pub decompression: DecompressionOptions,
+    /// How the blocks of the flat files are verified.
+    pub verification: VerificationOptions,
+    /// The number of worker threads, or `0` for one per available CPU.
+    pub threads: usize,
+    /// The maximum number of files decoded at once. Memory use is bounded by the decoded blocks
+    /// of this many files.
+    pub files_in_flight: usize,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            decompression: DecompressionOptions::default(),
+            verification: VerificationOptions::default(),
+            threads: 0,
+            files_in_flight: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// Error decoding or verifying a single flat file of a directory.
+#[derive(Debug, Error)]
+#[error("{}: {error}", path.display())]
+pub struct FileError {
+    path: PathBuf,
+    error: DecoderError,
+}
+
+impl FileError {
+    /// Get the path of the flat file that failed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the error that made the flat file fail.
+    pub fn error(&self) -> &DecoderError {
+        &self.error
+    }
+
+    /// Consume the error, returning the underlying [`DecoderError`].
+    pub fn into_error(self) -> DecoderError {
+        self.error
+    }
+}
+
+/// Decode and verify every `.dbin` file of a directory in parallel.
+///
+/// Compressed files with an extra extension, such as `.dbin.zst` or `.dbin.gz`, are included
+/// too, and decompressed according to [`ParallelOptions::decompression`]. With the default
+/// [`Compression::Auto`](crate::Compression::Auto), a directory can mix files with different
+/// compressions.
+///
+/// Files are decoded on a thread pool, at most [`ParallelOptions::files_in_flight`] at a time, and
+/// their blocks are verified in parallel too, according to [`ParallelOptions::verification`]. The
+/// returned iterator yields the blocks in block-number order, assuming the files follow the
+/// Firehose `{:010}.dbin` naming convention. A file that fails to decode, or to verify in
+/// [`FailureMode::FailFast`](crate::FailureMode::FailFast) mode, yields a single [`FileError`] in
+/// its place, without stopping the other files.
+///
+/// # Arguments
+///
+/// * `path`: The directory containing the flat files.
+/// * `options`: The [`ParallelOptions`] to decode with.
+pub fn decode_dir_parallel<P: AsRef<Path>>(
+    path: P,
+    options: ParallelOptions,
+) -> Result<ParallelBlocks, DecoderError> {
+    let mut files = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|path| is_dbin_file(path));
+    files.sort();
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()?;
+
+    Ok(ParallelBlocks {
+        pool,
+        files: files.into(),
+        options,
+        pending: VecDeque::new(),
+    })
+}
+
+/// Iterator of blocks decoded and verified by [`decode_dir_parallel`].
+pub struct ParallelBlocks {
+    pool: ThreadPool,
+    files: VecDeque<PathBuf>,
+    options: ParallelOptions,
+    pending: VecDeque<Result<Block, FileError>>,
+}
+
+impl ParallelBlocks {
+    /// Get the number of files not yet decoded.
+    pub fn files_remaining(&self) -> usize {
+        self.files.len()
+    }
+
+    fn decode_next_files(&mut self) {
+        let count = self.options.files_in_flight.max(1).min(self.files.len());
+        let files: Vec<_> = self.files.drain(..count).collect();
+        let options = &self.options;
+
+        let results: Vec<_> = self.pool.install(|| {
+            files
+                .into_par_iter()
+                .map(|path| {
+                    decode_and_verify_file(&path, options)
+                        .map_err(|error| FileError { path, error })
+                })
+                .collect()
+        });
+
+        for result in results {
+            match result {
+                Ok(blocks) => self.pending.extend(blocks.into_iter().map(Ok)),
+                Err(e) => self.pending.push_back(Err(e)),
+            }
+        }
+    }
+}
+
+impl Iterator for ParallelBlocks {
+    type Item = Result<Block, FileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.files.is_empty() {
+            self.decode_next_files();
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Checks if the path has a `.dbin` extension, optionally followed by a compression extension.
+fn is_dbin_file(path: &Path) -> bool {
+    let is_dbin = |path: &Path| path.extension().is_some_and(|ext| ext == EXTENSION);
+    is_dbin(path)
+        || path
+            .file_stem()
+            .is_some_and(|stem| is_dbin(Path::new(stem)))
+}
+
+fn decode_and_verify_file(
+    path: &Path,
+    options: &ParallelOptions,
+) -> Result<Vec<Block>, DecoderError> {
+    let blocks = decode_blocks_from_reader(File::open(path)?, options.decompression)?;
+
+    let results: Vec<_> = blocks
+        .into_par_iter()
+        .map(|block| options.verification.apply(block))
+        .collect();
+    let mut blocks = results
+        .into_iter()
+        .filter_map(|result| result.map(|(block, _)| block).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    blocks.sort_by_key(|block| block.number);
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression, FailureMode,
+    };
+
+    use super::*;
+
+    fn write_dbin_file(path: &Path, block_numbers: &[u64], compression: Compression) {
+        let blocks: Vec<_> = block_numbers.iter().copied().map(verified_block).collect();
+        fs::write(path, dbin_file(&blocks, compression)).unwrap();
+    }
+
+    #[test]
+    fn test_decode_dir_parallel_in_block_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for (file_name, block_numbers) in [
+            ("0000000100.dbin", [101, 100].as_slice()),
+            ("0000000000.dbin", &[0, 1]),
+            ("0000000200.dbin", &[200]),
+        ] {
+            write_dbin_file(
+                &dir.path().join(file_name),
+                block_numbers,
+                Compression::None,
+            );
+        }
+        fs::write(dir.path().join("0000000300.dbin"), b"not a dbin file").unwrap();
+        fs::write(dir.path().join("README.md"), b"ignored").unwrap();
+
+        let options = ParallelOptions {
+            files_in_flight: 2,
+            ..Default::default()
+        };
+        let results: Vec<_> = decode_dir_parallel(dir.path(), options).unwrap().collect();
+
+        let numbers: Vec<_> = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .map(|block| block.number)
+            .collect();
+        assert_eq!(numbers, vec![0, 1, 100, 101, 200]);
+
+        let errors: Vec<_> = results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path(), dir.path().join("0000000300.dbin"));
+        assert!(matches!(errors[0].error(), DecoderError::MagicBytesInvalid));
+    }
+
+    #[test]
+    fn test_decode_dir_parallel_mixed_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        for (file_name, block_number, compression) in [
+            ("0000000000.dbin", 0, Compression::None),
+            ("0000000100.dbin.zst", 100, Compression::Zstd),
+            ("0000000200.dbin.gz", 200, Compression::Gzip),
+        ] {
+            write_dbin_file(&dir.path().join(file_name), &[block_number], compression);
+        }
+        fs::write(dir.path().join("0000000300.zst"), b"ignored").unwrap();
+
+        let numbers: Vec<_> = decode_dir_parallel(dir.path(), ParallelOptions::default())
+            .unwrap()
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn test_decode_dir_parallel_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tampered = verified_block(2);
+        tampered.header.as_mut().unwrap().receipt_root = vec![0; 32];
+        let data = dbin_file(
+            &[verified_block(1), tampered, verified_block(3)],
+            Compression::None,
+        );
+        fs::write(dir.path().join("0000000000.dbin"), data).unwrap();
+
+        let results: Vec<_> = decode_dir_parallel(dir.path(), ParallelOptions::default())
+            .unwrap()
+            .collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0].as_ref().unwrap_err().error(),
+            DecoderError::VerificationFailed { block_number: 2 }
+        ));
+
+        let mut options = ParallelOptions::default();
+        options.verification.failure_mode = FailureMode::Skip;
+        let numbers: Vec<_> = decode_dir_parallel(dir.path(), options)
+            .unwrap()
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, vec![1, 3]);
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::ops::Range;
+
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path,
+    ObjectStore,
+};
+use tokio::runtime::Runtime;
+
+use crate::{error::DecoderError, storage::BlockStorage};
+
+/// [`BlockStorage`] backed by a bucket of Amazon S3 or an S3-compatible object storage, such
+/// as MinIO.
+///
+/// Requests are made on a runtime owned by the storage, so its methods block the calling
+/// thread, and must not be called from within an asynchronous context.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use flat_files_decoder::{FlatFileStore, S3Storage};
+///
+/// // Reads AWS_ENDPOINT, AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_ALLOW_HTTP and the
+/// // other AWS_* variables, such as for a local MinIO server.
+/// let storage = Arc::new(S3Storage::from_env("flat-files")?);
+/// let store = FlatFileStore::from_storage(storage, "mainnet/merged-blocks")?;
+/// let blocks = store.blocks(0..=199).collect::<Result<Vec<_>, _>>()?;
+/// # Ok::<(), flat_files_decoder::DecoderError>(())
+/// ```
+#[derive(Debug)]
+pub struct S3Storage {
+    store: AmazonS3,
+    runtime: Runtime,
+}
+
+impl S3Storage {
+    /// Connect to a bucket, configured by the `AWS_*` environment variables.
+    pub fn from_env(bucket: &str) -> Result<Self, DecoderError> {
+        Self::new(AmazonS3Builder::from_env().with_bucket_name(bucket))
+    }
+
+    /// Connect to the bucket configured by an [`AmazonS3Builder`], for setting the endpoint,
+    /// credentials and region explicitly.
+    pub fn new(builder: AmazonS3Builder) -> Result<Self, DecoderError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            store: builder.build()?,
+            runtime,
+        })
+    }
+}
+
+impl BlockStorage for S3Storage {
+    fn list(&self, dir: &str) -> Result<Vec<String>, DecoderError> {
+        let dir = Path::from(dir);
+        let prefix = (!dir.as_ref().is_empty()).then_some(&dir);
+        let listing = self
+            .runtime
+            .block_on(self.store.list_with_delimiter(prefix))?;
+        let mut names: Vec<String> = listing
+            .objects
+            .into_iter()
+            .map(|meta| meta.location.to_string())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn size(&self, name: &str) -> Result<u64, DecoderError> {
+        let meta = self.runtime.block_on(self.store.head(&Path::from(name)))?;
+        Ok(meta.size as u64)
+    }
+
+    fn read_range(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, DecoderError> {
+        let range = range.start as usize..range.end as usize;
+        let bytes = self
+            .runtime
+            .block_on(self.store.get_range(&Path::from(name), range))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use object_store::PutPayload;
+
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression, FlatFileBundle, FlatFileStore,
+    };
+
+    use super::*;
+
+    /// Runs against the bucket named by `S3_TEST_BUCKET`, such as on a local MinIO server:
+    ///
+    /// ```terminal
+    /// docker run -p 9000:9000 minio/minio server /data
+    /// AWS_ENDPOINT=http://localhost:9000 AWS_ALLOW_HTTP=true AWS_ACCESS_KEY_ID=minioadmin \
+    ///     AWS_SECRET_ACCESS_KEY=minioadmin S3_TEST_BUCKET=flat-files \
+    ///     cargo test --features s3 -- --ignored s3
+    /// ```
+    #[test]
+    #[ignore = "requires an S3-compatible server, such as MinIO"]
+    fn test_s3_store_blocks_in_range() {
+        let bucket = std::env::var("S3_TEST_BUCKET").unwrap();
+        let storage = Arc::new(S3Storage::from_env(&bucket).unwrap());
+
+        for start_block in [0, 100] {
+            let blocks: Vec<_> = (start_block.max(1)..start_block + 100)
+                .map(verified_block)
+                .collect();
+            let name = format!("veemon-test/{}.zst", FlatFileBundle::file_name(start_block));
+            let payload = PutPayload::from(dbin_file(&blocks, Compression::Zstd));
+            storage
+                .runtime
+                .block_on(storage.store.put(&Path::from(name), payload))
+                .unwrap();
+        }
+
+        let store = FlatFileStore::from_storage(storage, "veemon-test").unwrap();
+        assert_eq!(store.covered_ranges(), vec![0..=199]);
+        let numbers: Vec<_> = store
+            .blocks(50..=149)
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, (50..=149).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    fs::{self, File},
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, OnceLock, RwLock,
+    },
+};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::error::DecoderError;
+
+/// The default size of each ranged read of a [`RangedReader`], 8 MiB.
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The default number of chunks a [`RangedReader`] fetches ahead of the one being read.
+pub const DEFAULT_PREFETCH_CHUNKS: usize = 2;
+
+/// The number of threads shared by every [`RangedReader`] to fetch chunks in the background.
+const PREFETCH_THREADS: usize = 8;
+
+/// A source of flat file objects, such as a local directory or an object storage bucket.
+///
+/// Objects are named by their `/`-separated path relative to the root of the storage. Objects
+/// are read with [`RangedReader`], which fetches them in ranges and can be passed to any of the
+/// reader-based functions of this crate, such as
+/// [`read_blocks_from_reader`](crate::read_blocks_from_reader).
+pub trait BlockStorage: fmt::Debug + Send + Sync {
+    /// List the names of the objects directly in a directory, in lexicographic order.
+    ///
+    /// Objects in subdirectories are not listed, so that listing a directory of flat files
+    /// does not walk the whole storage. An empty directory is the root of the storage.
+    fn list(&self, dir: &str) -> Result<Vec<String>, DecoderError>;
+
+    /// Get the size of an object, in bytes.
+    fn size(&self, name: &str) -> Result<u64, DecoderError>;
+
+    /// Read a range of bytes of an object. The range must lie within the object.
+    fn read_range(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, DecoderError>;
+}
+
+/// [`BlockStorage`] backed by a directory of the local file system.
+#[derive(Clone, Debug)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Use the given directory as the root of the storage.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+}
+
+impl BlockStorage for LocalStorage {
+    fn list(&self, dir: &str) -> Result<Vec<String>, DecoderError> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.path(dir))? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            names.push(match dir.trim_end_matches('/') {
+                "" => file_name,
+                dir => format!("{dir}/{file_name}"),
+            });
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn size(&self, name: &str) -> Result<u64, DecoderError> {
+        Ok(fs::metadata(self.path(name))?.len())
+    }
+
+    fn read_range(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, DecoderError> {
+        let mut file = File::open(self.path(name))?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut bytes = Vec::with_capacity(range.end.saturating_sub(range.start) as usize);
+        file.take(range.end.saturating_sub(range.start))
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// [`BlockStorage`] holding its objects in memory, for tests and small caches.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    objects: RwLock<BTreeMap<String, Arc<[u8]>>>,
+}
+
+impl MemoryStorage {
+    /// Create an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store an object, replacing any object with the same name.
+    pub fn insert<B: Into<Arc<[u8]>>>(&self, name: &str, bytes: B) {
+        self.objects
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), bytes.into());
+    }
+
+    fn get(&self, name: &str) -> Result<Arc<[u8]>, DecoderError> {
+        self.objects
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("Object {name} not found")).into()
+            })
+    }
+}
+
+impl BlockStorage for MemoryStorage {
+    fn list(&self, dir: &str) -> Result<Vec<String>, DecoderError> {
+        let prefix = match dir.trim_end_matches('/') {
+            "" => String::new(),
+            dir => format!("{dir}/"),
+        };
+        Ok(self
+            .objects
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .range(prefix.clone()..)
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(&prefix))
+            .filter(|name| !name[prefix.len()..].contains('/'))
+            .cloned()
+            .collect())
+    }
+
+    fn size(&self, name: &str) -> Result<u64, DecoderError> {
+        Ok(self.get(name)?.len() as u64)
+    }
+
+    fn read_range(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, DecoderError> {
+        let bytes = self.get(name)?;
+        bytes
+            .get(range.start as usize..range.end as usize)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+}
+
+/// Set how a [`RangedReader`] fetches an object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangedReadOptions {
+    /// The number of bytes fetched by each ranged read.
+    pub chunk_size: u64,
+    /// The number of chunks fetched in the background ahead of the one being read. With `0`,
+    /// every chunk is fetched when the reader reaches it.
+    pub prefetch_chunks: usize,
+}
+
+impl Default for RangedReadOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            prefetch_chunks: DEFAULT_PREFETCH_CHUNKS,
+        }
+    }
+}
+
+/// A chunk fetched in the background, received once its ranged read completes.
+type Chunk = Receiver<Result<Vec<u8>, DecoderError>>;
+
+/// Get the thread pool shared by every [`RangedReader`] to fetch chunks in the background, or
+/// `None` if it could not be started, in which case chunks are only fetched when read.
+fn prefetch_pool() -> Option<&'static ThreadPool> {
+    static POOL: OnceLock<Option<ThreadPool>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .num_threads(PREFETCH_THREADS)
+            .thread_name(|index| format!("ranged-read-{index}"))
+            // A panicking read is reported to its reader as a closed channel.
+            .panic_handler(|_| {})
+            .build()
+            .ok()
+    })
+    .as_ref()
+}
+
+/// Read an object of a [`BlockStorage`] sequentially, with ranged reads of fixed-size chunks.
+///
+/// Upcoming chunks are fetched in the background while the current one is read, so that
+/// decoding a remote object does not wait on a round trip for every chunk, and memory use is
+/// bounded by the chunk size and the number of prefetched chunks rather than the object size.
+/// Background fetches run on a small pool of threads shared by every reader. When a reader is
+/// dropped, its prefetched chunks that were not read are discarded, and its fetches that have
+/// not started yet are cancelled.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use flat_files_decoder::{
+///     read_blocks_from_reader, Compression, LocalStorage, RangedReadOptions, RangedReader,
+/// };
+///
+/// let storage = Arc::new(LocalStorage::new("merged-blocks"));
+/// let reader = RangedReader::new(storage, "0000000000.dbin.zst", RangedReadOptions::default())?;
+/// let blocks = read_blocks_from_reader(reader, Compression::Auto)?;
+/// # Ok::<(), flat_files_decoder::DecoderError>(())
+/// ```
+pub struct RangedReader {
+    storage: Arc<dyn BlockStorage>,
+    name: Arc<str>,
+    size: u64,
+    next_offset: u64,
+    options: RangedReadOptions,
+    in_flight: VecDeque<Chunk>,
+    chunk: Cursor<Vec<u8>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl RangedReader {
+    /// Start reading an object, fetching its size and its first prefetched chunks.
+    pub fn new(
+        storage: Arc<dyn BlockStorage>,
+        name: &str,
+        options: RangedReadOptions,
+    ) -> Result<Self, DecoderError> {
+        let size = storage.size(name)?;
+        let mut reader = Self {
+            storage,
+            name: name.into(),
+            size,
+            next_offset: 0,
+            options: RangedReadOptions {
+                chunk_size: options.chunk_size.max(1),
+                ..options
+            },
+            in_flight: VecDeque::new(),
+            chunk: Cursor::default(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        reader.prefetch();
+        Ok(reader)
+    }
+
+    /// Get the size of the object, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn next_range(&mut self) -> Range<u64> {
+        let start = self.next_offset;
+        self.next_offset = start.saturating_add(self.options.chunk_size).min(self.size);
+        start..self.next_offset
+    }
+
+    fn prefetch(&mut self) {
+        let Some(pool) = prefetch_pool() else {
+            return;
+        };
+        while self.in_flight.len() < self.options.prefetch_chunks && self.next_offset < self.size {
+            let range = self.next_range();
+            let storage = Arc::clone(&self.storage);
+            let name = Arc::clone(&self.name);
+            let cancelled = Arc::clone(&self.cancelled);
+            let (sender, receiver) = mpsc::sync_channel(1);
+            pool.spawn(move || {
+                // Skip the fetch if the reader was dropped while it was queued.
+                if !cancelled.load(Ordering::Relaxed) {
+                    _ = sender.send(storage.read_range(&name, range));
+                }
+            });
+            self.in_flight.push_back(receiver);
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let chunk = match self.in_flight.pop_front() {
+            Some(receiver) => receiver
+                .recv()
+                .map_err(|_| io::Error::other("Ranged read thread panicked"))?,
+            None if self.next_offset < self.size => {
+                let range = self.next_range();
+                self.storage.read_range(&self.name, range)
+            }
+            None => return Ok(None),
+        };
+        self.prefetch();
+
+        chunk.map(Some).map_err(|e| match e {
+            DecoderError::Io(e) => e,
+            e => io::Error::other(e),
+        })
+    }
+}
+
+impl fmt::Debug for RangedReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RangedReader")
+            .field("storage", &self.storage)
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("next_offset", &self.next_offset)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for RangedReader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Read for RangedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.next_chunk()? {
+                Some(chunk) => self.chunk = Cursor::new(chunk),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicUsize, Condvar, Mutex};
+
+    use crate::{
+        read_blocks_from_reader,
+        test_utils::{dbin_file, verified_block},
+        Compression,
+    };
+
+    use super::*;
+
+    /// Storage whose ranged reads wait until it is opened, counting the reads started.
+    #[derive(Debug, Default)]
+    struct GatedStorage {
+        memory: MemoryStorage,
+        open: Mutex<bool>,
+        opened: Condvar,
+        reads: AtomicUsize,
+    }
+
+    impl BlockStorage for GatedStorage {
+        fn list(&self, dir: &str) -> Result<Vec<String>, DecoderError> {
+            self.memory.list(dir)
+        }
+
+        fn size(&self, name: &str) -> Result<u64, DecoderError> {
+            self.memory.size(name)
+        }
+
+        fn read_range(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, DecoderError> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            let open = self.open.lock().unwrap();
+            drop(self.opened.wait_while(open, |open| !*open).unwrap());
+            self.memory.read_range(name, range)
+        }
+    }
+
+    #[test]
+    fn test_storage_list_and_read_range() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("eth")).unwrap();
+        fs::write(dir.path().join("eth/0000000000.dbin"), b"0123456789").unwrap();
+        fs::create_dir(dir.path().join("eth/forks")).unwrap();
+        fs::write(dir.path().join("eth/forks/0000000000.dbin"), b"").unwrap();
+        fs::write(dir.path().join("other"), b"").unwrap();
+        let local = LocalStorage::new(dir.path());
+
+        let memory = MemoryStorage::new();
+        memory.insert("eth/0000000000.dbin", b"0123456789".as_slice());
+        memory.insert("eth/forks/0000000000.dbin", b"".as_slice());
+        memory.insert("ethereum", b"".as_slice());
+
+        let storages: [&dyn BlockStorage; 2] = [&local, &memory];
+        for storage in storages {
+            assert_eq!(storage.list("eth").unwrap(), vec!["eth/0000000000.dbin"]);
+            assert_eq!(storage.list("").unwrap().len(), 1);
+            assert_eq!(storage.size("eth/0000000000.dbin").unwrap(), 10);
+            assert_eq!(
+                storage.read_range("eth/0000000000.dbin", 2..5).unwrap(),
+                b"234"
+            );
+            assert!(storage.size("eth/missing.dbin").is_err());
+        }
+    }
+
+    #[test]
+    fn test_ranged_reader_reads_blocks() {
+        let blocks: Vec<_> = (1..=20).map(verified_block).collect();
+        let bytes = dbin_file(&blocks, Compression::Zstd);
+
+        let storage = Arc::new(MemoryStorage::new());
+        storage.insert("0000000000.dbin.zst", bytes);
+
+        for prefetch_chunks in [0, 3] {
+            let options = RangedReadOptions {
+                chunk_size: 7,
+                prefetch_chunks,
+            };
+            let reader =
+                RangedReader::new(storage.clone(), "0000000000.dbin.zst", options).unwrap();
+            let blocks = read_blocks_from_reader(reader, Compression::Auto).unwrap();
+            let numbers: Vec<_> = blocks.iter().map(|block| block.number).collect();
+            assert_eq!(numbers, (1..=20).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_ranged_reader_drop_cancels_prefetch() {
+        let storage = Arc::new(GatedStorage::default());
+        storage.memory.insert("0000000000.dbin", vec![0; 64]);
+        let options = RangedReadOptions {
+            chunk_size: 1,
+            prefetch_chunks: 64,
+        };
+
+        drop(RangedReader::new(storage.clone(), "0000000000.dbin", options).unwrap());
+        *storage.open.lock().unwrap() = true;
+        storage.opened.notify_all();
+
+        // Only the fetches that started before the drop, one per thread at most, are read.
+        assert!(storage.reads.load(Ordering::SeqCst) <= PREFETCH_THREADS);
+    }
+}
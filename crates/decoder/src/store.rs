@@ -0,0 +1,498 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::VecDeque,
+    ops::{Bound, RangeBounds, RangeInclusive},
+    path::Path,
+    sync::Arc,
+};
+
+use firehose_protos::EthBlock as Block;
+
+use crate::{
+    error::DecoderError, read_blocks_with_verification, BlockStorage, DecompressionOptions,
+    LocalStorage, RangedReadOptions, RangedReader, VerificationOptions,
+};
+
+/// The number of blocks in each Firehose merged-blocks bundle.
+pub const BUNDLE_SIZE: u64 = 100;
+
+/// Dbin file type extension
+const EXTENSION: &str = "dbin";
+
+/// The number of digits of the first block number in a bundle file name.
+const BUNDLE_NUMBER_DIGITS: usize = 10;
+
+/// A merged-blocks bundle file of a [`FlatFileStore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlatFileBundle {
+    start_block: u64,
+    name: String,
+}
+
+impl FlatFileBundle {
+    /// Get the number of the first block of the bundle, from its file name.
+    pub fn start_block(&self) -> u64 {
+        self.start_block
+    }
+
+    /// Get the number of the last block of the bundle.
+    pub fn end_block(&self) -> u64 {
+        self.start_block + BUNDLE_SIZE - 1
+    }
+
+    /// Get the name of the bundle object in the [`BlockStorage`] of the store.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the file name of the uncompressed bundle starting at the given block, following the
+    /// `{:010}.dbin` naming convention.
+    pub fn file_name(start_block: u64) -> String {
+        format!(
+            "{start_block:0width$}.{EXTENSION}",
+            width = BUNDLE_NUMBER_DIGITS
+        )
+    }
+
+    /// Parses the first block number from a `{:010}.dbin` file name, optionally followed by a
+    /// compression extension such as `.zst`.
+    fn parse_start_block(file_name: &str) -> Option<u64> {
+        let (number, extension) = file_name.split_once('.')?;
+        let is_dbin = extension == EXTENSION
+            || extension
+                .strip_prefix(EXTENSION)
+                .is_some_and(|rest| rest.starts_with('.'));
+        if number.len() != BUNDLE_NUMBER_DIGITS
+            || !number.bytes().all(|b| b.is_ascii_digit())
+            || !is_dbin
+        {
+            return None;
+        }
+        number.parse().ok()
+    }
+
+    fn range(&self) -> RangeInclusive<u64> {
+        self.start_block..=self.end_block()
+    }
+}
+
+/// Index of a directory of Firehose merged-blocks bundles, for reading blocks by number.
+///
+/// The directory can be local, or in any other [`BlockStorage`], such as an object storage
+/// bucket. Bundle files follow the `{:010}.dbin` naming convention, optionally with a compression
+/// extension such as `.dbin.zst`, and hold [`BUNDLE_SIZE`] blocks each, starting at the block
+/// number in their name. Other files are ignored.
+///
+/// ```no_run
+/// use flat_files_decoder::FlatFileStore;
+///
+/// let store = FlatFileStore::open("merged-blocks")?;
+/// for block in store.blocks(0..=8191) {
+///     let block = block?;
+///     println!("{}", block.number);
+/// }
+/// # Ok::<(), flat_files_decoder::DecoderError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct FlatFileStore {
+    storage: Arc<dyn BlockStorage>,
+    bundles: Arc<[FlatFileBundle]>,
+    decompression: DecompressionOptions,
+    read_options: RangedReadOptions,
+    verification: VerificationOptions,
+}
+
+impl FlatFileStore {
+    /// Index the bundle files of a local directory.
+    ///
+    /// Bundles are decompressed according to [`DecompressionOptions::default`], detecting the
+    /// compression of each file, and verified according to [`VerificationOptions::default`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DecoderError> {
+        Self::from_storage(Arc::new(LocalStorage::new(path.as_ref())), "")
+    }
+
+    /// Index the bundle files of a directory of a [`BlockStorage`]. Bundles in subdirectories
+    /// are ignored.
+    pub fn from_storage(storage: Arc<dyn BlockStorage>, dir: &str) -> Result<Self, DecoderError> {
+        let mut bundles = Vec::new();
+        for name in storage.list(dir)? {
+            let file_name = name
+                .strip_prefix(dir.trim_end_matches('/'))
+                .map(|rest| rest.trim_start_matches('/'))
+                .filter(|file_name| !file_name.contains('/'));
+            if let Some(start_block) = file_name.and_then(FlatFileBundle::parse_start_block) {
+                bundles.push(FlatFileBundle { start_block, name });
+            }
+        }
+        bundles.sort_by(|a, b| (a.start_block, &a.name).cmp(&(b.start_block, &b.name)));
+
+        Ok(Self {
+            storage,
+            bundles: bundles.into(),
+            decompression: DecompressionOptions::default(),
+            read_options: RangedReadOptions::default(),
+            verification: VerificationOptions::default(),
+        })
+    }
+
+    /// Set how the bundle files are decompressed.
+    pub fn with_decompression(mut self, decompression: DecompressionOptions) -> Self {
+        self.decompression = decompression;
+        self
+    }
+
+    /// Set how the bundle files are fetched from the storage.
+    pub fn with_read_options(mut self, read_options: RangedReadOptions) -> Self {
+        self.read_options = read_options;
+        self
+    }
+
+    /// Set how the blocks read from the bundle files are verified.
+    pub fn with_verification(mut self, verification: VerificationOptions) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Get the indexed bundles, ordered by their first block number.
+    pub fn bundles(&self) -> &[FlatFileBundle] {
+        &self.bundles
+    }
+
+    /// Get the bundle containing the given block number, if any.
+    ///
+    /// If overlapping bundles contain the block, the one starting at a multiple of
+    /// [`BUNDLE_SIZE`] is preferred.
+    pub fn bundle_for_block(&self, block_number: u64) -> Option<&FlatFileBundle> {
+        self.bundle_starting_at(block_number - block_number % BUNDLE_SIZE)
+            .or_else(|| {
+                self.bundles
+                    .iter()
+                    .find(|bundle| bundle.range().contains(&block_number))
+            })
+    }
+
+    /// Get the ranges of block numbers covered by the indexed bundles, merging adjacent and
+    /// overlapping bundles.
+    pub fn covered_ranges(&self) -> Vec<RangeInclusive<u64>> {
+        let mut ranges: Vec<RangeInclusive<u64>> = Vec::new();
+        for bundle in self.bundles.iter() {
+            match ranges.last_mut() {
+                Some(range) if bundle.start_block <= range.end() + 1 => {
+                    *range = *range.start()..=bundle.end_block().max(*range.end());
+                }
+                _ => ranges.push(bundle.range()),
+            }
+        }
+        ranges
+    }
+
+    /// Get the first block numbers of the bundles missing between the first and the last
+    /// indexed bundles.
+    pub fn missing_bundles(&self) -> Vec<u64> {
+        let covered = self.covered_ranges();
+        covered
+            .windows(2)
+            .flat_map(|pair| {
+                let first_missing = pair[0].end() + 1;
+                let first_missing = first_missing.next_multiple_of(BUNDLE_SIZE);
+                (first_missing..*pair[1].start()).step_by(BUNDLE_SIZE as usize)
+            })
+            .collect()
+    }
+
+    /// Get the pairs of indexed bundles that hold some of the same block numbers, such as a
+    /// `.dbin` and a `.dbin.zst` file for the same bundle, or a bundle that does not start at
+    /// a multiple of [`BUNDLE_SIZE`].
+    pub fn overlapping_bundles(&self) -> Vec<(&FlatFileBundle, &FlatFileBundle)> {
+        let mut overlaps = Vec::new();
+        for (i, bundle) in self.bundles.iter().enumerate() {
+            for other in &self.bundles[i + 1..] {
+                if other.start_block > bundle.end_block() {
+                    break;
+                }
+                overlaps.push((bundle, other));
+            }
+        }
+        overlaps
+    }
+
+    /// Get an iterator of the blocks in the given range of block numbers, in block order.
+    ///
+    /// Bundles are only read, decoded and verified as the iterator reaches them. A bundle that
+    /// fails to decode or verify yields a single error in place of its blocks, and so does each
+    /// gap of consecutive bundles missing from the store. Iteration then continues with the
+    /// next bundle. The range is clamped to the indexed bundles: it starts with the first of them
+    /// at the earliest, and ends with the last at the latest, even if it is unbounded.
+    pub fn blocks<R: RangeBounds<u64>>(&self, range: R) -> FlatFileBlocks {
+        let range = self.clamp(range);
+        FlatFileBlocks {
+            storage: Arc::clone(&self.storage),
+            bundles: Arc::clone(&self.bundles),
+            next_block: range.as_ref().map_or(0, |range| *range.start()),
+            range,
+            decompression: self.decompression,
+            read_options: self.read_options,
+            verification: self.verification.clone(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Clamps a range of block numbers to the indexed bundles, or `None` if none of them are in
+    /// the range.
+    fn clamp<R: RangeBounds<u64>>(&self, range: R) -> Option<RangeInclusive<u64>> {
+        let first = self.bundles.first()?.start_block;
+        let last = self.bundles.last()?.end_block();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end,
+            Bound::Excluded(&end) => end.checked_sub(1)?,
+            Bound::Unbounded => last,
+        };
+        let (start, end) = (start.max(first), end.min(last));
+        (start <= end).then_some(start..=end)
+    }
+
+    fn bundle_starting_at(&self, start_block: u64) -> Option<&FlatFileBundle> {
+        next_bundle(&self.bundles, start_block).filter(|bundle| bundle.start_block == start_block)
+    }
+}
+
+/// Get the first bundle starting at a multiple of [`BUNDLE_SIZE`] at or after the given block
+/// number.
+fn next_bundle(bundles: &[FlatFileBundle], block_number: u64) -> Option<&FlatFileBundle> {
+    let index = bundles.partition_point(|bundle| bundle.start_block < block_number);
+    bundles[index..]
+        .iter()
+        .find(|bundle| bundle.start_block % BUNDLE_SIZE == 0)
+}
+
+/// Iterator of the blocks of a [`FlatFileStore`] in a range, returned by
+/// [`FlatFileStore::blocks`].
+pub struct FlatFileBlocks {
+    storage: Arc<dyn BlockStorage>,
+    bundles: Arc<[FlatFileBundle]>,
+    /// The range of block numbers to read, or `None` if it holds no indexed bundle.
+    range: Option<RangeInclusive<u64>>,
+    /// The first block number not yet read from the range.
+    next_block: u64,
+    decompression: DecompressionOptions,
+    read_options: RangedReadOptions,
+    verification: VerificationOptions,
+    pending: VecDeque<Block>,
+}
+
+impl FlatFileBlocks {
+    fn read_bundle(
+        &self,
+        name: &str,
+        range: &RangeInclusive<u64>,
+    ) -> Result<Vec<Block>, DecoderError> {
+        let report = read_blocks_with_verification(
+            RangedReader::new(Arc::clone(&self.storage), name, self.read_options)?,
+            self.decompression,
+            &self.verification,
+        )?;
+        let mut blocks: Vec<_> = report
+            .blocks
+            .into_iter()
+            .filter(|block| range.contains(&block.number))
+            .collect();
+        blocks.sort_by_key(|block| block.number);
+        Ok(blocks)
+    }
+}
+
+impl Iterator for FlatFileBlocks {
+    type Item = Result<Block, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(block) = self.pending.pop_front() {
+                return Some(Ok(block));
+            }
+
+            let range = self.range.clone()?;
+            let start_block = self.next_block;
+            if !range.contains(&start_block) {
+                return None;
+            }
+
+            let bundle_start = start_block - start_block % BUNDLE_SIZE;
+            match next_bundle(&self.bundles, bundle_start) {
+                Some(bundle) if bundle.start_block == bundle_start => {
+                    let name = bundle.name.clone();
+                    self.next_block = bundle.end_block().saturating_add(1);
+                    match self.read_bundle(&name, &range) {
+                        Ok(blocks) => self.pending = blocks.into(),
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                next => {
+                    let end_block = next
+                        .map_or(u64::MAX, |bundle| bundle.start_block - 1)
+                        .min(*range.end());
+                    self.next_block = end_block.saturating_add(1);
+                    return Some(Err(DecoderError::BundleMissing {
+                        start_block,
+                        end_block,
+                    }));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression, MemoryStorage,
+    };
+
+    use super::*;
+
+    fn write_bundle(dir: &Path, file_name: &str, block_numbers: impl Iterator<Item = u64>) {
+        let compression = if file_name.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        };
+        let blocks: Vec<_> = block_numbers.map(verified_block).collect();
+        fs::write(dir.join(file_name), dbin_file(&blocks, compression)).unwrap();
+    }
+
+    #[test]
+    fn test_parse_start_block() {
+        assert_eq!(
+            FlatFileBundle::parse_start_block("0000000100.dbin"),
+            Some(100)
+        );
+        assert_eq!(
+            FlatFileBundle::parse_start_block("0000000100.dbin.zst"),
+            Some(100)
+        );
+        assert_eq!(FlatFileBundle::parse_start_block("0000000100.dbinx"), None);
+        assert_eq!(FlatFileBundle::parse_start_block("100.dbin"), None);
+        assert_eq!(FlatFileBundle::parse_start_block("README.md"), None);
+        assert_eq!(FlatFileBundle::file_name(100), "0000000100.dbin");
+    }
+
+    #[test]
+    fn test_store_blocks_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        write_bundle(dir.path(), "0000000000.dbin", (1..100).rev());
+        write_bundle(dir.path(), "0000000100.dbin.zst", 100..200);
+        write_bundle(dir.path(), "0000000200.dbin", 200..300);
+
+        let store = FlatFileStore::open(dir.path()).unwrap();
+        assert_eq!(store.covered_ranges(), vec![0..=299]);
+        assert!(store.missing_bundles().is_empty());
+        assert!(store.overlapping_bundles().is_empty());
+
+        let numbers: Vec<_> = store
+            .blocks(95..=204)
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, (95..=204).collect::<Vec<_>>());
+
+        assert_eq!(store.blocks(250..).count(), 50);
+    }
+
+    #[test]
+    fn test_store_blocks_clamped_to_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        write_bundle(dir.path(), "0015000000.dbin", 15_000_000..15_000_100);
+
+        let store = FlatFileStore::open(dir.path()).unwrap();
+        let numbers: Vec<_> = store
+            .blocks(15_000_050..=u64::MAX)
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, (15_000_050..15_000_100).collect::<Vec<_>>());
+
+        for blocks in [store.blocks(..), store.blocks(0..=u64::MAX)] {
+            let numbers: Vec<_> = blocks.map(|block| block.unwrap().number).collect();
+            assert_eq!(numbers, (15_000_000..15_000_100).collect::<Vec<_>>());
+        }
+        assert_eq!(store.blocks(..15_000_000).count(), 0);
+
+        let empty = FlatFileStore::open(tempfile::tempdir().unwrap().path()).unwrap();
+        assert_eq!(empty.blocks(0..=u64::MAX).count(), 0);
+    }
+
+    #[test]
+    fn test_store_missing_and_overlapping_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        write_bundle(dir.path(), "0000000000.dbin", 1..100);
+        write_bundle(dir.path(), "0000000300.dbin", 300..400);
+        write_bundle(dir.path(), "0000000300.dbin.zst", 300..400);
+
+        let store = FlatFileStore::open(dir.path()).unwrap();
+        assert_eq!(store.covered_ranges(), vec![0..=99, 300..=399]);
+        assert_eq!(store.missing_bundles(), vec![100, 200]);
+
+        let overlaps = store.overlapping_bundles();
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].0.start_block(), 300);
+        assert_eq!(overlaps[0].1.start_block(), 300);
+
+        let results: Vec<_> = store.blocks(98..=300).collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().number, 98);
+        assert_eq!(results[1].as_ref().unwrap().number, 99);
+        assert!(matches!(
+            results[2],
+            Err(DecoderError::BundleMissing {
+                start_block: 100,
+                end_block: 299
+            })
+        ));
+        assert_eq!(results[3].as_ref().unwrap().number, 300);
+
+        let results: Vec<_> = store.blocks(150..=250).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0],
+            Err(DecoderError::BundleMissing {
+                start_block: 150,
+                end_block: 250
+            })
+        ));
+    }
+
+    #[test]
+    fn test_store_from_storage() {
+        let storage = Arc::new(MemoryStorage::new());
+        for start_block in [0, 100] {
+            let blocks: Vec<_> = (start_block.max(1)..start_block + 100)
+                .map(verified_block)
+                .collect();
+            let name = format!("mainnet/{}.zst", FlatFileBundle::file_name(start_block));
+            storage.insert(&name, dbin_file(&blocks, Compression::Zstd));
+        }
+        storage.insert("mainnet/old/0000000200.dbin", b"".as_slice());
+
+        let store = FlatFileStore::from_storage(storage, "mainnet")
+            .unwrap()
+            .with_read_options(RangedReadOptions {
+                chunk_size: 512,
+                prefetch_chunks: 2,
+            });
+        assert_eq!(store.bundles()[1].name(), "mainnet/0000000100.dbin.zst");
+        assert_eq!(store.covered_ranges(), vec![0..=199]);
+
+        let numbers: Vec<_> = store
+            .blocks(50..150)
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, (50..150).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,487 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use firehose_protos::{EthBlock as Block, HeaderOnlyBlock, ProtosError};
+use tracing::{debug, info};
+
+use crate::{
+    decoder::{decode_block_from_message, decode_header_from_message},
+    error::DecoderError,
+    verification::{FailureMode, VerificationOptions, VerificationOutcome},
+    DbinReader, EndBlock, Reader,
+};
+
+/// Get an iterator of decoded, verified blocks from a reader.
+///
+/// Blocks are decoded and verified one message at a time, and each verified block is yielded as
+/// soon as it is decoded. Invalid blocks are skipped. The iterator ends once the end block has
+/// been yielded, or when the reader reaches EOF at the start of a message. To wait for more data
+/// at EOF instead, use [`follow_blocks`].
+///
+/// # Arguments
+///
+/// * `reader`: A [`Reader`] enum that specifies the source of the block data. The reader can be a
+///   [`BufReader`](std::io::BufReader) or a `StdIn` reader with or without compression.
+/// * `end_block`: Specifies the block number at which to stop streaming. By default, this is set to
+///   block 15537393, the last block before the Ethereum merge.
+pub fn stream_blocks(reader: Reader, end_block: EndBlock) -> Result<BlockStream, DecoderError> {
+    BlockStream::new(reader.into_reader()?, end_block)
+}
+
+/// Get an iterator of decoded, verified blocks from a reader that is still being written to.
+///
+/// Like [`stream_blocks`], but when the reader reaches EOF it is polled again, with the delay
+/// between polls backing off as set by the [`FollowOptions`], until more data arrives. The
+/// iterator ends once the end block has been yielded, once no data has arrived for
+/// [`FollowOptions::idle_timeout`], or once [`FollowOptions::cancellation`] is cancelled.
+///
+/// Cancelling or timing out while a message is only partially written yields an
+/// [`io::ErrorKind::UnexpectedEof`] error.
+pub fn follow_blocks(
+    reader: Reader,
+    end_block: EndBlock,
+    options: FollowOptions,
+) -> Result<BlockStream, DecoderError> {
+    BlockStream::new(reader.into_following_reader(Some(options))?, end_block)
+}
+
+/// Get an iterator of block headers from a reader, without decoding the rest of the blocks.
+///
+/// Like [`stream_blocks`], but each block is decoded as a [`HeaderOnlyBlock`], skipping over its
+/// transaction traces, as with [`decode_headers_from_reader`](crate::decode_headers_from_reader).
+/// Without the traces, blocks are not verified.
+pub fn stream_headers(reader: Reader, end_block: EndBlock) -> Result<HeaderStream, DecoderError> {
+    HeaderStream::new(reader.into_reader()?, end_block)
+}
+
+/// Get an iterator of block headers from a reader that is still being written to.
+///
+/// Like [`stream_headers`], but waits for more data at EOF as [`follow_blocks`] does.
+pub fn follow_headers(
+    reader: Reader,
+    end_block: EndBlock,
+    options: FollowOptions,
+) -> Result<HeaderStream, DecoderError> {
+    HeaderStream::new(reader.into_following_reader(Some(options))?, end_block)
+}
+
+/// Set how [`follow_blocks`] waits for more data once it reaches EOF.
+#[derive(Clone, Debug)]
+pub struct FollowOptions {
+    /// The delay before polling again after the first read that reaches EOF.
+    pub poll_interval: Duration,
+    /// The maximum delay between polls. The delay doubles after each poll that reaches EOF, up
+    /// to this value.
+    pub max_poll_interval: Duration,
+    /// Stop following after this long without new data, or follow forever if `None`.
+    pub idle_timeout: Option<Duration>,
+    /// Stop following once cancelled. Cancellation is noticed within
+    /// [`FollowOptions::max_poll_interval`].
+    pub cancellation: CancellationHandle,
+}
+
+impl Default for FollowOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(10),
+            max_poll_interval: Duration::from_secs(1),
+            idle_timeout: None,
+            cancellation: CancellationHandle::default(),
+        }
+    }
+}
+
+/// Handle to stop a [`follow_blocks`] iterator from another thread.
+///
+/// Clones share the same state, so any clone can cancel the iterator.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationHandle(Arc<AtomicBool>);
+
+impl CancellationHandle {
+    /// Cancel the iterators following with this handle.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether this handle has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Iterator of blocks decoded and verified by [`stream_blocks`] or [`follow_blocks`].
+///
+/// By default, blocks are verified with every check and blocks that fail verification are
+/// skipped. Use [`BlockStream::with_verification`] to change this, and
+/// [`BlockStream::with_outcomes`] to get the verification outcome of each block.
+pub struct BlockStream {
+    messages: DbinReader<Box<dyn Read>>,
+    end_block: u64,
+    verification: VerificationOptions,
+    done: bool,
+}
+
+impl BlockStream {
+    fn new(read: Box<dyn Read>, end_block: EndBlock) -> Result<Self, DecoderError> {
+        Ok(Self {
+            messages: DbinReader::new(read)?,
+            end_block: end_block.block_number(),
+            verification: VerificationOptions {
+                failure_mode: FailureMode::Skip,
+                ..Default::default()
+            },
+            done: false,
+        })
+    }
+
+    /// Set how the blocks of the stream are verified.
+    ///
+    /// With [`FailureMode::FailFast`], the stream yields a [`DecoderError::VerificationFailed`]
+    /// error and ends at the first block that fails verification.
+    pub fn with_verification(mut self, options: VerificationOptions) -> Self {
+        self.verification = options;
+        self
+    }
+
+    /// Yield the verification outcome of each block along with it.
+    ///
+    /// With [`FailureMode::Collect`], this tells the blocks that failed verification apart from
+    /// the verified ones.
+    pub fn with_outcomes(self) -> BlockOutcomeStream {
+        BlockOutcomeStream(self)
+    }
+
+    fn next_with_outcome(&mut self) -> Option<Result<(Block, VerificationOutcome), DecoderError>> {
+        while !self.done {
+            let block = match self.messages.next() {
+                Some(Ok(message)) => decode_block_from_message(self.messages.header(), &message),
+                Some(Err(e)) => Err(e),
+                None => break,
+            };
+
+            match block.and_then(|block| self.verification.apply(block)) {
+                Ok((Some(block), outcome)) => {
+                    self.done = block.number >= self.end_block;
+                    return Some(Ok((block, outcome)));
+                }
+                Ok((None, outcome)) => {
+                    self.done = outcome.block_number >= self.end_block;
+                    info!(
+                        "Block verification failed, skipping block {}",
+                        outcome.block_number
+                    );
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+impl Iterator for BlockStream {
+    type Item = Result<Block, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_outcome()
+            .map(|result| result.map(|(block, _)| block))
+    }
+}
+
+/// Iterator of blocks with their verification outcomes, from [`BlockStream::with_outcomes`].
+pub struct BlockOutcomeStream(BlockStream);
+
+impl Iterator for BlockOutcomeStream {
+    type Item = Result<(Block, VerificationOutcome), DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_with_outcome()
+    }
+}
+
+/// Iterator of block headers decoded by [`stream_headers`] or [`follow_headers`].
+pub struct HeaderStream {
+    messages: DbinReader<Box<dyn Read>>,
+    end_block: u64,
+    done: bool,
+}
+
+impl HeaderStream {
+    fn new(read: Box<dyn Read>, end_block: EndBlock) -> Result<Self, DecoderError> {
+        Ok(Self {
+            messages: DbinReader::new(read)?,
+            end_block: end_block.block_number(),
+            done: false,
+        })
+    }
+}
+
+impl Iterator for HeaderStream {
+    type Item = Result<HeaderOnlyBlock, DecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let block = match self.messages.next()? {
+            Ok(message) => decode_header_from_message(self.messages.header(), &message),
+            Err(e) => Err(e),
+        };
+        let decoded = block.and_then(|block| match &block.header {
+            Some(header) => Ok((header.number, block)),
+            None => Err(ProtosError::BlockHeaderMissing.into()),
+        });
+
+        match decoded {
+            Ok((number, block)) => {
+                self.done = number >= self.end_block;
+                Some(Ok(block))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Wraps a source so that reads reaching EOF poll it again, as set by the [`FollowOptions`].
+///
+/// A read only returns EOF once the idle timeout elapses or the cancellation handle is cancelled.
+pub(crate) struct FollowRead<R> {
+    read: R,
+    options: FollowOptions,
+}
+
+impl<R> FollowRead<R> {
+    pub(crate) fn new(read: R, options: FollowOptions) -> Self {
+        Self { read, options }
+    }
+}
+
+impl<R: Read> Read for FollowRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let started = Instant::now();
+        let mut delay = self.options.poll_interval;
+
+        loop {
+            if self.options.cancellation.is_cancelled() {
+                return Ok(0);
+            }
+
+            let bytes_read = self.read.read(buf)?;
+            if bytes_read > 0 {
+                return Ok(bytes_read);
+            }
+
+            let idle = started.elapsed();
+            if let Some(timeout) = self.options.idle_timeout {
+                if idle >= timeout {
+                    info!("No new data for {timeout:?}, stopping");
+                    return Ok(0);
+                }
+                delay = delay.min(timeout - idle);
+            }
+
+            debug!("Reached end of input, waiting {delay:?} for more data");
+            thread::sleep(delay);
+            delay = (delay * 2).min(self.options.max_poll_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        io::{BufReader, Cursor, Seek, Write},
+    };
+
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression,
+    };
+
+    use super::*;
+
+    fn buf_reader(data: Vec<u8>) -> Reader {
+        Reader::Buf(BufReader::new(Cursor::new(data)))
+    }
+
+    fn follow_options() -> FollowOptions {
+        FollowOptions {
+            poll_interval: Duration::from_millis(1),
+            max_poll_interval: Duration::from_millis(5),
+            idle_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        }
+    }
+
+    /// Source that reaches EOF between each of its chunks, like a file still being written to.
+    struct ChunkedRead(VecDeque<Option<Vec<u8>>>);
+
+    impl Read for ChunkedRead {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                Some(Some(chunk)) => {
+                    let len = chunk.len().min(buf.len());
+                    buf[..len].copy_from_slice(&chunk[..len]);
+                    if len < chunk.len() {
+                        self.0.push_front(Some(chunk[len..].to_vec()));
+                    }
+                    Ok(len)
+                }
+                Some(None) | None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_blocks_stops_at_end_block() {
+        let data = dbin_file(&[1, 2, 3, 4].map(verified_block), Compression::None);
+
+        let numbers: Vec<_> = stream_blocks(buf_reader(data), EndBlock::Block(2))
+            .unwrap()
+            .map(|block| block.unwrap().number)
+            .collect();
+
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_stream_headers_stops_at_end_block() {
+        let data = dbin_file(&[1, 2, 3, 4].map(verified_block), Compression::None);
+
+        let numbers: Vec<_> = stream_headers(buf_reader(data), EndBlock::Block(3))
+            .unwrap()
+            .map(|block| block.unwrap().header.unwrap().number)
+            .collect();
+
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_stream_blocks_verification() {
+        let mut tampered = verified_block(2);
+        tampered.header.as_mut().unwrap().receipt_root = vec![0; 32];
+        let data = dbin_file(
+            &[verified_block(1), tampered, verified_block(3)],
+            Compression::None,
+        );
+
+        let numbers: Vec<_> = stream_blocks(buf_reader(data.clone()), EndBlock::Block(3))
+            .unwrap()
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, vec![1, 3]);
+
+        let results: Vec<_> = stream_blocks(buf_reader(data.clone()), EndBlock::Block(3))
+            .unwrap()
+            .with_verification(VerificationOptions::default())
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(DecoderError::VerificationFailed { block_number: 2 })
+        ));
+
+        let collected: Vec<_> = stream_blocks(buf_reader(data), EndBlock::Block(3))
+            .unwrap()
+            .with_verification(VerificationOptions {
+                failure_mode: FailureMode::Collect,
+                ..Default::default()
+            })
+            .with_outcomes()
+            .map(|result| {
+                let (block, outcome) = result.unwrap();
+                (block.number, outcome.is_verified())
+            })
+            .collect();
+        assert_eq!(collected, vec![(1, true), (2, false), (3, true)]);
+    }
+
+    #[test]
+    fn test_stream_blocks_from_zstd_file() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&dbin_file(&[1, 2].map(verified_block), Compression::Zstd))
+            .unwrap();
+        file.rewind().unwrap();
+
+        let reader = Reader::File(file, Compression::Auto.into());
+        let numbers: Vec<_> = stream_blocks(reader, EndBlock::Block(2))
+            .unwrap()
+            .map(|block| block.unwrap().number)
+            .collect();
+
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_stream_blocks_truncated() {
+        let data = dbin_file(&[1, 2].map(verified_block), Compression::None);
+
+        let mut blocks = stream_blocks(
+            buf_reader(data[..data.len() - 1].to_vec()),
+            EndBlock::Block(2),
+        )
+        .unwrap();
+
+        assert_eq!(blocks.next().unwrap().unwrap().number, 1);
+        assert!(blocks.next().unwrap().is_err());
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn test_follow_read_resumes_partial_message() {
+        let data = dbin_file(&[1, 2].map(verified_block), Compression::None);
+        let (head, tail) = data.split_at(data.len() / 2 + 3);
+        let source = ChunkedRead(VecDeque::from([
+            Some(head.to_vec()),
+            None,
+            None,
+            Some(tail.to_vec()),
+        ]));
+
+        let read: Box<dyn Read> = Box::new(FollowRead::new(source, follow_options()));
+        let numbers: Vec<_> = BlockStream::new(read, EndBlock::Block(10))
+            .unwrap()
+            .map(|block| block.unwrap().number)
+            .collect();
+
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_follow_blocks_cancelled() {
+        let data = dbin_file(&[1].map(verified_block), Compression::None);
+        let options = FollowOptions {
+            idle_timeout: None,
+            ..follow_options()
+        };
+        let cancellation = options.cancellation.clone();
+
+        let mut blocks = follow_blocks(buf_reader(data), EndBlock::Block(10), options).unwrap();
+        assert_eq!(blocks.next().unwrap().unwrap().number, 1);
+
+        cancellation.cancel();
+        assert!(blocks.next().is_none());
+    }
+}
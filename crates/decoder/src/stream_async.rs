@@ -0,0 +1,352 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use async_compression::{
+    tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder},
+    zstd::DParameter,
+};
+use firehose_protos::EthBlock as Block;
+use futures::{stream, Stream, TryStreamExt};
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, DuplexStream, ReadBuf,
+    },
+    task::JoinHandle,
+};
+use tokio_util::io::SyncIoBridge;
+use tracing::info;
+
+use crate::{
+    dbin::{read_header, DbinHeader, Prefix, HEADER_SIZE, PREFIX_SIZE},
+    decoder::{block_is_verified, decode_block_from_message},
+    error::DecoderError,
+    Compression, DecompressionOptions,
+};
+
+/// Get an asynchronous stream of decoded, verified blocks from an [`AsyncRead`] source.
+///
+/// This is the asynchronous counterpart of [`stream_blocks`](crate::stream_blocks): invalid
+/// blocks are skipped, and the stream ends when the source reaches EOF at the start of a
+/// message. Messages are only read when the stream is polled, so a slow consumer applies
+/// backpressure all the way to the source. Decoding and verification are CPU-bound and run on
+/// the blocking thread pool of the Tokio runtime.
+///
+/// # Arguments
+///
+/// * `reader`: The source of the flat file contents, implementing [`AsyncRead`].
+/// * `compression`: The compression applied to the source, decompressed on the fly.
+pub fn stream_blocks_async<R>(
+    reader: R,
+    compression: impl Into<DecompressionOptions>,
+) -> impl Stream<Item = Result<Block, DecoderError>> + Send
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    stream::once(decompress(BufReader::new(reader), compression.into()))
+        .map_ok(|read| {
+            let reader = AsyncDbinReader { read, header: None };
+            stream::try_unfold(reader, next_block)
+        })
+        .try_flatten()
+}
+
+/// Wraps a source in a streaming decoder for the given compression, detecting it if
+/// [`Compression::Auto`].
+async fn decompress<R>(
+    mut read: R,
+    options: DecompressionOptions,
+) -> Result<Box<dyn AsyncRead + Unpin + Send>, DecoderError>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    let compression = match options.compression {
+        Compression::Auto => Compression::detect(read.fill_buf().await?),
+        compression => compression,
+    };
+
+    Ok(match compression {
+        Compression::Zstd => {
+            let params = [DParameter::window_log_max(options.zstd_window_log_max)];
+            let mut decoder = ZstdDecoder::with_params(read, &params);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(read);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Bzip2 => {
+            let mut decoder = BzDecoder::new(read);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Xz => {
+            let mut decoder = XzDecoder::new(read);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Lz4 => Box::new(Lz4Decoder::new(read)),
+        Compression::Auto | Compression::None => Box::new(read),
+    })
+}
+
+/// Size of the pipe between the blocking lz4 decoder and the stream, in bytes.
+const LZ4_PIPE_SIZE: usize = 64 * 1024;
+
+/// Decompresses lz4 frames on the blocking thread pool, since no asynchronous lz4 decoder is
+/// available, and pipes the decompressed bytes back to the stream.
+///
+/// The pipe is bounded, so the decoder only runs ahead of the stream by [`LZ4_PIPE_SIZE`] bytes.
+/// An error of the decoder is returned once the pipe reaches EOF.
+struct Lz4Decoder {
+    read: DuplexStream,
+    decoder: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Lz4Decoder {
+    fn new<R>(read: R) -> Self
+    where
+        R: AsyncBufRead + Unpin + Send + 'static,
+    {
+        let (write, pipe) = tokio::io::duplex(LZ4_PIPE_SIZE);
+        let mut decompressed = lz4_flex::frame::FrameDecoder::new(SyncIoBridge::new(read));
+        let mut write = SyncIoBridge::new(write);
+        let decoder = tokio::task::spawn_blocking(move || {
+            io::copy(&mut decompressed, &mut write)?;
+            write.shutdown()
+        });
+
+        Self {
+            read: pipe,
+            decoder: Some(decoder),
+        }
+    }
+}
+
+impl AsyncRead for Lz4Decoder {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.read).poll_read(cx, buf))?;
+        if buf.filled().len() > filled || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // The pipe reached EOF, so the decoder is done, successfully or not
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(Pin::new(decoder).poll(cx));
+        self.decoder = None;
+        Poll::Ready(result.map_err(io::Error::other).and_then(|result| result))
+    }
+}
+
+/// Reads, decodes and verifies messages until a verified block is found.
+async fn next_block(
+    mut reader: AsyncDbinReader,
+) -> Result<Option<(Block, AsyncDbinReader)>, DecoderError> {
+    while let Some(message) = reader.next_message().await? {
+        let header = reader.header.clone().ok_or(DecoderError::HeaderInvalid)?;
+        let block =
+            tokio::task::spawn_blocking(move || decode_verified_block(&header, &message)).await??;
+        if let Some(block) = block {
+            return Ok(Some((block, reader)));
+        }
+    }
+    Ok(None)
+}
+
+/// Decodes a block, returning `None` if it fails verification.
+fn decode_verified_block(
+    header: &DbinHeader,
+    message: &[u8],
+) -> Result<Option<Block>, DecoderError> {
+    let block = decode_block_from_message(header, message)?;
+    if block_is_verified(&block) {
+        Ok(Some(block))
+    } else {
+        info!("Block verification failed, skipping block {}", block.number);
+        Ok(None)
+    }
+}
+
+/// Reads `.dbin` messages from an [`AsyncRead`] source, like [`DbinReader`](crate::DbinReader).
+struct AsyncDbinReader {
+    read: Box<dyn AsyncRead + Unpin + Send>,
+    header: Option<DbinHeader>,
+}
+
+impl AsyncDbinReader {
+    /// Reads the next message, returning `None` if EOF is reached at the start of a message.
+    async fn next_message(&mut self) -> Result<Option<Vec<u8>>, DecoderError> {
+        if self.header.is_none() {
+            let mut bytes = [0; HEADER_SIZE];
+            self.read.read_exact(&mut bytes).await?;
+            let header = DbinHeader::try_from_read(&mut bytes.as_slice())?;
+            header.check_eth()?;
+            self.header = Some(header);
+        }
+
+        loop {
+            let mut prefix = [0; PREFIX_SIZE];
+            match self.read.read_exact(&mut prefix).await {
+                Ok(_) => {}
+                // Stop gracefully if EOF is reached at the start of a new message.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+
+            match Prefix::from(prefix) {
+                Prefix::Header => {
+                    let mut bytes = [0; HEADER_SIZE - PREFIX_SIZE];
+                    self.read.read_exact(&mut bytes).await?;
+                    let header = read_header(&mut bytes.as_slice())?;
+                    header.check_eth()?;
+                    self.header = Some(header);
+                }
+                Prefix::Message(message_length) => {
+                    let mut message = vec![0; message_length];
+                    self.read.read_exact(&mut message).await?;
+                    return Ok(Some(message));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        test_utils::{block_message, dbin_file, verified_block},
+        DbinWriter,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_blocks_async() {
+        let data = dbin_file(&[1, 2, 3].map(verified_block), Compression::None);
+
+        let blocks: Vec<_> = stream_blocks_async(Cursor::new(data), Compression::None)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let numbers: Vec<_> = blocks.iter().map(|block| block.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_async_zstd_concatenated() {
+        let mut data = dbin_file(&[1, 2].map(verified_block), Compression::Zstd);
+        data.extend(dbin_file(&[3].map(verified_block), Compression::Zstd));
+
+        let blocks: Vec<_> = stream_blocks_async(Cursor::new(data), Compression::Zstd)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let numbers: Vec<_> = blocks.iter().map(|block| block.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_async_auto_gzip() {
+        let data = dbin_file(&[1, 2].map(verified_block), Compression::Gzip);
+
+        let blocks: Vec<_> = stream_blocks_async(Cursor::new(data), Compression::Auto)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let numbers: Vec<_> = blocks.iter().map(|block| block.number).collect();
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_async_lz4() {
+        let data = dbin_file(&[1, 2, 3].map(verified_block), Compression::Lz4);
+
+        for compression in [Compression::Lz4, Compression::Auto] {
+            let blocks: Vec<_> = stream_blocks_async(Cursor::new(data.clone()), compression)
+                .try_collect()
+                .await
+                .unwrap();
+
+            let numbers: Vec<_> = blocks.iter().map(|block| block.number).collect();
+            assert_eq!(numbers, vec![1, 2, 3]);
+        }
+
+        // Errors of the blocking decoder, here on a corrupted end mark, end the stream
+        let mut corrupted = data;
+        let end = corrupted.len();
+        corrupted[end - 4..].fill(0xff);
+        let result: Result<Vec<_>, _> =
+            stream_blocks_async(Cursor::new(corrupted), Compression::Lz4)
+                .try_collect()
+                .await;
+        assert!(matches!(result, Err(DecoderError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_async_truncated() {
+        let data = dbin_file(&[1, 2].map(verified_block), Compression::None);
+
+        let results: Vec<_> = futures::StreamExt::collect(stream_blocks_async(
+            Cursor::new(data[..data.len() - 1].to_vec()),
+            Compression::None,
+        ))
+        .await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().number, 1);
+        assert!(
+            matches!(results[1], Err(DecoderError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_async_content_type() {
+        let mut writer = DbinWriter::new(vec![], "EOS", "01", Compression::None).unwrap();
+        writer
+            .write_message(&block_message(&verified_block(2)))
+            .unwrap();
+        let eos = writer.finish().unwrap();
+
+        let result: Result<Vec<_>, _> =
+            stream_blocks_async(Cursor::new(eos.clone()), Compression::None)
+                .try_collect()
+                .await;
+        assert!(matches!(
+            result,
+            Err(DecoderError::ContentTypeInvalid(ref content_type)) if content_type == "EOS"
+        ));
+
+        // The header of each concatenated file is checked too
+        let mut data = dbin_file(&[verified_block(1)], Compression::None);
+        data.extend(eos);
+        let results: Vec<_> =
+            futures::StreamExt::collect(stream_blocks_async(Cursor::new(data), Compression::None))
+                .await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().number, 1);
+        assert!(matches!(
+            results[1],
+            Err(DecoderError::ContentTypeInvalid(_))
+        ));
+    }
+}
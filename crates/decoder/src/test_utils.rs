@@ -0,0 +1,45 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use alloy_primitives::hex;
+use firehose_protos::{BlockHeader, BstreamBlock, EthBlock as Block};
+use prost::Message;
+
+use crate::{Compression, DbinWriter};
+
+/// Root of an empty trie, the receipt and transaction root of a block without transactions.
+const EMPTY_ROOT: &str = "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+
+/// Creates a block without transactions that passes verification.
+pub(crate) fn verified_block(number: u64) -> Block {
+    Block {
+        number,
+        hash: number.to_be_bytes().to_vec(),
+        header: Some(BlockHeader {
+            number,
+            receipt_root: hex::decode(EMPTY_ROOT).unwrap(),
+            transactions_root: hex::decode(EMPTY_ROOT).unwrap(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Encodes a block as a `.dbin` message, wrapped in a [`BstreamBlock`].
+pub(crate) fn block_message(block: &Block) -> Vec<u8> {
+    BstreamBlock {
+        number: block.number,
+        payload_buffer: block.encode_to_vec(),
+        ..Default::default()
+    }
+    .encode_to_vec()
+}
+
+/// Writes blocks as the messages of a `.dbin` file with the given compression.
+pub(crate) fn dbin_file(blocks: &[Block], compression: Compression) -> Vec<u8> {
+    let mut writer = DbinWriter::new(vec![], "ETH", "01", compression).unwrap();
+    for block in blocks {
+        writer.write_message(&block_message(block)).unwrap();
+    }
+    writer.finish().unwrap()
+}
@@ -0,0 +1,305 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{collections::HashSet, io::Read};
+
+use alloy_primitives::B256;
+use firehose_protos::EthBlock as Block;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{decoder::decode_blocks_from_reader, error::DecoderError, DecompressionOptions};
+
+/// A check performed when verifying a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VerificationCheck {
+    /// The receipt root computed from the transaction traces matches the block header.
+    ReceiptRoot,
+    /// The transaction root computed from the transaction traces matches the block header.
+    TransactionRoot,
+}
+
+/// Set what happens to a block that fails verification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Stop at the first block that fails verification, with
+    /// [`DecoderError::VerificationFailed`].
+    #[default]
+    FailFast,
+    /// Drop blocks that fail verification, and carry on with the next block.
+    Skip,
+    /// Keep blocks that fail verification, so that their outcomes can be inspected.
+    Collect,
+}
+
+/// Set which checks run when verifying blocks, and how failures are handled.
+#[derive(Clone, Debug)]
+pub struct VerificationOptions {
+    /// The checks to run on each block that is not exempt.
+    pub checks: Vec<VerificationCheck>,
+    /// What to do with blocks that fail any of the checks.
+    pub failure_mode: FailureMode,
+    /// Block numbers that are not checked, and always pass verification.
+    pub exempt_blocks: HashSet<u64>,
+}
+
+impl Default for VerificationOptions {
+    /// Run every check, fail fast, and exempt the genesis block, whose roots cannot be computed
+    /// from transaction traces.
+    fn default() -> Self {
+        Self {
+            checks: vec![
+                VerificationCheck::ReceiptRoot,
+                VerificationCheck::TransactionRoot,
+            ],
+            failure_mode: FailureMode::default(),
+            exempt_blocks: HashSet::from([0]),
+        }
+    }
+}
+
+impl VerificationOptions {
+    /// Run the checks on a block, returning the outcome of each of them.
+    pub fn verify(&self, block: &Block) -> VerificationOutcome {
+        let exempt = self.exempt_blocks.contains(&block.number);
+        let checks = if exempt {
+            Vec::new()
+        } else {
+            self.checks
+                .iter()
+                .map(|&check| CheckOutcome::run(check, block))
+                .collect()
+        };
+
+        VerificationOutcome {
+            block_number: block.number,
+            exempt,
+            checks,
+        }
+    }
+
+    /// Verify a block, logging the checks it failed, and apply the [`FailureMode`] to it.
+    ///
+    /// Returns the block if it is kept, with its verification outcome, or
+    /// [`DecoderError::VerificationFailed`] if it failed verification in
+    /// [`FailureMode::FailFast`] mode.
+    pub fn apply(
+        &self,
+        block: Block,
+    ) -> Result<(Option<Block>, VerificationOutcome), DecoderError> {
+        let outcome = self.verify(&block);
+        let verified = outcome.is_verified();
+        if !verified {
+            outcome.log_failures();
+        }
+
+        match (verified, self.failure_mode) {
+            (false, FailureMode::FailFast) => Err(DecoderError::VerificationFailed {
+                block_number: block.number,
+            }),
+            (false, FailureMode::Skip) => Ok((None, outcome)),
+            _ => Ok((Some(block), outcome)),
+        }
+    }
+}
+
+/// The result of a single [`VerificationCheck`] on a block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckOutcome {
+    /// The check that was performed.
+    pub check: VerificationCheck,
+    /// Whether the computed root matched the expected root.
+    pub passed: bool,
+    /// The root computed from the block contents, if it could be computed.
+    pub computed: Option<B256>,
+    /// The root found in the block header, if it could be read.
+    pub expected: Option<B256>,
+    /// Why the check could not be completed, if it could not.
+    pub error: Option<String>,
+}
+
+impl CheckOutcome {
+    fn run(check: VerificationCheck, block: &Block) -> Self {
+        let computed = match check {
+            VerificationCheck::ReceiptRoot => block.calculate_receipt_root(),
+            VerificationCheck::TransactionRoot => block.calculate_transaction_root(),
+        }
+        .map_err(|e| e.to_string());
+        let expected = block
+            .header()
+            .map_err(|e| e.to_string())
+            .and_then(|header| {
+                let root = match check {
+                    VerificationCheck::ReceiptRoot => &header.receipt_root,
+                    VerificationCheck::TransactionRoot => &header.transactions_root,
+                };
+                B256::try_from(root.as_slice()).map_err(|e| format!("Invalid header root: {e}"))
+            });
+
+        let error = computed.as_ref().err().or(expected.as_ref().err()).cloned();
+        let computed = computed.ok();
+        let expected = expected.ok();
+
+        Self {
+            check,
+            passed: error.is_none() && computed == expected,
+            computed,
+            expected,
+            error,
+        }
+    }
+}
+
+/// The outcome of verifying a block, with evidence for every check performed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationOutcome {
+    /// The number of the verified block.
+    pub block_number: u64,
+    /// Whether the block was exempt from verification, in which case no checks were performed.
+    pub exempt: bool,
+    /// The outcome of each check performed, in the order they ran.
+    pub checks: Vec<CheckOutcome>,
+}
+
+impl VerificationOutcome {
+    /// Check whether the block passed every check performed.
+    pub fn is_verified(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// Get the checks the block failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckOutcome> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+
+    pub(crate) fn log_failures(&self) {
+        for failure in self.failures() {
+            match &failure.error {
+                Some(e) => error!(
+                    "{:?} verification failed for block {}: {e}",
+                    failure.check, self.block_number
+                ),
+                None => error!(
+                    "{:?} verification failed for block {}",
+                    failure.check, self.block_number
+                ),
+            }
+        }
+    }
+}
+
+/// Blocks read by [`read_blocks_with_verification`], with the verification outcome of every
+/// decoded block.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationReport {
+    /// The blocks kept according to the [`FailureMode`].
+    pub blocks: Vec<Block>,
+    /// The verification outcome of every decoded block, including skipped ones.
+    pub outcomes: Vec<VerificationOutcome>,
+}
+
+/// Read and verify blocks from a flat file reader, according to the given options.
+///
+/// Like [`read_blocks_from_reader`](crate::read_blocks_from_reader), but the checks, the
+/// handling of failures and the exempt blocks are set by the [`VerificationOptions`], and the
+/// outcome of each block's verification is returned alongside the blocks.
+pub fn read_blocks_with_verification<R: Read>(
+    reader: R,
+    compression: impl Into<DecompressionOptions>,
+    options: &VerificationOptions,
+) -> Result<VerificationReport, DecoderError> {
+    let mut report = VerificationReport::default();
+
+    for block in decode_blocks_from_reader(reader, compression)? {
+        let (block, outcome) = options.apply(block)?;
+        report.outcomes.push(outcome);
+        report.blocks.extend(block);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{dbin_file, verified_block},
+        Compression,
+    };
+
+    use super::*;
+
+    fn tampered_block(number: u64) -> Block {
+        let mut block = verified_block(number);
+        block.header.as_mut().unwrap().receipt_root = vec![0xff; 32];
+        block
+    }
+
+    #[test]
+    fn test_verify_outcome_evidence() {
+        let outcome = VerificationOptions::default().verify(&tampered_block(1));
+
+        assert_eq!(outcome.block_number, 1);
+        assert!(!outcome.is_verified());
+        let failures: Vec<_> = outcome.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].check, VerificationCheck::ReceiptRoot);
+        assert_eq!(failures[0].expected, Some(B256::repeat_byte(0xff)));
+        assert!(failures[0].computed.is_some());
+        assert_eq!(failures[0].error, None);
+
+        let exempt = VerificationOptions::default().verify(&tampered_block(0));
+        assert!(exempt.exempt);
+        assert!(exempt.is_verified());
+    }
+
+    #[test]
+    fn test_verify_invalid_header_root() {
+        let mut block = verified_block(1);
+        block.header.as_mut().unwrap().transactions_root = vec![0; 31];
+
+        let options = VerificationOptions {
+            checks: vec![VerificationCheck::TransactionRoot],
+            ..Default::default()
+        };
+        let outcome = options.verify(&block);
+
+        assert_eq!(outcome.checks.len(), 1);
+        assert!(!outcome.checks[0].passed);
+        assert_eq!(outcome.checks[0].expected, None);
+        assert!(outcome.checks[0].error.is_some());
+    }
+
+    #[test]
+    fn test_read_blocks_with_failure_modes() {
+        let data = dbin_file(
+            &[verified_block(1), tampered_block(2), verified_block(3)],
+            Compression::None,
+        );
+        let read = |failure_mode| {
+            let options = VerificationOptions {
+                failure_mode,
+                ..Default::default()
+            };
+            read_blocks_with_verification(data.as_slice(), Compression::None, &options)
+        };
+
+        assert!(matches!(
+            read(FailureMode::FailFast),
+            Err(DecoderError::VerificationFailed { block_number: 2 })
+        ));
+
+        let skipped = read(FailureMode::Skip).unwrap();
+        let numbers: Vec<_> = skipped.blocks.iter().map(|block| block.number).collect();
+        assert_eq!(numbers, vec![1, 3]);
+        assert_eq!(skipped.outcomes.len(), 3);
+        assert!(!skipped.outcomes[1].is_verified());
+
+        let collected = read(FailureMode::Collect).unwrap();
+        assert_eq!(collected.blocks.len(), 3);
+        assert_eq!(collected.outcomes.len(), 3);
+
+        let json = serde_json::to_value(&collected.outcomes[1]).unwrap();
+        assert_eq!(json["checks"][0]["check"], "ReceiptRoot");
+        assert_eq!(json["checks"][0]["passed"], false);
+    }
+}
@@ -4,6 +4,80 @@
 use prost_build::Config;
 use std::{env, path::PathBuf};
 
+/// `bytes` fields of `sf.ethereum.type.v2` messages, serialized as 0x-prefixed hex strings.
+const HEX_FIELDS: &[(&str, &[&str])] = &[
+    ("Block", &["hash"]),
+    (
+        "BlockHeader",
+        &[
+            "parent_hash",
+            "uncle_hash",
+            "coinbase",
+            "state_root",
+            "transactions_root",
+            "receipt_root",
+            "logs_bloom",
+            "extra_data",
+            "mix_hash",
+            "hash",
+            "withdrawals_root",
+            "parent_beacon_root",
+        ],
+    ),
+    (
+        "TransactionTrace",
+        &[
+            "to",
+            "input",
+            "v",
+            "r",
+            "s",
+            "hash",
+            "from",
+            "return_data",
+            "public_key",
+        ],
+    ),
+    ("AccessTuple", &["address"]),
+    ("TransactionReceipt", &["state_root", "logs_bloom"]),
+    ("Log", &["address", "data"]),
+    ("Call", &["caller", "address", "return_data", "input"]),
+    (
+        "StorageChange",
+        &["address", "key", "old_value", "new_value"],
+    ),
+    ("BalanceChange", &["address"]),
+    ("NonceChange", &["address"]),
+    ("AccountCreation", &["account"]),
+    (
+        "CodeChange",
+        &["address", "old_hash", "old_code", "new_hash", "new_code"],
+    ),
+    ("BlockRef", &["hash"]),
+];
+
+/// `repeated bytes` fields of `sf.ethereum.type.v2` messages, serialized as lists of
+/// 0x-prefixed hex strings.
+const HEX_LIST_FIELDS: &[(&str, &[&str])] = &[
+    ("TransactionTrace", &["blob_hashes"]),
+    ("AccessTuple", &["storage_keys"]),
+    ("Log", &["topics"]),
+    ("TransactionRefs", &["hashes"]),
+];
+
+/// Enum fields of `sf.ethereum.type.v2` messages with the Rust path of their enum, serialized
+/// by name.
+const ENUM_FIELDS: &[(&str, &str, &str)] = &[
+    ("Block", "detail_level", "block::DetailLevel"),
+    ("TransactionTrace", "type", "transaction_trace::Type"),
+    ("TransactionTrace", "status", "TransactionTraceStatus"),
+    ("Call", "call_type", "CallType"),
+    ("BalanceChange", "reason", "balance_change::Reason"),
+    ("GasChange", "reason", "gas_change::Reason"),
+];
+
+const ETHEREUM_PACKAGE: &str = ".sf.ethereum.type.v2";
+
 fn main() {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
@@ -13,6 +87,34 @@ fn main() {
     config.type_attribute(".", "#[allow(clippy::enum_variant_names)]");
     config.type_attribute(".", "#[allow(missing_docs)]");
 
+    // Serialize Ethereum types to JSON the way JSON-RPC does
+    for (message, fields) in HEX_FIELDS {
+        for field in *fields {
+            config.field_attribute(
+                format!("{ETHEREUM_PACKAGE}.{message}.{field}"),
+                r#"#[serde(with = "crate::serde_hex::bytes")]"#,
+            );
+        }
+    }
+    for (message, fields) in HEX_LIST_FIELDS {
+        for field in *fields {
+            config.field_attribute(
+                format!("{ETHEREUM_PACKAGE}.{message}.{field}"),
+                r#"#[serde(with = "crate::serde_hex::bytes_list")]"#,
+            );
+        }
+    }
+    for (message, field, enumeration) in ENUM_FIELDS {
+        config.field_attribute(
+            format!("{ETHEREUM_PACKAGE}.{message}.{field}"),
+            format!(r#"#[serde(with = "crate::serde_hex::EnumName::<{enumeration}>")]"#),
+        );
+    }
+    config.type_attribute(
+        format!("{ETHEREUM_PACKAGE}.BigInt"),
+        r#"#[serde(from = "crate::serde_hex::Quantity", into = "crate::serde_hex::Quantity")]"#,
+    );
+
     // Map Google protobuf types to prost_wkt_types
     config.extern_path(".google.protobuf.Any", "::prost_wkt_types::Any");
     config.extern_path(".google.protobuf.Timestamp", "::prost_wkt_types::Timestamp");
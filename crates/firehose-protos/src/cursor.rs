@@ -0,0 +1,93 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Opaque string representation of bstream cursors.
+
+use crate::{
+    bstream::v1::{BlockRef, Cursor, ForkStep},
+    error::ProtosError,
+};
+
+/// The version prefix of opaque cursors.
+const CURSOR_VERSION: &str = "c1";
+
+impl BlockRef {
+    /// Create a reference to a block by number and id, its hex-encoded hash.
+    pub fn new(num: u64, id: impl Into<String>) -> Self {
+        Self { num, id: id.into() }
+    }
+}
+
+impl Cursor {
+    /// Encode the cursor as an opaque string, following the
+    /// `c1:{step}:{block_num}:{block_id}:{head_num}:{head_id}:{lib_num}:{lib_id}` format of
+    /// bstream.
+    pub fn to_opaque(&self) -> String {
+        let block_ref = |block: &Option<BlockRef>| {
+            let block = block.clone().unwrap_or_default();
+            format!("{}:{}", block.num, block.id)
+        };
+        format!(
+            "{CURSOR_VERSION}:{}:{}:{}:{}",
+            self.step,
+            block_ref(&self.block),
+            block_ref(&self.head_block),
+            block_ref(&self.lib)
+        )
+    }
+
+    /// Decode a cursor from the opaque string of [`Cursor::to_opaque`].
+    pub fn from_opaque(cursor: &str) -> Result<Self, ProtosError> {
+        let invalid = || ProtosError::CursorInvalid(cursor.to_string());
+        let parts: Vec<&str> = cursor.split(':').collect();
+        let [CURSOR_VERSION, step, block_num, block_id, head_num, head_id, lib_num, lib_id] =
+            parts.as_slice()
+        else {
+            return Err(invalid());
+        };
+
+        let step = step.parse().map_err(|_| invalid())?;
+        ForkStep::try_from(step).map_err(|_| invalid())?;
+        let block_ref = |num: &str, id: &str| -> Result<_, ProtosError> {
+            let num = num.parse().map_err(|_| invalid())?;
+            Ok(Some(BlockRef::new(num, id)))
+        };
+        Ok(Self {
+            block: block_ref(block_num, block_id)?,
+            head_block: block_ref(head_num, head_id)?,
+            lib: block_ref(lib_num, lib_id)?,
+            step,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_opaque_round_trip() {
+        let cursor = Cursor {
+            block: Some(BlockRef::new(12, "0c")),
+            head_block: Some(BlockRef::new(11, "0b")),
+            lib: Some(BlockRef::new(8, "08")),
+            step: ForkStep::StepUndo as i32,
+        };
+
+        let opaque = cursor.to_opaque();
+        assert_eq!(opaque, "c1:2:12:0c:11:0b:8:08");
+        assert_eq!(Cursor::from_opaque(&opaque).unwrap(), cursor);
+
+        for invalid in [
+            "",
+            "c2:2:12:0c:11:0b:8:08",
+            "c1:3:12:0c:11:0b:8:08",
+            "c1:2:x:0c:11:0b:8:08",
+        ] {
+            assert!(matches!(
+                Cursor::from_opaque(invalid),
+                Err(ProtosError::CursorInvalid(_))
+            ));
+        }
+    }
+}
@@ -42,6 +42,10 @@ pub enum ProtosError {
     #[error("Null checkpoint")]
     CheckpointMissing,
 
+    /// Invalid opaque bstream cursor.
+    #[error("Invalid cursor: {0}")]
+    CursorInvalid(String),
+
     /// [prost] library decode error.
     #[error("Error in decoding block: {0}")]
     DecodeError(#[from] prost::DecodeError),
@@ -1,7 +1,7 @@
 // Copyright 2024-, Semiotic AI, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{Block, BlockHeader, TransactionReceipt, TransactionTrace};
+use super::{Block, BlockHeader, HeaderOnlyBlock, TransactionReceipt, TransactionTrace};
 use alloy_primitives::{hex, Address, Bloom, FixedBytes, Uint, B256};
 use alloy_rlp::{Encodable, Header as RlpHeader};
 use ethportal_api::types::execution::header::Header;
@@ -25,6 +25,27 @@ impl TryFrom<&Block> for Header {
             .as_ref()
             .ok_or(ProtosError::BlockConversionError)?;
 
+        Header::try_from(block_header)
+    }
+}
+
+impl TryFrom<&HeaderOnlyBlock> for Header {
+    type Error = ProtosError;
+
+    fn try_from(block: &HeaderOnlyBlock) -> Result<Self, Self::Error> {
+        let block_header = block
+            .header
+            .as_ref()
+            .ok_or(ProtosError::BlockConversionError)?;
+
+        Header::try_from(block_header)
+    }
+}
+
+impl TryFrom<&BlockHeader> for Header {
+    type Error = ProtosError;
+
+    fn try_from(block_header: &BlockHeader) -> Result<Self, Self::Error> {
         let parent_hash = FixedBytes::from_slice(block_header.parent_hash.as_slice());
         let uncles_hash = FixedBytes::from_slice(block_header.uncle_hash.as_slice());
         let author = Address::from_slice(block_header.coinbase.as_slice());
@@ -157,11 +178,68 @@ impl Block {
         Ok(ordered_trie_root_with_encoder(&receipts, encoder))
     }
 
-    fn calculate_transaction_root(&self) -> Result<FixedBytes<32>, ProtosError> {
+    /// Calculates the trie transaction root of the block, from its transaction traces.
+    pub fn calculate_transaction_root(&self) -> Result<FixedBytes<32>, ProtosError> {
         let transactions = self.transaction_traces_to_signed_transactions()?;
         Ok(calculate_transaction_root(&transactions))
     }
 
+    /// Returns the [RLP](https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp)
+    /// encoding of the block body, the list of its transactions and the list of its uncle headers,
+    /// as found in `eth` wire protocol messages and Era1 archives.
+    ///
+    /// Transactions are rebuilt from the transaction traces, so the body of a post-Shanghai block
+    /// is encoded without its withdrawals.
+    pub fn rlp_encoded_body(&self) -> Result<Vec<u8>, ProtosError> {
+        let transactions = self.transaction_traces_to_signed_transactions()?;
+        let uncles = self
+            .uncles
+            .iter()
+            .map(Header::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut encoded = Vec::new();
+        RlpHeader {
+            list: true,
+            payload_length: transactions.length() + uncles.length(),
+        }
+        .encode(&mut encoded);
+        transactions.encode(&mut encoded);
+        uncles.encode(&mut encoded);
+        Ok(encoded)
+    }
+
+    /// Returns the [RLP](https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp)
+    /// encoding of the list of the block receipts, as found in `eth` wire protocol messages and
+    /// Era1 archives.
+    ///
+    /// Unlike in the receipt trie, typed receipts are wrapped in an RLP string.
+    pub fn rlp_encoded_receipts(&self) -> Result<Vec<u8>, ProtosError> {
+        let pre_byzantium = self.is_pre_byzantium();
+        let receipts: Vec<Vec<u8>> = self
+            .full_receipts()?
+            .iter()
+            .map(|receipt| {
+                let mut encoded = Vec::new();
+                if pre_byzantium {
+                    receipt.encode_pre_byzantium_receipt(&mut encoded);
+                } else {
+                    receipt.receipt.encode_inner(&mut encoded, true);
+                }
+                encoded
+            })
+            .collect();
+
+        let mut encoded = Vec::new();
+        RlpHeader {
+            list: true,
+            payload_length: receipts.iter().map(Vec::len).sum(),
+        }
+        .encode(&mut encoded);
+        receipts.iter().for_each(|receipt| encoded.extend(receipt));
+        Ok(encoded)
+    }
+
     /// Converts the transaction traces of the current block into a vector of `FullReceipt` objects.
     ///
     /// # Arguments
@@ -206,7 +284,7 @@ impl Block {
         self.header.as_ref().ok_or(ProtosError::BlockHeaderMissing)
     }
 
-    fn is_pre_byzantium(&self) -> bool {
+    pub(crate) fn is_pre_byzantium(&self) -> bool {
         const BYZANTIUM_FORK_BLOCK: u64 = 4_370_000;
 
         self.number < BYZANTIUM_FORK_BLOCK
@@ -383,6 +461,14 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_rlp_encoded_empty_block() {
+        let block = Block::default();
+
+        assert_eq!(block.rlp_encoded_body().unwrap(), vec![0xc2, 0xc0, 0xc0]);
+        assert_eq!(block.rlp_encoded_receipts().unwrap(), vec![0xc0]);
+    }
+
     #[test]
     fn test_block_to_header() {
         let block_header: BlockHeader = serde_json::from_str(BLOCK).unwrap();
@@ -410,6 +496,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_header_only_block_to_header() {
+        let block = Block {
+            header: Some(serde_json::from_str(BLOCK).unwrap()),
+            ..Default::default()
+        };
+
+        // Only the header field of the encoded block is decoded.
+        let header_only_block = HeaderOnlyBlock::decode(block.encode_to_vec().as_slice()).unwrap();
+
+        assert_eq!(
+            Header::try_from(&header_only_block).unwrap(),
+            Header::try_from(&block).unwrap()
+        );
+    }
+
     static BLOCK: &str = r###"
         {
             "parent_hash":[41,204,132,204,44,220,150,185,95,11,250,60,105,128,80,38,218,105,225,93,10,199,246,153,65,41,143,174,97,80,153,227],
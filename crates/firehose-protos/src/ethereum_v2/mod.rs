@@ -8,6 +8,7 @@
 pub mod access;
 pub mod eth_block;
 pub mod log;
+pub mod rpc;
 pub mod transaction;
 
 tonic::include_proto!("sf.ethereum.r#type.v2");
@@ -0,0 +1,482 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversions of Firehose blocks into the types returned by the Ethereum JSON-RPC API.
+
+use alloy_consensus::{Eip658Value, Receipt, ReceiptEnvelope, ReceiptWithBloom};
+use alloy_eip2930::AccessList;
+use alloy_primitives::{Address, Bloom, Bytes, B256, B64, U256};
+use alloy_rpc_types_eth::{
+    Block as RpcBlock, BlockTransactions, Header as RpcHeader, Log as RpcLog, Parity,
+    Signature as RpcSignature, Transaction as RpcTransaction, TransactionReceipt,
+};
+
+use crate::error::ProtosError;
+
+use super::{
+    transaction::{get_legacy_chain_id, get_u128_or_default, CHAIN_ID},
+    transaction_trace::Type,
+    BigInt, Block, CallType, Log, TransactionTrace,
+};
+
+impl Block {
+    /// Returns the block as returned by `eth_getBlockByNumber` and `eth_getBlockByHash`, with
+    /// full transaction objects if `full_transactions` is set, or transaction hashes otherwise.
+    ///
+    /// Firehose blocks do not carry withdrawals, so `withdrawals` is always `None`.
+    pub fn rpc_block(&self, full_transactions: bool) -> Result<RpcBlock, ProtosError> {
+        let transactions = match full_transactions {
+            true => BlockTransactions::Full(self.rpc_transactions()?),
+            false => BlockTransactions::Hashes(
+                self.transaction_traces
+                    .iter()
+                    .map(|trace| B256::from_slice(&trace.hash))
+                    .collect(),
+            ),
+        };
+
+        Ok(RpcBlock {
+            header: self.rpc_header()?,
+            uncles: self
+                .uncles
+                .iter()
+                .map(|uncle| B256::from_slice(&uncle.hash))
+                .collect(),
+            transactions,
+            size: Some(U256::from(self.size)),
+            withdrawals: None,
+        })
+    }
+
+    /// Returns the transactions of the block as returned by `eth_getTransactionByHash`, in
+    /// block order.
+    pub fn rpc_transactions(&self) -> Result<Vec<RpcTransaction>, ProtosError> {
+        self.transaction_traces
+            .iter()
+            .map(|trace| self.rpc_transaction_of(trace))
+            .collect()
+    }
+
+    /// Returns the transaction with the given hash as returned by `eth_getTransactionByHash`,
+    /// or `None` if it is not in the block.
+    pub fn rpc_transaction(&self, hash: &B256) -> Result<Option<RpcTransaction>, ProtosError> {
+        self.transaction_trace(hash)
+            .map(|trace| self.rpc_transaction_of(trace))
+            .transpose()
+    }
+
+    /// Returns the receipts of the block as returned by `eth_getBlockReceipts`, in block order.
+    pub fn rpc_receipts(&self) -> Result<Vec<TransactionReceipt>, ProtosError> {
+        self.transaction_traces
+            .iter()
+            .map(|trace| self.rpc_receipt_of(trace))
+            .collect()
+    }
+
+    /// Returns the receipt of the transaction with the given hash as returned by
+    /// `eth_getTransactionReceipt`, or `None` if it is not in the block.
+    pub fn rpc_receipt(&self, hash: &B256) -> Result<Option<TransactionReceipt>, ProtosError> {
+        self.transaction_trace(hash)
+            .map(|trace| self.rpc_receipt_of(trace))
+            .transpose()
+    }
+
+    /// Returns the logs of the block as returned by `eth_getLogs`, ordered by log index.
+    pub fn rpc_logs(&self) -> Result<Vec<RpcLog>, ProtosError> {
+        let mut logs = Vec::new();
+        for trace in &self.transaction_traces {
+            for log in &trace.receipt()?.logs {
+                logs.push(self.rpc_log(trace, log)?);
+            }
+        }
+        Ok(logs)
+    }
+
+    fn rpc_header(&self) -> Result<RpcHeader, ProtosError> {
+        let header = self.header()?;
+
+        Ok(RpcHeader {
+            hash: B256::from_slice(&self.hash),
+            parent_hash: B256::from_slice(&header.parent_hash),
+            uncles_hash: B256::from_slice(&header.uncle_hash),
+            miner: Address::from_slice(&header.coinbase),
+            state_root: B256::from_slice(&header.state_root),
+            transactions_root: B256::from_slice(&header.transactions_root),
+            receipts_root: B256::from_slice(&header.receipt_root),
+            logs_bloom: Bloom::from_slice(&header.logs_bloom),
+            difficulty: header
+                .difficulty
+                .as_ref()
+                .map(U256::from)
+                .unwrap_or_default(),
+            number: header.number,
+            gas_limit: header.gas_limit.into(),
+            gas_used: header.gas_used.into(),
+            timestamp: self.timestamp()?,
+            total_difficulty: header.total_difficulty.as_ref().map(U256::from),
+            extra_data: Bytes::copy_from_slice(&header.extra_data),
+            mix_hash: Some(B256::from_slice(&header.mix_hash)),
+            nonce: Some(B64::from(header.nonce.to_be_bytes())),
+            base_fee_per_gas: header
+                .base_fee_per_gas
+                .as_ref()
+                .map(u128::try_from)
+                .transpose()?,
+            withdrawals_root: non_empty(&header.withdrawals_root).map(B256::from_slice),
+            blob_gas_used: header.blob_gas_used.map(Into::into),
+            excess_blob_gas: header.excess_blob_gas.map(Into::into),
+            parent_beacon_block_root: non_empty(&header.parent_beacon_root).map(B256::from_slice),
+            requests_root: None,
+        })
+    }
+
+    fn rpc_transaction_of(&self, trace: &TransactionTrace) -> Result<RpcTransaction, ProtosError> {
+        let tx_type = trace_type(trace)?;
+        let is_legacy = tx_type == Type::TrxTypeLegacy;
+        let is_dynamic_fee = matches!(tx_type, Type::TrxTypeDynamicFee | Type::TrxTypeBlob);
+        let is_blob = tx_type == Type::TrxTypeBlob;
+
+        let signature = RpcSignature {
+            r: U256::from_be_slice(&trace.r),
+            s: U256::from_be_slice(&trace.s),
+            v: U256::from_be_slice(&trace.v),
+            y_parity: match is_legacy {
+                true => None,
+                false => Some(Parity(trace.parity()?.y_parity())),
+            },
+        };
+
+        Ok(RpcTransaction {
+            hash: B256::from_slice(&trace.hash),
+            nonce: trace.nonce,
+            block_hash: Some(B256::from_slice(&self.hash)),
+            block_number: Some(self.number),
+            transaction_index: Some(trace.index.into()),
+            from: Address::from_slice(&trace.from),
+            to: match trace.is_create() {
+                true => None,
+                false => Some(Address::from_slice(&trace.to)),
+            },
+            value: trace.value.as_ref().map(U256::from).unwrap_or_default(),
+            gas_price: Some(trace.effective_gas_price()?),
+            gas: trace.gas_limit.into(),
+            max_fee_per_gas: is_dynamic_fee
+                .then(|| get_u128_or_default(&trace.max_fee_per_gas))
+                .transpose()?,
+            max_priority_fee_per_gas: is_dynamic_fee
+                .then(|| get_u128_or_default(&trace.max_priority_fee_per_gas))
+                .transpose()?,
+            max_fee_per_blob_gas: is_blob
+                .then(|| get_u128_or_default(&trace.blob_gas_fee_cap))
+                .transpose()?,
+            input: Bytes::copy_from_slice(&trace.input),
+            signature: Some(signature),
+            chain_id: match is_legacy {
+                true => get_legacy_chain_id(trace),
+                false => Some(CHAIN_ID),
+            },
+            blob_versioned_hashes: is_blob.then(|| {
+                trace
+                    .blob_hashes
+                    .iter()
+                    .map(|hash| B256::from_slice(hash))
+                    .collect()
+            }),
+            access_list: (!is_legacy)
+                .then(|| AccessList::try_from(trace))
+                .transpose()?,
+            transaction_type: Some(tx_type as u8),
+            authorization_list: None,
+        })
+    }
+
+    fn rpc_receipt_of(&self, trace: &TransactionTrace) -> Result<TransactionReceipt, ProtosError> {
+        let trace_receipt = trace.receipt()?;
+        let status = match self.is_pre_byzantium() {
+            true => Eip658Value::PostState(B256::from_slice(&trace_receipt.state_root)),
+            false => Eip658Value::Eip658(trace.is_success()),
+        };
+        let logs = trace_receipt
+            .logs
+            .iter()
+            .map(|log| self.rpc_log(trace, log))
+            .collect::<Result<Vec<_>, _>>()?;
+        let receipt = ReceiptWithBloom {
+            receipt: Receipt {
+                status,
+                cumulative_gas_used: trace_receipt.cumulative_gas_used.into(),
+                logs,
+            },
+            logs_bloom: Bloom::try_from(trace_receipt)?,
+        };
+
+        let inner = match trace_type(trace)? {
+            Type::TrxTypeLegacy => ReceiptEnvelope::Legacy(receipt),
+            Type::TrxTypeAccessList => ReceiptEnvelope::Eip2930(receipt),
+            Type::TrxTypeDynamicFee => ReceiptEnvelope::Eip1559(receipt),
+            Type::TrxTypeBlob => ReceiptEnvelope::Eip4844(receipt),
+            tx_type => {
+                return Err(ProtosError::TxTypeConversion(
+                    tx_type.as_str_name().to_string(),
+                ))
+            }
+        };
+
+        Ok(TransactionReceipt {
+            inner,
+            transaction_hash: B256::from_slice(&trace.hash),
+            transaction_index: Some(trace.index.into()),
+            block_hash: Some(B256::from_slice(&self.hash)),
+            block_number: Some(self.number),
+            gas_used: trace.gas_used.into(),
+            effective_gas_price: trace.effective_gas_price()?,
+            blob_gas_used: trace_receipt.blob_gas_used.map(Into::into),
+            blob_gas_price: trace_receipt
+                .blob_gas_price
+                .as_ref()
+                .map(u128::try_from)
+                .transpose()?,
+            from: Address::from_slice(&trace.from),
+            to: match trace.is_create() {
+                true => None,
+                false => Some(Address::from_slice(&trace.to)),
+            },
+            contract_address: trace.created_address(),
+            authorization_list: None,
+        })
+    }
+
+    fn rpc_log(&self, trace: &TransactionTrace, log: &Log) -> Result<RpcLog, ProtosError> {
+        Ok(RpcLog {
+            inner: alloy_primitives::Log::try_from(log)?,
+            block_hash: Some(B256::from_slice(&self.hash)),
+            block_number: Some(self.number),
+            block_timestamp: Some(self.timestamp()?),
+            transaction_hash: Some(B256::from_slice(&trace.hash)),
+            transaction_index: Some(trace.index.into()),
+            log_index: Some(log.block_index.into()),
+            removed: false,
+        })
+    }
+
+    fn timestamp(&self) -> Result<u64, ProtosError> {
+        self.header()?
+            .timestamp
+            .as_ref()
+            .map(|timestamp| timestamp.seconds as u64)
+            .ok_or(ProtosError::BlockConversionError)
+    }
+
+    fn transaction_trace(&self, hash: &B256) -> Option<&TransactionTrace> {
+        self.transaction_traces
+            .iter()
+            .find(|trace| trace.hash.as_slice() == hash.as_slice())
+    }
+}
+
+impl TransactionTrace {
+    /// The gas price paid, which for dynamic fee transactions is the base fee of the block plus
+    /// the priority fee paid.
+    fn effective_gas_price(&self) -> Result<u128, ProtosError> {
+        get_u128_or_default(&self.gas_price)
+    }
+
+    /// Whether the transaction deploys a contract. Without calls, as in blocks of the `BASE`
+    /// detail level, a transaction without recipient is a contract creation.
+    fn is_create(&self) -> bool {
+        match self.calls.first() {
+            Some(call) => call.call_type() == CallType::Create,
+            None => self.to.is_empty(),
+        }
+    }
+
+    /// The address of the contract deployed by the transaction, if any.
+    fn created_address(&self) -> Option<Address> {
+        if !self.is_create() {
+            return None;
+        }
+        match self.calls.first() {
+            Some(call) => Some(Address::from_slice(&call.address)),
+            None => non_empty(&self.to).map(Address::from_slice),
+        }
+    }
+}
+
+impl From<&BigInt> for U256 {
+    fn from(value: &BigInt) -> Self {
+        U256::from_be_slice(&value.bytes)
+    }
+}
+
+fn non_empty(bytes: &[u8]) -> Option<&[u8]> {
+    (!bytes.is_empty()).then_some(bytes)
+}
+
+fn trace_type(trace: &TransactionTrace) -> Result<Type, ProtosError> {
+    Type::try_from(trace.r#type).map_err(|e| ProtosError::TxTypeConversion(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_wkt_types::Timestamp;
+
+    use crate::ethereum_v2::{BlockHeader, Call, TransactionReceipt as TraceReceipt};
+
+    use super::*;
+
+    fn block() -> Block {
+        let log = Log {
+            address: vec![0x33; 20],
+            topics: vec![vec![0x44; 32]],
+            data: vec![0x01],
+            index: 0,
+            block_index: 3,
+            ..Default::default()
+        };
+        let transfer = TransactionTrace {
+            r#type: Type::TrxTypeDynamicFee as i32,
+            hash: vec![0xaa; 32],
+            from: vec![0x11; 20],
+            to: vec![0x22; 20],
+            index: 1,
+            nonce: 7,
+            gas_limit: 50_000,
+            gas_used: 21_000,
+            gas_price: Some(BigInt {
+                bytes: vec![0x3b, 0x9a, 0xca, 0x00],
+            }),
+            max_fee_per_gas: Some(BigInt {
+                bytes: vec![0x77, 0x35, 0x94, 0x00],
+            }),
+            v: vec![1],
+            r: vec![0x01; 32],
+            s: vec![0x02; 32],
+            status: 1,
+            receipt: Some(TraceReceipt {
+                cumulative_gas_used: 42_000,
+                logs_bloom: vec![0; 256],
+                logs: vec![log],
+                ..Default::default()
+            }),
+            calls: vec![Call {
+                call_type: CallType::Call as i32,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let deployment = TransactionTrace {
+            r#type: Type::TrxTypeLegacy as i32,
+            hash: vec![0xbb; 32],
+            from: vec![0x11; 20],
+            to: vec![0x55; 20],
+            index: 2,
+            v: vec![27],
+            receipt: Some(TraceReceipt {
+                logs_bloom: vec![0; 256],
+                ..Default::default()
+            }),
+            calls: vec![Call {
+                call_type: CallType::Create as i32,
+                address: vec![0x55; 20],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        Block {
+            hash: vec![0xcc; 32],
+            number: 20_000_000,
+            size: 1234,
+            header: Some(BlockHeader {
+                number: 20_000_000,
+                parent_hash: vec![0; 32],
+                uncle_hash: vec![0; 32],
+                coinbase: vec![0; 20],
+                state_root: vec![0; 32],
+                transactions_root: vec![0; 32],
+                receipt_root: vec![0; 32],
+                logs_bloom: vec![0; 256],
+                mix_hash: vec![0; 32],
+                hash: vec![0xcc; 32],
+                timestamp: Some(Timestamp {
+                    seconds: 1_717_281_407,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            }),
+            transaction_traces: vec![transfer, deployment],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rpc_block() {
+        let block = block();
+
+        let rpc_block = block.rpc_block(false).unwrap();
+        assert_eq!(rpc_block.header.hash, B256::repeat_byte(0xcc));
+        assert_eq!(rpc_block.header.number, 20_000_000);
+        assert_eq!(rpc_block.header.timestamp, 1_717_281_407);
+        assert_eq!(rpc_block.size, Some(U256::from(1234)));
+        assert_eq!(
+            rpc_block.transactions.as_hashes(),
+            Some([B256::repeat_byte(0xaa), B256::repeat_byte(0xbb)].as_slice())
+        );
+
+        let rpc_block = block.rpc_block(true).unwrap();
+        assert_eq!(rpc_block.transactions.as_transactions().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rpc_transaction() {
+        let block = block();
+
+        let transaction = block
+            .rpc_transaction(&B256::repeat_byte(0xaa))
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.block_hash, Some(B256::repeat_byte(0xcc)));
+        assert_eq!(transaction.transaction_index, Some(1));
+        assert_eq!(transaction.to, Some(Address::repeat_byte(0x22)));
+        assert_eq!(transaction.gas_price, Some(1_000_000_000));
+        assert_eq!(transaction.max_fee_per_gas, Some(2_000_000_000));
+        assert_eq!(transaction.transaction_type, Some(2));
+        assert_eq!(transaction.chain_id, Some(CHAIN_ID));
+
+        let deployment = block
+            .rpc_transaction(&B256::repeat_byte(0xbb))
+            .unwrap()
+            .unwrap();
+        assert_eq!(deployment.to, None);
+        assert_eq!(deployment.access_list, None);
+
+        assert!(block
+            .rpc_transaction(&B256::repeat_byte(0xdd))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_rpc_receipts_and_logs() {
+        let block = block();
+
+        let receipts = block.rpc_receipts().unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts[0].status());
+        assert_eq!(receipts[0].effective_gas_price, 1_000_000_000);
+        assert_eq!(receipts[0].contract_address, None);
+        assert_eq!(
+            receipts[1].contract_address,
+            Some(Address::repeat_byte(0x55))
+        );
+        assert!(!receipts[1].status());
+
+        let logs = block.rpc_logs().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].log_index, Some(3));
+        assert_eq!(logs[0].transaction_index, Some(1));
+        assert_eq!(logs[0].block_hash, Some(B256::repeat_byte(0xcc)));
+        assert_eq!(logs[0].address(), Address::repeat_byte(0x33));
+        assert_eq!(receipts[0].inner.logs(), logs.as_slice());
+    }
+}
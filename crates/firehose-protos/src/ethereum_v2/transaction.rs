@@ -48,7 +48,7 @@ pub const CHAIN_ID: ChainId = 1;
 /// have `v` values of `27` or `28`, which do not encode a chain ID. For such transactions, this function returns `None`.
 /// For non-legacy transactions where `v` encodes a chain ID, this function returns the constant mainnet chain ID.
 ///
-fn get_legacy_chain_id(trace: &TransactionTrace) -> Option<ChainId> {
+pub(crate) fn get_legacy_chain_id(trace: &TransactionTrace) -> Option<ChainId> {
     let v = trace.v();
     if v == 27 || v == 28 {
         None
@@ -63,7 +63,7 @@ impl TransactionTrace {
         self.status == 1
     }
 
-    fn parity(&self) -> Result<Parity, ProtosError> {
+    pub(crate) fn parity(&self) -> Result<Parity, ProtosError> {
         // Extract the first byte of the V value (Ethereum's V value).
         let v = self.v();
 
@@ -232,7 +232,7 @@ impl TryFrom<&TransactionTrace> for Transaction {
     }
 }
 
-fn get_u128_or_default(opt_big_int: &Option<BigInt>) -> Result<u128, ProtosError> {
+pub(crate) fn get_u128_or_default(opt_big_int: &Option<BigInt>) -> Result<u128, ProtosError> {
     let big_int = match opt_big_int {
         Some(gas_price) => gas_price,
         None => &BigInt { bytes: vec![0] },
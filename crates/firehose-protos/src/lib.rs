@@ -4,8 +4,10 @@
 #![deny(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+mod cursor;
 mod error;
 mod ethereum_v2;
+mod serde_hex;
 
 mod bstream {
     pub mod v1 {
@@ -13,6 +15,15 @@ mod bstream {
     }
 }
 
-pub use bstream::v1::Block as BstreamBlock;
+pub use bstream::v1::{
+    block_request::Order as BstreamOrder,
+    block_stream_client::BlockStreamClient as BstreamClient,
+    block_stream_server::{BlockStream as BstreamService, BlockStreamServer as BstreamServer},
+    Block as BstreamBlock, BlockRef as BstreamBlockRef, BlockRequest as BstreamRequest,
+    Cursor as BstreamCursor, ForkStep as BstreamForkStep, Protocol as BstreamProtocol,
+};
 pub use error::ProtosError;
-pub use ethereum_v2::{eth_block::FullReceipt, Block as EthBlock, BlockHeader};
+pub use ethereum_v2::{
+    eth_block::FullReceipt, BigInt, Block as EthBlock, BlockHeader, HeaderOnlyBlock, Log,
+    TransactionReceipt, TransactionTrace,
+};
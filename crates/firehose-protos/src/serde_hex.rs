@@ -0,0 +1,321 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON representations of the Ethereum protobuffer types matching the conventions of the
+//! Ethereum JSON-RPC API, wired into the generated types by `build.rs`.
+//!
+//! Byte fields are 0x-prefixed hex strings, [`BigInt`]s are hex quantities and enum fields are
+//! the names of their variants. For compatibility with existing dumps, deserialization also
+//! accepts byte fields as arrays of integers and enum fields as integers.
+
+use std::{fmt, marker::PhantomData};
+
+use alloy_primitives::hex;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::ethereum_v2::{
+    balance_change, block, gas_change, transaction_trace, BigInt, CallType, TransactionTraceStatus,
+};
+
+/// Serialize `bytes` fields as 0x-prefixed hex strings.
+pub(crate) mod bytes {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode_prefixed(bytes))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        HexBytes::deserialize(deserializer).map(|bytes| bytes.0)
+    }
+}
+
+/// Serialize `repeated bytes` fields as lists of 0x-prefixed hex strings.
+pub(crate) mod bytes_list {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        list: &[Vec<u8>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(list.iter().map(hex::encode_prefixed))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<HexBytes>::deserialize(deserializer)
+            .map(|list| list.into_iter().map(|bytes| bytes.0).collect())
+    }
+}
+
+/// Bytes deserialized from a hex string, with or without `0x` prefix, or an array of integers.
+struct HexBytes(Vec<u8>);
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HexBytesVisitor;
+
+        impl<'de> Visitor<'de> for HexBytesVisitor {
+            type Value = HexBytes;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a hex string or an array of bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                hex::decode(value).map(HexBytes).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+                Ok(HexBytes(value.to_vec()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(HexBytes(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(HexBytesVisitor)
+    }
+}
+
+/// The JSON representation of a [`BigInt`], a hex quantity such as `0x1b4` without leading
+/// zeros, or `0x0`.
+///
+/// Deserialization also accepts the `{"bytes": ...}` object of the protobuffer message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quantity(Vec<u8>);
+
+impl From<BigInt> for Quantity {
+    fn from(value: BigInt) -> Self {
+        Self(value.bytes)
+    }
+}
+
+impl From<Quantity> for BigInt {
+    fn from(value: Quantity) -> Self {
+        Self { bytes: value.0 }
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let digits = hex::encode(&self.0);
+        match digits.trim_start_matches('0') {
+            "" => serializer.serialize_str("0x0"),
+            digits => serializer.serialize_str(&format!("0x{digits}")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Quantity(String),
+            Message {
+                #[serde(with = "bytes")]
+                bytes: Vec<u8>,
+            },
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Quantity(quantity) => {
+                let digits = quantity
+                    .strip_prefix("0x")
+                    .ok_or_else(|| de::Error::custom("quantity is missing the 0x prefix"))?
+                    .trim_start_matches('0');
+                let digits = if digits.len() % 2 == 1 {
+                    format!("0{digits}")
+                } else {
+                    digits.to_string()
+                };
+                hex::decode(digits).map(Quantity).map_err(de::Error::custom)
+            }
+            Repr::Message { bytes } => Ok(Quantity(bytes)),
+        }
+    }
+}
+
+/// A protobuffer enum, stored by prost as an `i32` field.
+pub(crate) trait ProtoEnum: Sized + TryFrom<i32> {
+    /// The name of the variant in the protobuffer definition.
+    fn name(&self) -> &'static str;
+
+    /// The variant with the given name in the protobuffer definition.
+    fn from_name(name: &str) -> Option<Self>;
+
+    /// The value of the variant.
+    fn value(self) -> i32;
+}
+
+macro_rules! impl_proto_enum {
+    ($($enumeration:ty),* $(,)?) => {
+        $(
+            impl ProtoEnum for $enumeration {
+                fn name(&self) -> &'static str {
+                    self.as_str_name()
+                }
+
+                fn from_name(name: &str) -> Option<Self> {
+                    Self::from_str_name(name)
+                }
+
+                fn value(self) -> i32 {
+                    self as i32
+                }
+            }
+        )*
+    };
+}
+
+impl_proto_enum!(
+    block::DetailLevel,
+    transaction_trace::Type,
+    TransactionTraceStatus,
+    CallType,
+    balance_change::Reason,
+    gas_change::Reason,
+);
+
+/// Serialize enum fields by the name of their variant, or by value for variants unknown to
+/// this version of the definitions.
+pub(crate) struct EnumName<E>(PhantomData<E>);
+
+impl<E: ProtoEnum> EnumName<E> {
+    pub(crate) fn serialize<S: Serializer>(value: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+        match E::try_from(*value) {
+            Ok(variant) => serializer.serialize_str(variant.name()),
+            Err(_) => serializer.serialize_i32(*value),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Name(String),
+            Value(i32),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Name(name) => E::from_name(&name)
+                .map(E::value)
+                .ok_or_else(|| de::Error::custom(format!("unknown variant {name}"))),
+            Repr::Value(value) => Ok(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_wkt_types::Timestamp;
+    use serde_json::json;
+
+    use crate::ethereum_v2::{BalanceChange, Block, BlockHeader, Log};
+
+    use super::*;
+
+    #[test]
+    fn test_block_json_uses_ethereum_conventions() {
+        let block = Block {
+            hash: vec![0xab; 32],
+            number: 1,
+            header: Some(BlockHeader {
+                coinbase: vec![0x12; 20],
+                difficulty: Some(BigInt {
+                    bytes: vec![0x00, 0x01, 0xb4],
+                }),
+                total_difficulty: Some(BigInt { bytes: vec![] }),
+                timestamp: Some(Timestamp {
+                    seconds: 1438269988,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            }),
+            balance_changes: vec![BalanceChange {
+                reason: balance_change::Reason::RewardMineBlock as i32,
+                ..Default::default()
+            }],
+            detail_level: block::DetailLevel::DetaillevelExtended as i32,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&block).unwrap();
+
+        assert_eq!(json["hash"], format!("0x{}", "ab".repeat(32)));
+        assert_eq!(json["header"]["coinbase"], format!("0x{}", "12".repeat(20)));
+        assert_eq!(json["header"]["parent_hash"], "0x");
+        assert_eq!(json["header"]["difficulty"], "0x1b4");
+        assert_eq!(json["header"]["total_difficulty"], "0x0");
+        assert_eq!(json["header"]["timestamp"], "2015-07-30T15:26:28Z");
+        assert_eq!(
+            json["balance_changes"][0]["reason"],
+            "REASON_REWARD_MINE_BLOCK"
+        );
+        assert_eq!(json["detail_level"], "DETAILLEVEL_EXTENDED");
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let log = Log {
+            address: vec![0x12; 20],
+            topics: vec![vec![0x01; 32], vec![0x02; 32]],
+            data: vec![0xde, 0xad, 0xbe, 0xef],
+            ..Default::default()
+        };
+        let balance_change = BalanceChange {
+            new_value: Some(BigInt {
+                bytes: vec![0x0d, 0xe0, 0xb6, 0xb3, 0xa7, 0x64, 0x00, 0x00],
+            }),
+            reason: 99,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&log).unwrap();
+        assert_eq!(serde_json::from_str::<Log>(&json).unwrap(), log);
+
+        let json = serde_json::to_string(&balance_change).unwrap();
+        assert_eq!(
+            serde_json::from_str::<BalanceChange>(&json).unwrap(),
+            balance_change
+        );
+    }
+
+    #[test]
+    fn test_json_accepts_integer_arrays() {
+        let log: Log = serde_json::from_value(json!({
+            "address": [18, 52],
+            "topics": [[1], "0x02"],
+            "data": "",
+            "index": 0,
+            "block_index": 0,
+            "ordinal": 0,
+        }))
+        .unwrap();
+        assert_eq!(log.address, vec![0x12, 0x34]);
+        assert_eq!(log.topics, vec![vec![0x01], vec![0x02]]);
+
+        let balance_change: BalanceChange = serde_json::from_value(json!({
+            "address": "0x",
+            "old_value": {"bytes": [1, 0]},
+            "new_value": "0x100",
+            "reason": 1,
+            "ordinal": 0,
+        }))
+        .unwrap();
+        assert_eq!(balance_change.old_value, balance_change.new_value);
+        assert_eq!(balance_change.reason, 1);
+    }
+}
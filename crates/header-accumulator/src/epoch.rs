@@ -9,7 +9,7 @@ use ethportal_api::{
     Header,
 };
 
-use crate::errors::EraValidateError;
+use crate::{era1::Era1, errors::EraValidateError};
 
 /// The maximum number of slots per epoch in Ethereum.
 ///
@@ -94,6 +94,24 @@ impl TryFrom<Vec<Header>> for Epoch {
     }
 }
 
+impl TryFrom<&Era1> for Epoch {
+    type Error = EraValidateError;
+
+    /// Builds the epoch from the header records of an Era1 file, with the total difficulty of
+    /// each block as stored in the file.
+    fn try_from(era1: &Era1) -> Result<Self, Self::Error> {
+        let data: Box<[HeaderRecord]> = era1.header_records().into_boxed_slice();
+        let len = data.len();
+        let data: Box<[HeaderRecord; MAX_EPOCH_SIZE]> = data
+            .try_into()
+            .map_err(|_| EraValidateError::InvalidEpochLength(len))?;
+        Ok(Self {
+            number: era1.epoch_number(),
+            data,
+        })
+    }
+}
+
 impl From<Epoch> for EpochAccumulator {
     fn from(value: Epoch) -> Self {
         let vec: Vec<HeaderRecord> = value.data.to_vec();
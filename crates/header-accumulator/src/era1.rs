@@ -0,0 +1,724 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use alloy_primitives::{Bytes, FixedBytes, U256};
+use alloy_rlp::Decodable;
+use ethportal_api::{
+    types::execution::accumulator::{EpochAccumulator, HeaderRecord},
+    Header,
+};
+use firehose_protos::{EthBlock as Block, ProtosError};
+use tree_hash::TreeHash;
+
+use crate::{
+    epoch::{Epoch, FINAL_EPOCH, MAX_EPOCH_SIZE, MERGE_BLOCK},
+    era_validator::EraValidator,
+    errors::EraValidateError,
+};
+
+/// e2store entry type of the version entry at the start of every Era1 file.
+pub const ERA1_VERSION: u16 = 0x3265;
+
+/// e2store entry type of a snappy-compressed RLP block header.
+pub const ERA1_COMPRESSED_HEADER: u16 = 0x03;
+
+/// e2store entry type of a snappy-compressed RLP block body.
+pub const ERA1_COMPRESSED_BODY: u16 = 0x04;
+
+/// e2store entry type of a snappy-compressed RLP list of block receipts.
+pub const ERA1_COMPRESSED_RECEIPTS: u16 = 0x05;
+
+/// e2store entry type of a block total difficulty, as a little-endian 256-bit integer.
+pub const ERA1_TOTAL_DIFFICULTY: u16 = 0x06;
+
+/// e2store entry type of the epoch accumulator root.
+pub const ERA1_ACCUMULATOR: u16 = 0x07;
+
+/// e2store entry type of the block index at the end of every Era1 file.
+pub const ERA1_BLOCK_INDEX: u16 = 0x3266;
+
+/// Size of the header of an e2store entry: a 2-byte type, a 4-byte length and 2 reserved bytes.
+const ENTRY_HEADER_SIZE: u64 = 8;
+
+/// Writes the blocks of a pre-merge epoch as an [Era1](https://github.com/ethereum/go-ethereum/tree/master/internal/era)
+/// archive.
+///
+/// An Era1 file is a sequence of e2store entries: a version entry, then for each block its
+/// snappy-compressed RLP header, body and receipts followed by its total difficulty, then the
+/// epoch accumulator root and an index of the offsets of the blocks.
+///
+/// The output is readable by Era1 readers, but is not guaranteed to be byte-for-byte identical
+/// to the Era1 files written by geth, since snappy encoders may compress the same data
+/// differently. Compare the decoded blocks with [`Era1::compare_blocks`], or the epoch
+/// accumulator roots, rather than file hashes.
+///
+/// Blocks must be written in order, starting at the first block of an epoch. Every epoch holds
+/// [`MAX_EPOCH_SIZE`] blocks, except for the last pre-merge epoch, which ends with the block
+/// before [`MERGE_BLOCK`].
+pub struct Era1Writer<W: Write> {
+    writer: W,
+    written: u64,
+    offsets: Vec<u64>,
+    records: Vec<HeaderRecord>,
+    start_block: Option<u64>,
+}
+
+impl<W: Write> Era1Writer<W> {
+    /// Start an Era1 file, writing its version entry.
+    pub fn new(writer: W) -> Result<Self, EraValidateError> {
+        let mut era1_writer = Self {
+            writer,
+            written: 0,
+            offsets: Vec::with_capacity(MAX_EPOCH_SIZE),
+            records: Vec::with_capacity(MAX_EPOCH_SIZE),
+            start_block: None,
+        };
+        era1_writer.write_entry(ERA1_VERSION, &[])?;
+        Ok(era1_writer)
+    }
+
+    /// Write the entries of the next block of the epoch.
+    pub fn write_block(&mut self, block: &Block) -> Result<(), EraValidateError> {
+        let start_block = *self
+            .start_block
+            .get_or_insert(block.number - block.number % MAX_EPOCH_SIZE as u64);
+        let expected_number = start_block + self.records.len() as u64;
+        if block.number != expected_number {
+            return Err(EraValidateError::HeaderMismatch {
+                expected_number,
+                block_number: block.number,
+            });
+        }
+        let epoch = (block.number / MAX_EPOCH_SIZE as u64) as usize;
+        if epoch > FINAL_EPOCH || block.number >= MERGE_BLOCK {
+            return Err(EraValidateError::EpochPostMerge(epoch));
+        }
+
+        let header = Header::try_from(block)?;
+        let total_difficulty = block
+            .header()?
+            .total_difficulty
+            .as_ref()
+            .map(|total_difficulty| U256::from_be_slice(&total_difficulty.bytes))
+            .ok_or(ProtosError::BlockConversionError)?;
+
+        self.offsets.push(self.written);
+        self.write_compressed_entry(ERA1_COMPRESSED_HEADER, &alloy_rlp::encode(&header))?;
+        self.write_compressed_entry(ERA1_COMPRESSED_BODY, &block.rlp_encoded_body()?)?;
+        self.write_compressed_entry(ERA1_COMPRESSED_RECEIPTS, &block.rlp_encoded_receipts()?)?;
+        self.write_entry(ERA1_TOTAL_DIFFICULTY, &total_difficulty.to_le_bytes::<32>())?;
+
+        self.records.push(HeaderRecord {
+            block_hash: header.hash(),
+            total_difficulty,
+        });
+        Ok(())
+    }
+
+    /// Write the accumulator root and the block index, completing the Era1 file.
+    ///
+    /// Returns the underlying writer and the epoch accumulator root, which can be checked
+    /// against the pre-merge accumulator with [`EraValidator`](crate::EraValidator).
+    pub fn finish(mut self) -> Result<(W, FixedBytes<32>), EraValidateError> {
+        let start_block = self
+            .start_block
+            .ok_or(EraValidateError::InvalidEpochLength(0))?;
+        let epoch_size = (MERGE_BLOCK - start_block).min(MAX_EPOCH_SIZE as u64) as usize;
+        if self.records.len() != epoch_size {
+            return Err(EraValidateError::InvalidEpochLength(self.records.len()));
+        }
+
+        let root = EpochAccumulator::from(std::mem::take(&mut self.records)).tree_hash_root();
+        self.write_entry(ERA1_ACCUMULATOR, root.as_slice())?;
+
+        // Offsets are relative to the start of the block index entry.
+        let index_offset = self.written;
+        let mut index = Vec::with_capacity(16 + 8 * self.offsets.len());
+        index.extend_from_slice(&start_block.to_le_bytes());
+        for offset in &self.offsets {
+            index.extend_from_slice(&(*offset as i64 - index_offset as i64).to_le_bytes());
+        }
+        index.extend_from_slice(&(self.offsets.len() as u64).to_le_bytes());
+        self.write_entry(ERA1_BLOCK_INDEX, &index)?;
+
+        self.writer.flush()?;
+        Ok((self.writer, root))
+    }
+
+    fn write_compressed_entry(
+        &mut self,
+        entry_type: u16,
+        data: &[u8],
+    ) -> Result<(), EraValidateError> {
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder.write_all(data)?;
+        let compressed = encoder
+            .into_inner()
+            .map_err(|e| EraValidateError::Io(e.into_error()))?;
+        self.write_entry(entry_type, &compressed)
+    }
+
+    fn write_entry(&mut self, entry_type: u16, data: &[u8]) -> Result<(), EraValidateError> {
+        let length = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "e2store entry too large"))?;
+        self.writer.write_all(&entry_type.to_le_bytes())?;
+        self.writer.write_all(&length.to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+        self.writer.write_all(data)?;
+        self.written += ENTRY_HEADER_SIZE + data.len() as u64;
+        Ok(())
+    }
+}
+
+/// Export the blocks of a pre-merge epoch to an Era1 file.
+///
+/// The blocks are sorted by number, and must cover the whole epoch. Returns the epoch
+/// accumulator root of the exported epoch.
+///
+/// # Arguments
+///
+/// * `blocks` - The verified blocks of the epoch, such as read from flat files.
+/// * `writer` - Where to write the Era1 file.
+pub fn export_era1<W: Write>(
+    mut blocks: Vec<Block>,
+    writer: W,
+) -> Result<FixedBytes<32>, EraValidateError> {
+    blocks.sort_by_key(|block| block.number);
+    let mut era1_writer = Era1Writer::new(writer)?;
+    for block in &blocks {
+        era1_writer.write_block(block)?;
+    }
+    era1_writer.finish().map(|(_, root)| root)
+}
+
+/// Get the conventional file name of an Era1 file, such as `mainnet-00000-5ec1ffb8.era1`, from
+/// the network name, the epoch number and the first four bytes of the epoch accumulator root.
+pub fn era1_file_name(network: &str, epoch: usize, root: &FixedBytes<32>) -> String {
+    format!(
+        "{network}-{epoch:05}-{}.era1",
+        alloy_primitives::hex::encode(&root[..4])
+    )
+}
+
+/// An entry of an e2store file, such as an Era1 file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct E2StoreEntry {
+    /// The type of the entry.
+    pub entry_type: u16,
+    /// The data of the entry.
+    pub data: Vec<u8>,
+}
+
+/// Reads the entries of an e2store file, one after the other.
+pub struct E2StoreReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> E2StoreReader<R> {
+    /// Read entries from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Read the next entry, or `None` at the end of the file.
+    pub fn read_entry(&mut self) -> Result<Option<E2StoreEntry>, EraValidateError> {
+        let mut header = [0; ENTRY_HEADER_SIZE as usize];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let entry_type = u16::from_le_bytes([header[0], header[1]]);
+        let length = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+        if header[6..] != [0, 0] {
+            return Err(EraValidateError::Era1Invalid(format!(
+                "reserved bytes set in entry of type {entry_type:#06x}"
+            )));
+        }
+
+        let mut data = vec![0; length as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(E2StoreEntry { entry_type, data }))
+    }
+}
+
+impl<R: Read> Iterator for E2StoreReader<R> {
+    type Item = Result<E2StoreEntry, EraValidateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+/// The body of a block read from an Era1 file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Era1Body {
+    /// The RLP encoding of each transaction, as found in the block body.
+    pub transactions: Vec<Bytes>,
+    /// The uncle headers.
+    pub uncles: Vec<Header>,
+}
+
+/// A block read from an Era1 file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Era1Block {
+    /// The block header.
+    pub header: Header,
+    /// The block body.
+    pub body: Era1Body,
+    /// The RLP encoding of each receipt, as found in the list of the block receipts.
+    pub receipts: Vec<Bytes>,
+    /// The total difficulty of the chain up to and including the block.
+    pub total_difficulty: U256,
+}
+
+impl Era1Block {
+    fn header_record(&self) -> HeaderRecord {
+        HeaderRecord {
+            block_hash: self.header.hash(),
+            total_difficulty: self.total_difficulty,
+        }
+    }
+}
+
+/// A field of a block that differs between an Era1 file and Firehose flat files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMismatch {
+    /// The number of the block.
+    pub block_number: u64,
+    /// The name of the field that differs, such as `state_root` or `receipts`, or `block` if
+    /// the block is missing from the Firehose blocks.
+    pub field: &'static str,
+}
+
+/// The contents of an Era1 file, read as an independent reference for auditing Firehose flat
+/// files.
+#[derive(Clone, Debug)]
+pub struct Era1 {
+    /// The number of the first block of the file.
+    pub start_block: u64,
+    /// The blocks of the file, in order.
+    pub blocks: Vec<Era1Block>,
+    /// The epoch accumulator root stored in the file.
+    pub accumulator_root: FixedBytes<32>,
+}
+
+impl Era1 {
+    /// Read and decode an Era1 file.
+    ///
+    /// The block index must match the blocks found in the file, which must be in order.
+    pub fn read<R: Read>(reader: R) -> Result<Self, EraValidateError> {
+        let mut entries = E2StoreReader::new(reader);
+        match entries.read_entry()? {
+            Some(entry) if entry.entry_type == ERA1_VERSION => {}
+            _ => {
+                return Err(EraValidateError::Era1Invalid(
+                    "missing version entry".into(),
+                ))
+            }
+        }
+
+        let mut blocks = Vec::new();
+        let mut accumulator_root = None;
+        let mut index = None;
+        while let Some(entry) = entries.read_entry()? {
+            match entry.entry_type {
+                ERA1_COMPRESSED_HEADER => {
+                    let header = Header::decode(&mut decompress(&entry.data)?.as_slice())?;
+                    let body = read_compressed_entry(&mut entries, ERA1_COMPRESSED_BODY)?;
+                    let receipts = read_compressed_entry(&mut entries, ERA1_COMPRESSED_RECEIPTS)?;
+                    let total_difficulty = read_entry(&mut entries, ERA1_TOTAL_DIFFICULTY)?;
+                    let total_difficulty: [u8; 32] =
+                        total_difficulty.as_slice().try_into().map_err(|_| {
+                            EraValidateError::Era1Invalid("invalid total difficulty".into())
+                        })?;
+                    blocks.push(Era1Block {
+                        header,
+                        body: decode_body(&body)?,
+                        receipts: rlp_list_items(&receipts)?,
+                        total_difficulty: U256::from_le_bytes(total_difficulty),
+                    });
+                }
+                ERA1_ACCUMULATOR => {
+                    accumulator_root =
+                        Some(FixedBytes::try_from(entry.data.as_slice()).map_err(|_| {
+                            EraValidateError::Era1Invalid("invalid accumulator root".into())
+                        })?);
+                }
+                ERA1_BLOCK_INDEX => index = Some(entry.data),
+                _ => {}
+            }
+        }
+
+        let accumulator_root = accumulator_root
+            .ok_or_else(|| EraValidateError::Era1Invalid("missing accumulator root".into()))?;
+        let index =
+            index.ok_or_else(|| EraValidateError::Era1Invalid("missing block index".into()))?;
+        if index.len() < 16 || index.len() != 16 + 8 * blocks.len() {
+            return Err(EraValidateError::Era1Invalid(format!(
+                "block index of {} bytes for {} blocks",
+                index.len(),
+                blocks.len()
+            )));
+        }
+        let start_block = u64::from_le_bytes(index[..8].try_into().expect("8 bytes"));
+        let count = u64::from_le_bytes(index[index.len() - 8..].try_into().expect("8 bytes"));
+        if count != blocks.len() as u64 {
+            return Err(EraValidateError::Era1Invalid(format!(
+                "block index count {count} for {} blocks",
+                blocks.len()
+            )));
+        }
+        for (expected_number, block) in (start_block..).zip(&blocks) {
+            if block.header.number != expected_number {
+                return Err(EraValidateError::HeaderMismatch {
+                    expected_number,
+                    block_number: block.header.number,
+                });
+            }
+        }
+
+        Ok(Self {
+            start_block,
+            blocks,
+            accumulator_root,
+        })
+    }
+
+    /// Get the epoch number of the file.
+    pub fn epoch_number(&self) -> usize {
+        (self.start_block / MAX_EPOCH_SIZE as u64) as usize
+    }
+
+    /// Get the header records of the blocks, with their hashes and total difficulties.
+    pub fn header_records(&self) -> Vec<HeaderRecord> {
+        self.blocks.iter().map(Era1Block::header_record).collect()
+    }
+
+    /// Validate the file against the pre-merge accumulator.
+    ///
+    /// The accumulator root computed from the blocks must match the root stored in the file,
+    /// and the epoch must be validated by [`EraValidator::validate_era`]. The final pre-merge
+    /// epoch, [`FINAL_EPOCH`], ends before [`MERGE_BLOCK`] with fewer than [`MAX_EPOCH_SIZE`]
+    /// blocks, so only its accumulator root is checked against the pre-merge accumulator.
+    /// Returns the accumulator root.
+    pub fn validate(&self, validator: &EraValidator) -> Result<FixedBytes<32>, EraValidateError> {
+        let header_records = self.header_records();
+        let len = header_records.len();
+        let root = EpochAccumulator::from(header_records).tree_hash_root();
+        if root != self.accumulator_root {
+            tracing::error!(
+                "the root stored in the era1 file is: {:?} and the computed root was: {:?}",
+                self.accumulator_root,
+                root
+            );
+            return Err(EraValidateError::EraAccumulatorMismatch);
+        }
+
+        if self.epoch_number() == FINAL_EPOCH {
+            if len as u64 != MERGE_BLOCK - self.start_block {
+                return Err(EraValidateError::InvalidEpochLength(len));
+            }
+            return validator.validate_root(FINAL_EPOCH, root);
+        }
+        validator.validate_era(&Epoch::try_from(self)?)
+    }
+
+    /// Compare each block of the file field by field against the matching Firehose block.
+    ///
+    /// Returns every field that differs. Firehose blocks that are not in the file are ignored.
+    pub fn compare_blocks(&self, blocks: &[Block]) -> Result<Vec<BlockMismatch>, EraValidateError> {
+        let firehose_blocks: HashMap<u64, &Block> =
+            blocks.iter().map(|block| (block.number, block)).collect();
+
+        let mut mismatches = Vec::new();
+        for era1_block in &self.blocks {
+            let block_number = era1_block.header.number;
+            let fields = match firehose_blocks.get(&block_number) {
+                Some(block) => compare_block(era1_block, block)?,
+                None => vec!["block"],
+            };
+            mismatches.extend(fields.into_iter().map(|field| BlockMismatch {
+                block_number,
+                field,
+            }));
+        }
+        Ok(mismatches)
+    }
+}
+
+fn compare_block(
+    era1_block: &Era1Block,
+    block: &Block,
+) -> Result<Vec<&'static str>, EraValidateError> {
+    let era1 = &era1_block.header;
+    let firehose = Header::try_from(block)?;
+    let firehose_body = decode_body(&block.rlp_encoded_body()?)?;
+    let firehose_receipts = rlp_list_items(&block.rlp_encoded_receipts()?)?;
+    let firehose_total_difficulty = block
+        .header()?
+        .total_difficulty
+        .as_ref()
+        .map(|total_difficulty| U256::from_be_slice(&total_difficulty.bytes));
+
+    let fields = [
+        ("hash", era1.hash().as_slice() == block.hash.as_slice()),
+        ("parent_hash", era1.parent_hash == firehose.parent_hash),
+        ("uncles_hash", era1.uncles_hash == firehose.uncles_hash),
+        ("author", era1.author == firehose.author),
+        ("state_root", era1.state_root == firehose.state_root),
+        (
+            "transactions_root",
+            era1.transactions_root == firehose.transactions_root,
+        ),
+        (
+            "receipts_root",
+            era1.receipts_root == firehose.receipts_root,
+        ),
+        ("logs_bloom", era1.logs_bloom == firehose.logs_bloom),
+        ("difficulty", era1.difficulty == firehose.difficulty),
+        ("number", era1.number == firehose.number),
+        ("gas_limit", era1.gas_limit == firehose.gas_limit),
+        ("gas_used", era1.gas_used == firehose.gas_used),
+        ("timestamp", era1.timestamp == firehose.timestamp),
+        ("extra_data", era1.extra_data == firehose.extra_data),
+        ("mix_hash", era1.mix_hash == firehose.mix_hash),
+        ("nonce", era1.nonce == firehose.nonce),
+        (
+            "base_fee_per_gas",
+            era1.base_fee_per_gas == firehose.base_fee_per_gas,
+        ),
+        (
+            "transactions",
+            era1_block.body.transactions == firehose_body.transactions,
+        ),
+        ("uncles", era1_block.body.uncles == firehose_body.uncles),
+        ("receipts", era1_block.receipts == firehose_receipts),
+        (
+            "total_difficulty",
+            Some(era1_block.total_difficulty) == firehose_total_difficulty,
+        ),
+    ];
+
+    Ok(fields
+        .into_iter()
+        .filter(|(_, matches)| !matches)
+        .map(|(field, _)| field)
+        .collect())
+}
+
+fn read_entry<R: Read>(
+    entries: &mut E2StoreReader<R>,
+    entry_type: u16,
+) -> Result<Vec<u8>, EraValidateError> {
+    match entries.read_entry()? {
+        Some(entry) if entry.entry_type == entry_type => Ok(entry.data),
+        _ => Err(EraValidateError::Era1Invalid(format!(
+            "expected entry of type {entry_type:#06x}"
+        ))),
+    }
+}
+
+fn read_compressed_entry<R: Read>(
+    entries: &mut E2StoreReader<R>,
+    entry_type: u16,
+) -> Result<Vec<u8>, EraValidateError> {
+    decompress(&read_entry(entries, entry_type)?)
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, EraValidateError> {
+    let mut decompressed = Vec::new();
+    snap::read::FrameDecoder::new(data).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+fn decode_body(encoded: &[u8]) -> Result<Era1Body, EraValidateError> {
+    let items = rlp_list_items(encoded)?;
+    let [transactions, uncles, ..] = items.as_slice() else {
+        return Err(EraValidateError::Era1Invalid("invalid block body".into()));
+    };
+    Ok(Era1Body {
+        transactions: rlp_list_items(transactions)?,
+        uncles: Vec::<Header>::decode(&mut uncles.as_ref())?,
+    })
+}
+
+/// Splits the RLP encoding of a list into the RLP encodings of its items.
+fn rlp_list_items(mut encoded: &[u8]) -> Result<Vec<Bytes>, EraValidateError> {
+    let mut payload = alloy_rlp::Header::decode_bytes(&mut encoded, true)?;
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let mut rest = payload;
+        let header = alloy_rlp::Header::decode(&mut rest)?;
+        let length = payload.len() - rest.len() + header.payload_length;
+        let item = payload
+            .get(..length)
+            .ok_or(alloy_rlp::Error::InputTooShort)?;
+        items.push(Bytes::copy_from_slice(item));
+        payload = &payload[length..];
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use firehose_protos::{BigInt, BlockHeader};
+
+    use super::*;
+
+    /// Size of the data of the block index entry of a full epoch.
+    const INDEX_SIZE: usize = 16 + 8 * MAX_EPOCH_SIZE;
+
+    /// A block without transactions, with the fields that Era1 files keep.
+    fn epoch_block(number: u64) -> Block {
+        let mut header = BlockHeader {
+            parent_hash: vec![0; 32],
+            uncle_hash: vec![0x1d; 32],
+            coinbase: vec![0; 20],
+            state_root: vec![0x0a; 32],
+            transactions_root: vec![0x56; 32],
+            receipt_root: vec![0x56; 32],
+            logs_bloom: vec![0; 256],
+            difficulty: Some(BigInt {
+                bytes: vec![0x04, 0x00],
+            }),
+            total_difficulty: Some(BigInt {
+                bytes: ((number + 1) * 0x400).to_be_bytes().to_vec(),
+            }),
+            number,
+            gas_limit: 5000,
+            timestamp: Some(Default::default()),
+            mix_hash: vec![0; 32],
+            nonce: number,
+            ..Default::default()
+        };
+        header.hash = Header::try_from(&header).unwrap().hash().to_vec();
+
+        Block {
+            hash: header.hash.clone(),
+            number,
+            header: Some(header),
+            ..Default::default()
+        }
+    }
+
+    fn epoch_blocks() -> Vec<Block> {
+        (0..MAX_EPOCH_SIZE as u64).map(epoch_block).collect()
+    }
+
+    fn export(blocks: &[Block]) -> (Vec<u8>, FixedBytes<32>) {
+        let mut data = Vec::new();
+        let root = export_era1(blocks.to_vec(), &mut data).unwrap();
+        (data, root)
+    }
+
+    #[test]
+    fn test_export_era1_round_trip() {
+        let blocks = epoch_blocks();
+        let (data, root) = export(&blocks);
+
+        let era1 = Era1::read(data.as_slice()).unwrap();
+        assert_eq!(era1.start_block, 0);
+        assert_eq!(era1.epoch_number(), 0);
+        assert_eq!(era1.blocks.len(), MAX_EPOCH_SIZE);
+        assert_eq!(era1.accumulator_root, root);
+        assert_eq!(
+            EpochAccumulator::from(era1.header_records()).tree_hash_root(),
+            root
+        );
+        assert!(era1.compare_blocks(&blocks).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_era1_entries() {
+        let (data, root) = export(&epoch_blocks());
+
+        // The version entry, then the compressed header of the first block
+        assert_eq!(data[..8], [0x65, 0x32, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(data[8..10], [0x03, 0x00]);
+        assert_eq!(data[14..16], [0, 0]);
+
+        // The accumulator root, then the block index
+        let index = data.len() - 8 - INDEX_SIZE;
+        let accumulator = index - 8 - 32;
+        assert_eq!(
+            data[accumulator..accumulator + 8],
+            [0x07, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(data[accumulator + 8..index], root[..]);
+        assert_eq!(
+            data[index..index + 8],
+            [0x66, 0x32, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00]
+        );
+
+        // The start block, the offset of the first block, right after the version entry,
+        // relative to the index entry, and the number of blocks
+        let entry = &data[index + 8..];
+        assert_eq!(entry[..8], 0u64.to_le_bytes());
+        assert_eq!(entry[8..16], (8 - index as i64).to_le_bytes());
+        assert_eq!(entry[INDEX_SIZE - 8..], [0x00, 0x20, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_read_truncated_entry() {
+        let (data, _) = export(&epoch_blocks());
+
+        // Cut the compressed header of the first block short
+        let result = Era1::read(&data[..20]);
+        assert!(matches!(result, Err(EraValidateError::Io(_))));
+    }
+
+    #[test]
+    fn test_read_index_count_mismatch() {
+        let (mut data, _) = export(&epoch_blocks());
+        let end = data.len();
+        data[end - 8..].copy_from_slice(&8191u64.to_le_bytes());
+
+        let result = Era1::read(data.as_slice());
+        assert!(matches!(
+            result,
+            Err(EraValidateError::Era1Invalid(message)) if message.contains("count 8191")
+        ));
+    }
+
+    #[test]
+    fn test_validate_tampered_accumulator_root() {
+        let (mut data, _) = export(&epoch_blocks());
+        let root = data.len() - 8 - INDEX_SIZE - 32;
+        data[root] ^= 0xff;
+
+        let era1 = Era1::read(data.as_slice()).unwrap();
+        assert!(matches!(
+            era1.validate(&EraValidator::default()),
+            Err(EraValidateError::EraAccumulatorMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_compare_blocks_mismatch() {
+        let mut blocks = epoch_blocks();
+        let (data, _) = export(&blocks);
+        let era1 = Era1::read(data.as_slice()).unwrap();
+
+        blocks[5].header.as_mut().unwrap().gas_used = 21000;
+        blocks.pop();
+        assert_eq!(
+            era1.compare_blocks(&blocks).unwrap(),
+            vec![
+                BlockMismatch {
+                    block_number: 5,
+                    field: "gas_used",
+                },
+                BlockMismatch {
+                    block_number: 8191,
+                    field: "block",
+                },
+            ]
+        );
+    }
+}
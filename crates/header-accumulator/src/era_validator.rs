@@ -58,15 +58,23 @@ impl EraValidator {
     /// For block post merge, the sync-committee should be used to validate block headers
     /// in the canonical blockchain. So this function is not useful for those.
     pub fn validate_era(&self, epoch: &Epoch) -> Result<FixedBytes<32>, EraValidateError> {
-        if epoch.number() > FINAL_EPOCH {
-            return Err(EraValidateError::EpochPostMerge(epoch.number()));
-        }
-
         let header_records: Vec<_> = epoch.iter().cloned().collect();
         let epoch_accumulator = EpochAccumulator::from(header_records);
 
-        let root = epoch_accumulator.tree_hash_root();
-        let valid_root = self.historical_epochs[epoch.number()];
+        self.validate_root(epoch.number(), epoch_accumulator.tree_hash_root())
+    }
+
+    /// Checks the accumulator root of an epoch against the header accumulator.
+    pub(crate) fn validate_root(
+        &self,
+        epoch_number: usize,
+        root: FixedBytes<32>,
+    ) -> Result<FixedBytes<32>, EraValidateError> {
+        if epoch_number > FINAL_EPOCH {
+            return Err(EraValidateError::EpochPostMerge(epoch_number));
+        }
+
+        let valid_root = self.historical_epochs[epoch_number];
 
         if root == valid_root {
             Ok(root)
@@ -72,6 +72,22 @@ pub enum EraValidateError {
     #[error("Epoch is in post merge: {0}")]
     EpochPostMerge(usize),
 
+    /// Invalid Era1 file
+    #[error("Invalid Era1 file: {0}")]
+    Era1Invalid(String),
+
+    /// Error decoding RLP from an Era1 file
+    #[error("RLP error: {0}")]
+    Rlp(#[from] alloy_rlp::Error),
+
+    /// I/O error while reading or writing an Era1 file
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Error converting a block from flat files
+    #[error("Protos error: {0}")]
+    ProtosError(ProtosError),
+
     /// Header block number is different than expected
     #[error("Header block number ({block_number}) is different than expected ({expected_number})")]
     HeaderMismatch {
@@ -86,7 +102,7 @@ impl From<ProtosError> for EraValidateError {
     fn from(error: ProtosError) -> Self {
         match error {
             ProtosError::BlockConversionError => Self::HeaderDecodeError,
-            _ => unimplemented!("Error mapping is not implemented"),
+            error => Self::ProtosError(error),
         }
     }
 }
@@ -5,11 +5,13 @@
 #![doc = include_str!("../README.md")]
 
 mod epoch;
+mod era1;
 mod era_validator;
 mod errors;
 mod inclusion_proof;
 
 pub use epoch::*;
+pub use era1::*;
 pub use era_validator::*;
 pub use errors::*;
 pub use inclusion_proof::*;
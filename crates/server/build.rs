@@ -0,0 +1,20 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use prost_build::Config;
+
+fn main() {
+    let mut config = Config::new();
+    config.type_attribute(".", "#[allow(clippy::enum_variant_names)]");
+    config.type_attribute(".", "#[allow(missing_docs)]");
+
+    // The messages come from firehose-rs, so that responses convert to blocks with
+    // firehose-protos, and only the service stubs are generated here
+    config.extern_path(".sf.firehose.v2", "::firehose_rs");
+
+    tonic_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .compile_protos_with_config(config, &["protos/firehose.proto"], &["protos/"])
+        .unwrap();
+}
@@ -0,0 +1,269 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::Arc;
+
+use alloy_primitives::hex;
+use firehose_protos::{
+    BstreamBlock, BstreamClient, BstreamProtocol, BstreamRequest, BstreamServer, BstreamService,
+    EthBlock as Block,
+};
+use flat_files_decoder::{DecoderError, FlatFileBundle, FlatFileStore, VerificationOptions};
+use futures::{future, Stream, TryStreamExt};
+use prost::Message;
+use tokio::net::TcpListener;
+use tonic::{
+    transport::{Channel, Server},
+    Request, Response, Status,
+};
+use tracing::{debug, info};
+
+use crate::{
+    error::ServerError,
+    firehose::ETH_BLOCK_TYPE_URL,
+    grpc::{incoming, stream_blocks, ResponseStream},
+};
+
+/// The content types of [`BstreamRequest`]s for Ethereum blocks, the content type of
+/// Ethereum `.dbin` files and the type of their payloads. An empty content type matches any.
+const CONTENT_TYPES: &[&str] = &["", "ETH", "sf.ethereum.type.v2.Block", ETH_BLOCK_TYPE_URL];
+
+/// The bstream `sf.bstream.v1` `BlockStream` service of dfuse-style relayers, serving the blocks
+/// of a [`FlatFileStore`] as [`BstreamBlock`]s.
+///
+/// The `burst` of a request sets where the stream starts: the given number of blocks before the
+/// end of the last bundle of the store, the last block with `-1`, as every block of the store
+/// is irreversible, or block `X` with `-X`. Streams never start before the first block of the
+/// store, so a burst larger than the store streams all of it, and end after the last block of
+/// the store rather than waiting for new blocks. Requests for other content types than Ethereum
+/// blocks are rejected, and blocks are always sent in order.
+#[derive(Clone, Debug)]
+pub struct BlockStreamService {
+    store: Arc<FlatFileStore>,
+}
+
+impl BlockStreamService {
+    /// Serve the blocks of a store.
+    pub fn new(store: FlatFileStore) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl BstreamService for BlockStreamService {
+    type BlocksStream = ResponseStream<BstreamBlock>;
+
+    async fn blocks(
+        &self,
+        request: Request<BstreamRequest>,
+    ) -> Result<Response<Self::BlocksStream>, Status> {
+        let request = request.into_inner();
+        if !CONTENT_TYPES.contains(&request.content_type.as_str()) {
+            return Err(Status::invalid_argument(format!(
+                "Unsupported content type {}, expected Ethereum blocks",
+                request.content_type
+            )));
+        }
+
+        let head = self
+            .store
+            .bundles()
+            .last()
+            .map(FlatFileBundle::end_block)
+            .ok_or_else(|| Status::unavailable("No blocks to stream"))?;
+        let start = match request.burst {
+            -1 => head,
+            burst if burst < 0 => burst.unsigned_abs(),
+            burst => (head + 1).saturating_sub(burst.unsigned_abs()),
+        };
+        debug!(
+            "Streaming blocks {start} to {head} to {}",
+            request.requester
+        );
+
+        let stream = stream_blocks(Arc::clone(&self.store), start..=head, move |block| {
+            bstream_block(&block, head)
+        });
+        Ok(Response::new(stream))
+    }
+}
+
+/// Serve the bstream `BlockStream` service over gRPC until the server fails.
+pub async fn serve_bstream(
+    listener: TcpListener,
+    service: BlockStreamService,
+) -> Result<(), ServerError> {
+    info!("Serving bstream on {}", listener.local_addr()?);
+    Server::builder()
+        .add_service(BstreamServer::new(service))
+        .serve_with_incoming(incoming(listener)?)
+        .await?;
+    Ok(())
+}
+
+/// Stream the Ethereum blocks of a bstream `BlockStream` server, such as a relayer.
+///
+/// Blocks are decoded from their payload and verified as they arrive, like the blocks of flat
+/// files, with failures handled according to the [`FailureMode`] of the
+/// [`VerificationOptions`]. A block that cannot be decoded ends the stream with an error.
+///
+/// [`FailureMode`]: flat_files_decoder::FailureMode
+pub async fn stream_bstream_blocks(
+    client: &mut BstreamClient<Channel>,
+    request: BstreamRequest,
+    verification: VerificationOptions,
+) -> Result<impl Stream<Item = Result<Block, ServerError>>, ServerError> {
+    let blocks = client.blocks(request).await?.into_inner();
+    Ok(blocks
+        .map_err(ServerError::from)
+        .try_filter_map(move |block| {
+            let block = Block::decode(block.payload_buffer.as_slice())
+                .map_err(DecoderError::from)
+                .and_then(|block| verification.apply(block))
+                .map(|(block, _)| block)
+                .map_err(ServerError::from);
+            future::ready(block)
+        }))
+}
+
+/// Wrap a block in a [`BstreamBlock`], irreversible as are all blocks of flat files.
+fn bstream_block(block: &Block, head: u64) -> BstreamBlock {
+    let header = block.header.as_ref();
+    BstreamBlock {
+        number: block.number,
+        id: hex::encode(&block.hash),
+        previous_id: header
+            .map(|header| hex::encode(&header.parent_hash))
+            .unwrap_or_default(),
+        timestamp: header.and_then(|header| header.timestamp.clone()),
+        lib_num: block.number,
+        payload_kind: BstreamProtocol::Eth as i32,
+        payload_buffer: block.encode_to_vec(),
+        head_num: head,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flat_files_decoder::{FailureMode, VerificationCheck};
+
+    use crate::test_utils::{block, block_hash, bundle_store, store};
+
+    use super::*;
+
+    async fn serve(store: FlatFileStore) -> BstreamClient<Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_bstream(listener, BlockStreamService::new(store)));
+        BstreamClient::connect(url).await.unwrap()
+    }
+
+    async fn numbers(
+        client: &mut BstreamClient<Channel>,
+        burst: i64,
+        verification: VerificationOptions,
+    ) -> Result<Vec<u64>, ServerError> {
+        let request = BstreamRequest {
+            burst,
+            content_type: "ETH".to_string(),
+            ..Default::default()
+        };
+        stream_bstream_blocks(client, request, verification)
+            .await?
+            .map_ok(|block| block.number)
+            .try_collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_stream_bursts() {
+        let mut client = serve(store([0..100, 100..200])).await;
+
+        for (burst, expected) in [
+            (3, vec![197, 198, 199]),
+            (0, vec![]),
+            (-1, vec![199]),
+            (-195, vec![195, 196, 197, 198, 199]),
+        ] {
+            let numbers = numbers(&mut client, burst, VerificationOptions::default()).await;
+            assert_eq!(numbers.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_bursts_clamped_to_store() {
+        let mut client = serve(store([100..200, 200..300])).await;
+
+        for burst in [500, -5] {
+            let numbers = numbers(&mut client, burst, VerificationOptions::default()).await;
+            assert_eq!(numbers.unwrap(), (100..300).collect::<Vec<_>>());
+        }
+        let numbers = numbers(&mut client, -250, VerificationOptions::default()).await;
+        assert_eq!(numbers.unwrap(), (250..300).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_stream_bstream_blocks() {
+        let mut client = serve(store([0..100, 100..200])).await;
+
+        let request = BstreamRequest {
+            burst: 1,
+            ..Default::default()
+        };
+        let blocks: Vec<_> = client
+            .blocks(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].number, 199);
+        assert_eq!(blocks[0].id, hex::encode(block_hash(199)));
+        assert_eq!(blocks[0].previous_id, hex::encode(block_hash(198)));
+        assert_eq!(blocks[0].lib_num, 199);
+        assert_eq!(blocks[0].head_num, 199);
+        assert_eq!(
+            Block::decode(blocks[0].payload_buffer.as_slice()).unwrap(),
+            block(199)
+        );
+
+        let request = BstreamRequest {
+            burst: 1,
+            content_type: "SOL".to_string(),
+            ..Default::default()
+        };
+        let status = client.blocks(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_stream_bstream_blocks_verification() {
+        let mut blocks: Vec<_> = (0..100).map(block).collect();
+        blocks[98].header.as_mut().unwrap().receipt_root = vec![0xff; 32];
+        let unverified = VerificationOptions {
+            checks: Vec::new(),
+            ..Default::default()
+        };
+        let mut client = serve(bundle_store([blocks]).with_verification(unverified)).await;
+
+        let error = numbers(&mut client, 3, VerificationOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ServerError::Decoder(DecoderError::VerificationFailed { block_number: 98 })
+        ));
+
+        let skip = VerificationOptions {
+            checks: vec![VerificationCheck::ReceiptRoot],
+            failure_mode: FailureMode::Skip,
+            ..Default::default()
+        };
+        assert_eq!(numbers(&mut client, 3, skip).await.unwrap(), vec![97, 99]);
+    }
+}
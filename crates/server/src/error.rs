@@ -0,0 +1,31 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use flat_files_decoder::DecoderError;
+use thiserror::Error;
+
+/// Get custom error variants for issues with loading and serving flat file blocks.
+#[derive(Debug, Error)]
+pub enum ServerError {
+    /// Error reading, decoding or verifying blocks.
+    #[error("Decoder error: {0}")]
+    Decoder(#[from] DecoderError),
+
+    /// I/O error, such as binding the listening socket.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Error status returned by a gRPC server.
+    #[error("gRPC status: {0}")]
+    Status(Box<tonic::Status>),
+
+    /// gRPC transport error, such as a failure to serve connections.
+    #[error("Transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+}
+
+impl From<tonic::Status> for ServerError {
+    fn from(status: tonic::Status) -> Self {
+        Self::Status(Box::new(status))
+    }
+}
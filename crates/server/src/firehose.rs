@@ -0,0 +1,490 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fmt, ops::RangeInclusive, str::FromStr, sync::Arc};
+
+use alloy_primitives::hex;
+use firehose_protos::EthBlock as Block;
+use firehose_rs::{
+    single_block_request::Reference, ForkStep, Request as FirehoseRequest,
+    Response as FirehoseResponse, SingleBlockRequest, SingleBlockResponse,
+};
+use flat_files_decoder::{FlatFileBundle, FlatFileStore};
+use prost::Message;
+use prost_wkt_types::Any;
+use tokio::net::TcpListener;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{debug, info};
+
+use crate::{
+    error::ServerError,
+    grpc::{incoming, read, status, stream_blocks, ResponseStream},
+    sf::firehose::v2::{
+        fetch_server::{self, FetchServer},
+        stream_server::{self, StreamServer},
+    },
+};
+
+/// The type URL of the Ethereum blocks in Firehose responses.
+pub const ETH_BLOCK_TYPE_URL: &str = "type.googleapis.com/sf.ethereum.type.v2.Block";
+
+/// The position of a block in a Firehose stream, from which a stream can be resumed.
+///
+/// Cursors are opaque to clients, encoded as the block number and hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cursor {
+    number: u64,
+    hash: Vec<u8>,
+}
+
+impl Cursor {
+    fn of(block: &Block) -> Self {
+        Self {
+            number: block.number,
+            hash: block.hash.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.number, hex::encode(&self.hash))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = Status;
+
+    fn from_str(cursor: &str) -> Result<Self, Self::Err> {
+        let invalid = || Status::invalid_argument(format!("Invalid cursor {cursor}"));
+        let (number, hash) = cursor.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            number: number.parse().map_err(|_| invalid())?,
+            hash: hex::decode(hash).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// The Firehose `sf.firehose.v2` `Stream` and `Fetch` services, serving the blocks of a
+/// [`FlatFileStore`].
+///
+/// Flat files only hold final blocks, so streams never undo blocks: responses have
+/// [`ForkStep::StepFinal`] when the request asks for final blocks only, and
+/// [`ForkStep::StepNew`] otherwise. The head of the chain is the last block of the last bundle
+/// of the store, and streams without a stop block end there rather than waiting for new blocks.
+/// Blocks are decoded and verified as they are read, and a bundle that is missing or fails to
+/// verify ends the stream with an error. Transforms are not supported.
+#[derive(Clone, Debug)]
+pub struct FirehoseService {
+    store: Arc<FlatFileStore>,
+}
+
+impl FirehoseService {
+    /// Serve the blocks of a store.
+    pub fn new(store: FlatFileStore) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    /// Get the number of the last block of the store.
+    fn head(&self) -> Option<u64> {
+        self.store.bundles().last().map(FlatFileBundle::end_block)
+    }
+
+    /// Read a single block, which must exist in the store.
+    fn read_block(&self, number: u64) -> Result<Block, Status> {
+        match self.store.blocks(number..=number).next() {
+            Some(Ok(block)) => Ok(block),
+            Some(Err(e)) => Err(status(e)),
+            None => Err(Status::not_found(format!("Block {number} not found"))),
+        }
+    }
+
+    /// Read the block at a cursor, checking that it is the block the cursor was issued for.
+    fn block_at(&self, cursor: &Cursor) -> Result<Block, Status> {
+        let block = self.read_block(cursor.number)?;
+        if block.hash != cursor.hash {
+            return Err(Status::not_found(format!(
+                "Block {} with hash {} not found",
+                cursor.number,
+                hex::encode(&cursor.hash)
+            )));
+        }
+        Ok(block)
+    }
+
+    /// Resolve the range of block numbers of a stream request, ending with the head of the
+    /// store at the latest. The store skips the blocks of the range before its first bundle.
+    fn stream_range(&self, request: &FirehoseRequest) -> Result<RangeInclusive<u64>, Status> {
+        let Some(head) = self.head() else {
+            return Err(Status::not_found("No blocks to stream"));
+        };
+        let stop = match request.stop_block_num {
+            0 => head,
+            stop => stop.min(head),
+        };
+
+        if !request.cursor.is_empty() {
+            let cursor: Cursor = request.cursor.parse()?;
+            self.block_at(&cursor)?;
+            return Ok(cursor.number + 1..=stop);
+        }
+
+        let start = match u64::try_from(request.start_block_num) {
+            Ok(start) => start,
+            Err(_) => head.saturating_add_signed(request.start_block_num),
+        };
+        if start > stop {
+            return Err(Status::invalid_argument(format!(
+                "Start block {start} is after stop block {stop}"
+            )));
+        }
+        Ok(start..=stop)
+    }
+}
+
+#[tonic::async_trait]
+impl stream_server::Stream for FirehoseService {
+    type BlocksStream = ResponseStream<FirehoseResponse>;
+
+    async fn blocks(
+        &self,
+        request: Request<FirehoseRequest>,
+    ) -> Result<Response<Self::BlocksStream>, Status> {
+        let request = request.into_inner();
+        if !request.transforms.is_empty() {
+            return Err(Status::unimplemented("Transforms are not supported"));
+        }
+
+        let step = if request.final_blocks_only {
+            ForkStep::StepFinal
+        } else {
+            ForkStep::StepNew
+        };
+        let service = self.clone();
+        let range = read(move || service.stream_range(&request)).await?;
+        debug!("Streaming blocks {} to {}", range.start(), range.end());
+
+        let stream = stream_blocks(Arc::clone(&self.store), range, move |block| {
+            FirehoseResponse {
+                cursor: Cursor::of(&block).to_string(),
+                block: Some(any(&block)),
+                step: step as i32,
+            }
+        });
+        Ok(Response::new(stream))
+    }
+}
+
+#[tonic::async_trait]
+impl fetch_server::Fetch for FirehoseService {
+    async fn block(
+        &self,
+        request: Request<SingleBlockRequest>,
+    ) -> Result<Response<SingleBlockResponse>, Status> {
+        let request = request.into_inner();
+        if !request.transforms.is_empty() {
+            return Err(Status::unimplemented("Transforms are not supported"));
+        }
+
+        let (number, hash) = match request.reference {
+            Some(Reference::BlockNumber(reference)) => (reference.num, None),
+            Some(Reference::BlockHashAndNumber(reference)) => {
+                let hash = hex::decode(&reference.hash).map_err(|_| {
+                    Status::invalid_argument(format!("Invalid block hash {}", reference.hash))
+                })?;
+                (reference.num, Some(hash))
+            }
+            Some(Reference::Cursor(reference)) => {
+                let cursor: Cursor = reference.cursor.parse()?;
+                (cursor.number, Some(cursor.hash))
+            }
+            None => return Err(Status::invalid_argument("Missing block reference")),
+        };
+
+        let service = self.clone();
+        let block = read(move || match hash {
+            Some(hash) => service.block_at(&Cursor { number, hash }),
+            None => service.read_block(number),
+        })
+        .await?;
+
+        Ok(Response::new(SingleBlockResponse {
+            block: Some(any(&block)),
+        }))
+    }
+}
+
+/// Serve the Firehose `Stream` and `Fetch` services over gRPC until the server fails.
+pub async fn serve_firehose(
+    listener: TcpListener,
+    service: FirehoseService,
+) -> Result<(), ServerError> {
+    info!("Serving Firehose on {}", listener.local_addr()?);
+    Server::builder()
+        .add_service(StreamServer::new(service.clone()))
+        .add_service(FetchServer::new(service))
+        .serve_with_incoming(incoming(listener)?)
+        .await?;
+    Ok(())
+}
+
+fn any(block: &Block) -> Any {
+    Any {
+        type_url: ETH_BLOCK_TYPE_URL.to_string(),
+        value: block.encode_to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use firehose_rs::single_block_request;
+    use tonic::{transport::Channel, Code};
+
+    use crate::{
+        sf::firehose::v2::{fetch_client::FetchClient, stream_client::StreamClient},
+        test_utils::{block_hash, store},
+    };
+
+    use super::*;
+
+    async fn serve() -> (StreamClient<Channel>, FetchClient<Channel>) {
+        let service = FirehoseService::new(store([0..100, 100..200]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_firehose(listener, service));
+
+        let channel = Channel::from_shared(url).unwrap().connect().await.unwrap();
+        (
+            StreamClient::new(channel.clone()),
+            FetchClient::new(channel),
+        )
+    }
+
+    async fn stream(
+        client: &mut StreamClient<Channel>,
+        request: FirehoseRequest,
+    ) -> Result<Vec<FirehoseResponse>, Status> {
+        let mut stream = client.blocks(request).await?.into_inner();
+        let mut responses = Vec::new();
+        while let Some(response) = stream.message().await? {
+            responses.push(response);
+        }
+        Ok(responses)
+    }
+
+    fn numbers(responses: &[FirehoseResponse]) -> Vec<u64> {
+        responses
+            .iter()
+            .map(|response| {
+                let any = response.block.as_ref().unwrap();
+                assert_eq!(any.type_url, ETH_BLOCK_TYPE_URL);
+                Block::decode(any.value.as_slice()).unwrap().number
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_blocks_and_resume_from_cursor() {
+        let (mut client, _) = serve().await;
+
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                start_block_num: 98,
+                stop_block_num: 101,
+                final_blocks_only: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![98, 99, 100, 101]);
+        assert!(responses
+            .iter()
+            .all(|response| response.step == ForkStep::StepFinal as i32));
+
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                cursor: responses[1].cursor.clone(),
+                stop_block_num: 102,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![100, 101, 102]);
+        assert_eq!(responses[0].step, ForkStep::StepNew as i32);
+
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                start_block_num: -2,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![197, 198, 199]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_invalid_requests() {
+        let (mut client, _) = serve().await;
+
+        let invalid = [
+            (
+                FirehoseRequest {
+                    start_block_num: 10,
+                    stop_block_num: 5,
+                    ..Default::default()
+                },
+                Code::InvalidArgument,
+            ),
+            (
+                FirehoseRequest {
+                    cursor: "not a cursor".to_string(),
+                    ..Default::default()
+                },
+                Code::InvalidArgument,
+            ),
+            (
+                FirehoseRequest {
+                    cursor: format!("5:{}", hex::encode(block_hash(6))),
+                    ..Default::default()
+                },
+                Code::NotFound,
+            ),
+            (
+                FirehoseRequest {
+                    transforms: vec![Any::default()],
+                    ..Default::default()
+                },
+                Code::Unimplemented,
+            ),
+        ];
+        for (request, code) in invalid {
+            assert_eq!(stream(&mut client, request).await.unwrap_err().code(), code);
+        }
+
+        // The stream ends with the last block of the store.
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                start_block_num: 199,
+                stop_block_num: 200,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![199]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_clamped_to_store() {
+        let service = FirehoseService::new(store([100..200, 200..300]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_firehose(listener, service));
+        let mut client = StreamClient::connect(url).await.unwrap();
+
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                stop_block_num: 101,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![100, 101]);
+
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                start_block_num: -1_000,
+                stop_block_num: 100,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![100]);
+
+        let responses = stream(
+            &mut client,
+            FirehoseRequest {
+                start_block_num: 298,
+                stop_block_num: u64::MAX,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(numbers(&responses), vec![298, 299]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_block() {
+        let (_, mut client) = serve().await;
+
+        let fetch = |reference| SingleBlockRequest {
+            reference: Some(reference),
+            ..Default::default()
+        };
+        let number = |response: SingleBlockResponse| {
+            Block::decode(response.block.unwrap().value.as_slice())
+                .unwrap()
+                .number
+        };
+
+        let response = client
+            .block(fetch(Reference::BlockNumber(
+                single_block_request::BlockNumber { num: 150 },
+            )))
+            .await
+            .unwrap();
+        assert_eq!(number(response.into_inner()), 150);
+
+        let response = client
+            .block(fetch(Reference::BlockHashAndNumber(
+                single_block_request::BlockHashAndNumber {
+                    num: 150,
+                    hash: hex::encode(block_hash(150)),
+                },
+            )))
+            .await
+            .unwrap();
+        assert_eq!(number(response.into_inner()), 150);
+
+        let response = client
+            .block(fetch(Reference::Cursor(single_block_request::Cursor {
+                cursor: format!("42:{}", hex::encode(block_hash(42))),
+            })))
+            .await
+            .unwrap();
+        assert_eq!(number(response.into_inner()), 42);
+
+        let error = client
+            .block(fetch(Reference::BlockHashAndNumber(
+                single_block_request::BlockHashAndNumber {
+                    num: 150,
+                    hash: hex::encode(block_hash(151)),
+                },
+            )))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::NotFound);
+
+        let error = client
+            .block(fetch(Reference::BlockNumber(
+                single_block_request::BlockNumber { num: 250 },
+            )))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::NotFound);
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers shared by the gRPC services.
+
+use std::{ops::RangeInclusive, pin::Pin, sync::Arc};
+
+use firehose_protos::EthBlock as Block;
+use flat_files_decoder::{DecoderError, FlatFileStore};
+use futures::Stream;
+use tokio::{net::TcpListener, sync::mpsc};
+use tonic::{transport::server::TcpIncoming, Status};
+
+use crate::error::ServerError;
+
+/// The number of responses buffered ahead of a slow client.
+const STREAM_BUFFER_SIZE: usize = 100;
+
+/// A stream of responses of a server-streaming gRPC method.
+pub(crate) type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Stream responses for the blocks of a store in a range, read on a blocking thread.
+///
+/// The stream ends with the range, or with the first error reading the blocks.
+pub(crate) fn stream_blocks<T, F>(
+    store: Arc<FlatFileStore>,
+    range: RangeInclusive<u64>,
+    response: F,
+) -> ResponseStream<T>
+where
+    T: Send + 'static,
+    F: Fn(Block) -> T + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+    tokio::task::spawn_blocking(move || {
+        for block in store.blocks(range) {
+            let response = block.map(&response).map_err(status);
+            let is_err = response.is_err();
+            if sender.blocking_send(response).is_err() || is_err {
+                break;
+            }
+        }
+    });
+
+    Box::pin(futures::stream::unfold(
+        receiver,
+        |mut receiver| async move { receiver.recv().await.map(|response| (response, receiver)) },
+    ))
+}
+
+/// Run a blocking read of the store on a blocking thread.
+pub(crate) async fn read<T, F>(read: F) -> Result<T, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+{
+    tokio::task::spawn_blocking(read)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+}
+
+/// Accept gRPC connections on a listener.
+pub(crate) fn incoming(listener: TcpListener) -> Result<TcpIncoming, ServerError> {
+    let incoming =
+        TcpIncoming::from_listener(listener, true, None).map_err(std::io::Error::other)?;
+    Ok(incoming)
+}
+
+/// The gRPC status of an error reading blocks.
+pub(crate) fn status(error: DecoderError) -> Status {
+    match error {
+        DecoderError::BundleMissing {
+            start_block,
+            end_block,
+        } => Status::not_found(format!(
+            "Bundles of blocks {start_block} to {end_block} not found"
+        )),
+        e => Status::internal(e.to_string()),
+    }
+}
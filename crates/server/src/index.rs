@@ -0,0 +1,261 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::{RangeBounds, RangeInclusive},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use alloy_primitives::B256;
+use firehose_protos::EthBlock as Block;
+use flat_files_decoder::{FlatFileStore, BUNDLE_SIZE};
+use tracing::info;
+
+use crate::error::ServerError;
+
+/// The number of bundles of decoded blocks a [`BlockIndex`] keeps in memory by default.
+pub const DEFAULT_CACHED_BUNDLES: usize = 8;
+
+/// An index of the blocks of a [`FlatFileStore`] in a range, by number, by hash and by the
+/// hashes of their transactions.
+///
+/// Only the hashes are held in memory, so memory use does not grow with the size of the blocks.
+/// Blocks are read from the store when they are requested, a bundle at a time, and the most
+/// recently read bundles are cached. Blocks are verified as they are read, according to the
+/// verification options of the store, so the index only serves verified data.
+#[derive(Debug)]
+pub struct BlockIndex {
+    store: FlatFileStore,
+    range: Option<RangeInclusive<u64>>,
+    block_numbers: HashMap<B256, u64>,
+    transaction_blocks: HashMap<B256, u64>,
+    cache: Mutex<VecDeque<(u64, Vec<Arc<Block>>)>>,
+    cached_bundles: usize,
+}
+
+impl BlockIndex {
+    /// Index the blocks of a store in the given range of block numbers.
+    ///
+    /// The range is clamped to the bundles of the store. Fails if any bundle of the range is
+    /// missing from the store, or fails to decode or verify.
+    pub fn load<R: RangeBounds<u64>>(store: FlatFileStore, range: R) -> Result<Self, ServerError> {
+        let mut index = Self {
+            range: None,
+            store,
+            block_numbers: HashMap::new(),
+            transaction_blocks: HashMap::new(),
+            cache: Mutex::new(VecDeque::new()),
+            cached_bundles: DEFAULT_CACHED_BUNDLES,
+        };
+
+        for block in index.store.blocks(range) {
+            let block = block?;
+            index
+                .block_numbers
+                .insert(B256::from_slice(&block.hash), block.number);
+            for trace in &block.transaction_traces {
+                index
+                    .transaction_blocks
+                    .insert(B256::from_slice(&trace.hash), block.number);
+            }
+            index.range = Some(match index.range {
+                Some(range) => *range.start()..=block.number,
+                None => block.number..=block.number,
+            });
+        }
+
+        if let Some(range) = &index.range {
+            info!(
+                "Indexed {} blocks from {} to {}",
+                index.len(),
+                range.start(),
+                range.end()
+            );
+        }
+        Ok(index)
+    }
+
+    /// Set how many bundles of decoded blocks are kept in memory.
+    pub fn with_cached_bundles(mut self, cached_bundles: usize) -> Self {
+        self.cached_bundles = cached_bundles;
+        self
+    }
+
+    /// Get the number of indexed blocks.
+    pub fn len(&self) -> usize {
+        self.block_numbers.len()
+    }
+
+    /// Check whether the index holds no blocks.
+    pub fn is_empty(&self) -> bool {
+        self.block_numbers.is_empty()
+    }
+
+    /// Get the lowest indexed block number.
+    pub fn earliest(&self) -> Option<u64> {
+        self.range.as_ref().map(|range| *range.start())
+    }
+
+    /// Get the highest indexed block number.
+    pub fn latest(&self) -> Option<u64> {
+        self.range.as_ref().map(|range| *range.end())
+    }
+
+    /// Get a block by number.
+    pub fn block_by_number(&self, number: u64) -> Result<Option<Arc<Block>>, ServerError> {
+        if !self
+            .range
+            .as_ref()
+            .is_some_and(|range| range.contains(&number))
+        {
+            return Ok(None);
+        }
+
+        let start_block = number - number % BUNDLE_SIZE;
+        let bundle = self.bundle(start_block)?;
+        Ok(bundle.into_iter().find(|block| block.number == number))
+    }
+
+    /// Get a block by hash.
+    pub fn block_by_hash(&self, hash: &B256) -> Result<Option<Arc<Block>>, ServerError> {
+        match self.block_numbers.get(hash) {
+            Some(&number) => self.block_by_number(number),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the block containing the transaction with the given hash.
+    pub fn block_by_transaction_hash(
+        &self,
+        hash: &B256,
+    ) -> Result<Option<Arc<Block>>, ServerError> {
+        match self.transaction_blocks.get(hash) {
+            Some(&number) => self.block_by_number(number),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the indexed blocks in a range of block numbers, in block order.
+    ///
+    /// The blocks are read straight from the store, without going through the cache.
+    pub fn blocks(
+        &self,
+        range: RangeInclusive<u64>,
+    ) -> impl Iterator<Item = Result<Block, ServerError>> + '_ {
+        self.range
+            .iter()
+            .flat_map(move |indexed| {
+                let start = *range.start().max(indexed.start());
+                let end = *range.end().min(indexed.end());
+                self.store.blocks(start..=end)
+            })
+            .map(|block| block.map_err(ServerError::from))
+    }
+
+    /// Get the blocks of the bundle starting at the given block, from the cache or the store.
+    fn bundle(&self, start_block: u64) -> Result<Vec<Arc<Block>>, ServerError> {
+        if let Some(blocks) = self.cached(start_block) {
+            return Ok(blocks);
+        }
+
+        let blocks = self
+            .blocks(start_block..=start_block + BUNDLE_SIZE - 1)
+            .map(|block| block.map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut cache = self.lock_cache();
+        cache.push_front((start_block, blocks.clone()));
+        cache.truncate(self.cached_bundles);
+        Ok(blocks)
+    }
+
+    /// Get the blocks of a cached bundle, marking it as the most recently used.
+    fn cached(&self, start_block: u64) -> Option<Vec<Arc<Block>>> {
+        let mut cache = self.lock_cache();
+        let position = cache.iter().position(|(start, _)| *start == start_block)?;
+        let entry = cache.remove(position)?;
+        let blocks = entry.1.clone();
+        cache.push_front(entry);
+        Some(blocks)
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, VecDeque<(u64, Vec<Arc<Block>>)>> {
+        // The cache is only a copy of the store, so it is still usable after a panic.
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use firehose_protos::TransactionTrace;
+    use flat_files_decoder::VerificationOptions;
+
+    use crate::test_utils::{block, block_hash, bundle_store, store};
+
+    use super::*;
+
+    #[test]
+    fn test_block_index_lookups() {
+        let mut with_transaction = block(2);
+        with_transaction.transaction_traces = vec![TransactionTrace {
+            hash: vec![0xaa; 32],
+            ..Default::default()
+        }];
+        let store = bundle_store([vec![block(1), with_transaction, block(3)]]).with_verification(
+            VerificationOptions {
+                checks: Vec::new(),
+                ..Default::default()
+            },
+        );
+
+        let index = BlockIndex::load(store, ..).unwrap();
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.earliest(), Some(1));
+        assert_eq!(index.latest(), Some(3));
+        assert_eq!(
+            index
+                .block_by_hash(&B256::from(block_hash(3)))
+                .unwrap()
+                .map(|block| block.number),
+            Some(3)
+        );
+        assert_eq!(
+            index
+                .block_by_transaction_hash(&B256::repeat_byte(0xaa))
+                .unwrap()
+                .map(|block| block.number),
+            Some(2)
+        );
+        assert!(index.block_by_number(4).unwrap().is_none());
+        let numbers: Vec<_> = index
+            .blocks(2..=10)
+            .map(|block| block.unwrap().number)
+            .collect();
+        assert_eq!(numbers, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_block_index_clamped_to_store() {
+        let index = BlockIndex::load(store([100..200, 200..300]), 0..=u64::MAX)
+            .unwrap()
+            .with_cached_bundles(1);
+
+        assert_eq!(index.earliest(), Some(100));
+        assert_eq!(index.latest(), Some(299));
+        assert!(index.block_by_number(99).unwrap().is_none());
+        for number in [150, 250, 150] {
+            let block = index.block_by_number(number).unwrap().unwrap();
+            assert_eq!(block.number, number);
+        }
+        assert_eq!(index.lock_cache().len(), 1);
+
+        let index = BlockIndex::load(store([100..200, 200..300]), 120..150).unwrap();
+        assert_eq!(index.len(), 30);
+        assert!(index.block_by_number(150).unwrap().is_none());
+        assert_eq!(index.blocks(0..=u64::MAX).count(), 30);
+    }
+}
@@ -0,0 +1,533 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{ops::RangeInclusive, sync::Arc};
+
+use alloy_primitives::{B256, U64};
+use alloy_rpc_types_eth::{
+    BlockId, BlockNumberOrTag, Filter, FilterBlockOption, FilteredParams, Log,
+};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use firehose_protos::{EthBlock as Block, ProtosError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+use crate::{error::ServerError, index::BlockIndex};
+
+/// The maximum number of blocks an `eth_getLogs` request may span by default.
+pub const DEFAULT_MAX_LOGS_BLOCK_RANGE: u64 = 10_000;
+
+/// The error object of a failed JSON-RPC call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// The JSON-RPC error code.
+    pub code: i64,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl RpcError {
+    const PARSE_ERROR: i64 = -32700;
+    const INVALID_REQUEST: i64 = -32600;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+    const INTERNAL_ERROR: i64 = -32603;
+    const LIMIT_EXCEEDED: i64 = -32005;
+
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl ToString) -> Self {
+        Self::new(Self::INVALID_PARAMS, message.to_string())
+    }
+}
+
+impl From<ProtosError> for RpcError {
+    fn from(error: ProtosError) -> Self {
+        Self::new(Self::INTERNAL_ERROR, error.to_string())
+    }
+}
+
+impl From<ServerError> for RpcError {
+    fn from(error: ServerError) -> Self {
+        Self::new(Self::INTERNAL_ERROR, error.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct ResponseObject {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl ResponseObject {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// A read-only Ethereum JSON-RPC API answering from a [`BlockIndex`].
+///
+/// Supports `eth_blockNumber`, `eth_getBlockByNumber`, `eth_getBlockByHash`,
+/// `eth_getTransactionByHash`, `eth_getTransactionReceipt`, `eth_getBlockReceipts` and
+/// `eth_getLogs`. Blocks outside of the index are reported as not found, with a `null` result.
+/// The `earliest` tag resolves to the lowest indexed block, the `latest`, `safe` and `finalized`
+/// tags to the highest indexed block, as flat files only hold final blocks, and `pending` to no
+/// block.
+#[derive(Clone, Debug)]
+pub struct JsonRpc {
+    index: Arc<BlockIndex>,
+    max_logs_block_range: u64,
+}
+
+impl JsonRpc {
+    /// Serve the blocks of an index.
+    pub fn new(index: Arc<BlockIndex>) -> Self {
+        Self {
+            index,
+            max_logs_block_range: DEFAULT_MAX_LOGS_BLOCK_RANGE,
+        }
+    }
+
+    /// Set the maximum number of blocks an `eth_getLogs` request may span.
+    pub fn with_max_logs_block_range(mut self, max_logs_block_range: u64) -> Self {
+        self.max_logs_block_range = max_logs_block_range;
+        self
+    }
+
+    /// Answer the body of a JSON-RPC HTTP request, a single request or a batch of requests.
+    ///
+    /// Returns `None` if there is nothing to answer, as when every request is a notification,
+    /// without `id`.
+    pub fn handle(&self, body: &[u8]) -> Option<Value> {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = RpcError::new(RpcError::PARSE_ERROR, e.to_string());
+                return Some(to_value(ResponseObject::new(Value::Null, Err(error))));
+            }
+        };
+
+        match request {
+            Value::Array(requests) if requests.is_empty() => {
+                let error = RpcError::new(RpcError::INVALID_REQUEST, "Empty batch");
+                Some(to_value(ResponseObject::new(Value::Null, Err(error))))
+            }
+            Value::Array(requests) => {
+                let responses: Vec<_> = requests
+                    .into_iter()
+                    .filter_map(|request| self.handle_request(request))
+                    .collect();
+                (!responses.is_empty()).then(|| to_value(responses))
+            }
+            request => self.handle_request(request).map(to_value),
+        }
+    }
+
+    fn handle_request(&self, request: Value) -> Option<ResponseObject> {
+        let request: Request = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = RpcError::new(RpcError::INVALID_REQUEST, e.to_string());
+                return Some(ResponseObject::new(Value::Null, Err(error)));
+            }
+        };
+
+        debug!("JSON-RPC call {}", request.method);
+        let outcome = self.call(&request.method, request.params);
+        request.id.map(|id| ResponseObject::new(id, outcome))
+    }
+
+    /// Call a method with its JSON-RPC parameters, returning its JSON result.
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "eth_blockNumber" => {
+                let latest = self
+                    .index
+                    .latest()
+                    .ok_or_else(|| RpcError::new(RpcError::INTERNAL_ERROR, "No blocks indexed"))?;
+                Ok(to_value(U64::from(latest)))
+            }
+            "eth_getBlockByNumber" => {
+                let (number, full): (BlockNumberOrTag, bool) = parse_params(params)?;
+                let block = self.block_by_number(number)?;
+                Ok(to_value(
+                    block.map(|block| block.rpc_block(full)).transpose()?,
+                ))
+            }
+            "eth_getBlockByHash" => {
+                let (hash, full): (B256, bool) = parse_params(params)?;
+                let block = self.index.block_by_hash(&hash)?;
+                Ok(to_value(
+                    block.map(|block| block.rpc_block(full)).transpose()?,
+                ))
+            }
+            "eth_getTransactionByHash" => {
+                let (hash,): (B256,) = parse_params(params)?;
+                let block = self.index.block_by_transaction_hash(&hash)?;
+                let transaction = match block {
+                    Some(block) => block.rpc_transaction(&hash)?,
+                    None => None,
+                };
+                Ok(to_value(transaction))
+            }
+            "eth_getTransactionReceipt" => {
+                let (hash,): (B256,) = parse_params(params)?;
+                let block = self.index.block_by_transaction_hash(&hash)?;
+                let receipt = match block {
+                    Some(block) => block.rpc_receipt(&hash)?,
+                    None => None,
+                };
+                Ok(to_value(receipt))
+            }
+            "eth_getBlockReceipts" => {
+                let (block_id,): (BlockId,) = parse_params(params)?;
+                let block = match block_id {
+                    BlockId::Hash(hash) => self.index.block_by_hash(&hash.block_hash)?,
+                    BlockId::Number(number) => self.block_by_number(number)?,
+                };
+                Ok(to_value(
+                    block.map(|block| block.rpc_receipts()).transpose()?,
+                ))
+            }
+            "eth_getLogs" => {
+                let (filter,): (Filter,) = parse_params(params)?;
+                Ok(to_value(self.logs(filter)?))
+            }
+            method => Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("Method {method} not found"),
+            )),
+        }
+    }
+
+    fn block_by_number(&self, number: BlockNumberOrTag) -> Result<Option<Arc<Block>>, RpcError> {
+        match self.resolve(number) {
+            Some(number) => Ok(self.index.block_by_number(number)?),
+            None => Ok(None),
+        }
+    }
+
+    fn resolve(&self, number: BlockNumberOrTag) -> Option<u64> {
+        match number {
+            BlockNumberOrTag::Number(number) => Some(number),
+            BlockNumberOrTag::Earliest => self.index.earliest(),
+            BlockNumberOrTag::Latest | BlockNumberOrTag::Safe | BlockNumberOrTag::Finalized => {
+                self.index.latest()
+            }
+            BlockNumberOrTag::Pending => None,
+        }
+    }
+
+    fn logs(&self, filter: Filter) -> Result<Vec<Log>, RpcError> {
+        let blocks: Vec<Arc<Block>> = match &filter.block_option {
+            FilterBlockOption::AtBlockHash(hash) => {
+                self.index.block_by_hash(hash)?.into_iter().collect()
+            }
+            FilterBlockOption::Range {
+                from_block,
+                to_block,
+            } => match self.logs_range(*from_block, *to_block)? {
+                Some(range) => self
+                    .index
+                    .blocks(range)
+                    .map(|block| block.map(Arc::new))
+                    .collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            },
+        };
+
+        let params = FilteredParams::new(Some(filter));
+        let mut logs = Vec::new();
+        for block in blocks {
+            logs.extend(block.rpc_logs()?.into_iter().filter(|log| {
+                params.filter_address(&log.address()) && params.filter_topics(log.topics())
+            }));
+        }
+        Ok(logs)
+    }
+
+    fn logs_range(
+        &self,
+        from_block: Option<BlockNumberOrTag>,
+        to_block: Option<BlockNumberOrTag>,
+    ) -> Result<Option<RangeInclusive<u64>>, RpcError> {
+        let from = self.resolve(from_block.unwrap_or_default());
+        let to = self.resolve(to_block.unwrap_or_default());
+        let (Some(from), Some(to)) = (from, to) else {
+            return Ok(None);
+        };
+
+        if from > to {
+            return Err(RpcError::invalid_params(format!(
+                "Invalid block range {from} to {to}"
+            )));
+        }
+        if to - from >= self.max_logs_block_range {
+            return Err(RpcError::new(
+                RpcError::LIMIT_EXCEEDED,
+                format!(
+                    "Block range {from} to {to} exceeds the limit of {} blocks",
+                    self.max_logs_block_range
+                ),
+            ));
+        }
+        Ok(Some(from..=to))
+    }
+}
+
+/// Serve the JSON-RPC API over HTTP, with requests posted to `/`, until the server fails.
+pub async fn serve_json_rpc(listener: TcpListener, json_rpc: JsonRpc) -> Result<(), ServerError> {
+    info!("Serving JSON-RPC on {}", listener.local_addr()?);
+    let app = Router::new()
+        .route("/", post(handle_http))
+        .with_state(Arc::new(json_rpc));
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn handle_http(State(json_rpc): State<Arc<JsonRpc>>, body: Bytes) -> Response {
+    let response = tokio::task::spawn_blocking(move || json_rpc.handle(&body)).await;
+    match response {
+        Ok(Some(response)) => Json(response).into_response(),
+        Ok(None) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+fn to_value<T: Serialize>(value: T) -> Value {
+    serde_json::to_value(value).expect("JSON-RPC types serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Address;
+    use firehose_protos::{Log as TraceLog, TransactionReceipt, TransactionTrace};
+    use flat_files_decoder::VerificationOptions;
+    use serde_json::json;
+
+    use crate::test_utils::{block, block_hash, bundle_store};
+
+    use super::*;
+
+    fn json_rpc() -> JsonRpc {
+        let mut with_transfer = block(2);
+        with_transfer.transaction_traces = vec![TransactionTrace {
+            hash: vec![0xaa; 32],
+            from: vec![0x11; 20],
+            to: vec![0x22; 20],
+            r: vec![0x01; 32],
+            s: vec![0x02; 32],
+            v: vec![27],
+            status: 1,
+            receipt: Some(TransactionReceipt {
+                logs_bloom: vec![0; 256],
+                logs: vec![
+                    TraceLog {
+                        address: vec![0x33; 20],
+                        topics: vec![vec![0x44; 32]],
+                        block_index: 0,
+                        ..Default::default()
+                    },
+                    TraceLog {
+                        address: vec![0x55; 20],
+                        block_index: 1,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }];
+
+        let store = bundle_store([vec![block(1), with_transfer, block(3)]]).with_verification(
+            VerificationOptions {
+                checks: Vec::new(),
+                ..Default::default()
+            },
+        );
+        JsonRpc::new(Arc::new(BlockIndex::load(store, ..).unwrap()))
+    }
+
+    fn request(json_rpc: &JsonRpc, method: &str, params: Value) -> Value {
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        json_rpc.handle(body.to_string().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_json_rpc_blocks() {
+        let json_rpc = json_rpc();
+
+        let response = request(&json_rpc, "eth_blockNumber", json!([]));
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": 1, "result": "0x3"})
+        );
+
+        let response = request(&json_rpc, "eth_getBlockByNumber", json!(["0x2", false]));
+        let hash = B256::from(block_hash(2));
+        assert_eq!(response["result"]["hash"], json!(hash));
+        assert_eq!(
+            response["result"]["transactions"],
+            json!([B256::repeat_byte(0xaa)])
+        );
+
+        let response = request(&json_rpc, "eth_getBlockByHash", json!([hash, true]));
+        assert_eq!(response["result"]["number"], "0x2");
+        assert_eq!(
+            response["result"]["transactions"][0]["blockHash"],
+            json!(hash)
+        );
+
+        let response = request(&json_rpc, "eth_getBlockByNumber", json!(["latest", false]));
+        assert_eq!(response["result"]["number"], "0x3");
+
+        let response = request(
+            &json_rpc,
+            "eth_getBlockByNumber",
+            json!(["earliest", false]),
+        );
+        assert_eq!(response["result"]["number"], "0x1");
+
+        let response = request(&json_rpc, "eth_getBlockByNumber", json!(["0x4", false]));
+        assert_eq!(response["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_json_rpc_transactions_and_receipts() {
+        let json_rpc = json_rpc();
+        let hash = B256::repeat_byte(0xaa);
+
+        let response = request(&json_rpc, "eth_getTransactionByHash", json!([hash]));
+        assert_eq!(response["result"]["blockNumber"], "0x2");
+
+        let response = request(&json_rpc, "eth_getTransactionReceipt", json!([hash]));
+        assert_eq!(response["result"]["transactionHash"], json!(hash));
+        assert_eq!(response["result"]["logs"][1]["logIndex"], "0x1");
+
+        let response = request(&json_rpc, "eth_getBlockReceipts", json!(["0x2"]));
+        assert_eq!(response["result"].as_array().map(Vec::len), Some(1));
+
+        let response = request(
+            &json_rpc,
+            "eth_getTransactionByHash",
+            json!([B256::repeat_byte(0xdd)]),
+        );
+        assert_eq!(response["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_json_rpc_logs() {
+        let json_rpc = json_rpc().with_max_logs_block_range(2);
+
+        let response = request(
+            &json_rpc,
+            "eth_getLogs",
+            json!([{"fromBlock": "0x2", "toBlock": "0x3"}]),
+        );
+        assert_eq!(response["result"].as_array().map(Vec::len), Some(2));
+
+        let response = request(
+            &json_rpc,
+            "eth_getLogs",
+            json!([{"fromBlock": "0x2", "toBlock": "0x3", "address": Address::repeat_byte(0x33)}]),
+        );
+        assert_eq!(response["result"].as_array().map(Vec::len), Some(1));
+
+        let response = request(
+            &json_rpc,
+            "eth_getLogs",
+            json!([{"fromBlock": "earliest", "toBlock": "0x2"}]),
+        );
+        assert_eq!(response["result"].as_array().map(Vec::len), Some(2));
+
+        let response = request(
+            &json_rpc,
+            "eth_getLogs",
+            json!([{"blockHash": B256::from(block_hash(2)), "topics": [B256::repeat_byte(0x44)]}]),
+        );
+        assert_eq!(
+            response["result"][0]["address"],
+            json!(Address::repeat_byte(0x33))
+        );
+
+        let response = request(
+            &json_rpc,
+            "eth_getLogs",
+            json!([{"fromBlock": "0x1", "toBlock": "0x3"}]),
+        );
+        assert_eq!(response["error"]["code"], RpcError::LIMIT_EXCEEDED);
+    }
+
+    #[test]
+    fn test_json_rpc_errors_and_batches() {
+        let json_rpc = json_rpc();
+
+        let response = request(&json_rpc, "eth_sendRawTransaction", json!(["0x"]));
+        assert_eq!(response["error"]["code"], RpcError::METHOD_NOT_FOUND);
+
+        let response = request(&json_rpc, "eth_getBlockByNumber", json!(["0x2"]));
+        assert_eq!(response["error"]["code"], RpcError::INVALID_PARAMS);
+
+        let response = json_rpc.handle(b"{").unwrap();
+        assert_eq!(response["error"]["code"], RpcError::PARSE_ERROR);
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber"},
+            {"jsonrpc": "2.0", "method": "eth_blockNumber"},
+            {"jsonrpc": "2.0", "id": "two", "method": "eth_blockNumber"},
+        ]);
+        let response = json_rpc.handle(batch.to_string().as_bytes()).unwrap();
+        assert_eq!(
+            response,
+            json!([
+                {"jsonrpc": "2.0", "id": 1, "result": "0x3"},
+                {"jsonrpc": "2.0", "id": "two", "result": "0x3"},
+            ])
+        );
+
+        let notification = json!({"jsonrpc": "2.0", "method": "eth_blockNumber"});
+        assert!(json_rpc
+            .handle(notification.to_string().as_bytes())
+            .is_none());
+    }
+}
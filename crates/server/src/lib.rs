@@ -0,0 +1,33 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+#![deny(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+mod bstream;
+mod error;
+mod firehose;
+mod grpc;
+mod index;
+mod json_rpc;
+#[cfg(test)]
+mod test_utils;
+
+mod sf {
+    pub mod firehose {
+        #[allow(missing_docs)]
+        pub mod v2 {
+            tonic::include_proto!("sf.firehose.v2");
+        }
+    }
+}
+
+pub use bstream::*;
+pub use error::*;
+pub use firehose::*;
+pub use index::*;
+pub use json_rpc::*;
+pub use sf::firehose::v2::{
+    fetch_client::FetchClient, fetch_server::FetchServer, stream_client::StreamClient,
+    stream_server::StreamServer,
+};
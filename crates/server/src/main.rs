@@ -0,0 +1,128 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{net::SocketAddr, ops::Bound, process::ExitCode, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use flat_files_decoder::FlatFileStore;
+use flat_files_server::{
+    serve_bstream, serve_firehose, serve_json_rpc, BlockIndex, BlockStreamService, FirehoseService,
+    JsonRpc, ServerError, DEFAULT_MAX_LOGS_BLOCK_RANGE,
+};
+use tokio::net::TcpListener;
+use tracing::{error, level_filters::LevelFilter, subscriber::set_global_default};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+fn main() -> ExitCode {
+    init_tracing();
+    if let Err(e) = run() {
+        error!("Server error: {e}");
+        return ExitCode::from(1);
+    }
+    ExitCode::SUCCESS
+}
+
+fn init_tracing() {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    set_global_default(FmtSubscriber::builder().with_env_filter(filter).finish()).expect(
+        "Failed to set up the global default subscriber for logging. Please check if the RUST_LOG environment variable is set correctly.",
+    );
+}
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Serve a read-only Ethereum JSON-RPC API over HTTP from a folder of flat files
+    JsonRpc {
+        /// Path to the folder containing flat files
+        #[clap(short, long)]
+        dir: String,
+
+        /// First block to serve
+        #[clap(short, long, default_value_t = 0)]
+        start_block: u64,
+
+        /// Last block to serve, or the last block of the flat files if not set
+        #[clap(short, long)]
+        end_block: Option<u64>,
+
+        /// Address to listen on
+        #[clap(short, long, default_value = "127.0.0.1:8545")]
+        addr: SocketAddr,
+
+        /// Maximum number of blocks an eth_getLogs request may span
+        #[clap(long, default_value_t = DEFAULT_MAX_LOGS_BLOCK_RANGE)]
+        max_logs_block_range: u64,
+    },
+
+    /// Serve the Firehose Stream and Fetch gRPC services from a folder of flat files
+    Firehose {
+        /// Path to the folder containing flat files
+        #[clap(short, long)]
+        dir: String,
+
+        /// Address to listen on
+        #[clap(short, long, default_value = "127.0.0.1:10015")]
+        addr: SocketAddr,
+    },
+
+    /// Serve the bstream BlockStream gRPC service of relayers from a folder of flat files
+    Bstream {
+        /// Path to the folder containing flat files
+        #[clap(short, long)]
+        dir: String,
+
+        /// Address to listen on
+        #[clap(short, long, default_value = "127.0.0.1:10014")]
+        addr: SocketAddr,
+    },
+}
+
+fn run() -> Result<(), ServerError> {
+    let cli = Cli::parse();
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    match cli.command {
+        Commands::JsonRpc {
+            dir,
+            start_block,
+            end_block,
+            addr,
+            max_logs_block_range,
+        } => {
+            let end_block = end_block.map_or(Bound::Unbounded, Bound::Included);
+            let index = BlockIndex::load(
+                FlatFileStore::open(dir)?,
+                (Bound::Included(start_block), end_block),
+            )?;
+            let json_rpc =
+                JsonRpc::new(Arc::new(index)).with_max_logs_block_range(max_logs_block_range);
+            runtime.block_on(async {
+                let listener = TcpListener::bind(addr).await?;
+                serve_json_rpc(listener, json_rpc).await
+            })
+        }
+        Commands::Firehose { dir, addr } => {
+            let service = FirehoseService::new(FlatFileStore::open(dir)?);
+            runtime.block_on(async {
+                let listener = TcpListener::bind(addr).await?;
+                serve_firehose(listener, service).await
+            })
+        }
+        Commands::Bstream { dir, addr } => {
+            let service = BlockStreamService::new(FlatFileStore::open(dir)?);
+            runtime.block_on(async {
+                let listener = TcpListener::bind(addr).await?;
+                serve_bstream(listener, service).await
+            })
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2024-, Semiotic AI, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{ops::Range, sync::Arc};
+
+use alloy_primitives::hex;
+use firehose_protos::{BlockHeader, BstreamBlock, EthBlock as Block};
+use flat_files_decoder::{
+    Compression, DbinWriter, FlatFileBundle, FlatFileStore, MemoryStorage, BUNDLE_SIZE,
+};
+use prost::Message;
+use prost_wkt_types::Timestamp;
+
+/// Root of an empty trie, the receipt and transaction root of a block without transactions.
+const EMPTY_ROOT: [u8; 32] =
+    hex!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+
+/// A block with a well-formed header, hashed after its number, that passes verification as
+/// long as it has no transactions.
+pub(crate) fn block(number: u64) -> Block {
+    Block {
+        hash: block_hash(number).to_vec(),
+        number,
+        header: Some(BlockHeader {
+            number,
+            hash: block_hash(number).to_vec(),
+            parent_hash: block_hash(number.saturating_sub(1)).to_vec(),
+            uncle_hash: vec![0; 32],
+            coinbase: vec![0; 20],
+            state_root: vec![0; 32],
+            transactions_root: EMPTY_ROOT.to_vec(),
+            receipt_root: EMPTY_ROOT.to_vec(),
+            logs_bloom: vec![0; 256],
+            mix_hash: vec![0; 32],
+            timestamp: Some(Timestamp {
+                seconds: 1_438_269_973 + number as i64 * 12,
+                nanos: 0,
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// The hash of the block built by [`block`].
+pub(crate) fn block_hash(number: u64) -> [u8; 32] {
+    let mut hash = [0xbb; 32];
+    hash[24..].copy_from_slice(&number.to_be_bytes());
+    hash
+}
+
+/// A store of uncompressed bundles of the blocks built by [`block`], one for each bundle start.
+pub(crate) fn store(bundles: impl IntoIterator<Item = Range<u64>>) -> FlatFileStore {
+    bundle_store(
+        bundles
+            .into_iter()
+            .map(|blocks| blocks.map(block).collect()),
+    )
+}
+
+/// A store of uncompressed bundles of blocks, each named after the start of the bundle of its
+/// first block.
+pub(crate) fn bundle_store(bundles: impl IntoIterator<Item = Vec<Block>>) -> FlatFileStore {
+    let storage = MemoryStorage::new();
+    for blocks in bundles {
+        let mut writer = DbinWriter::new(Vec::new(), "ETH", "01", Compression::None).unwrap();
+        for block in &blocks {
+            let message = BstreamBlock {
+                number: block.number,
+                payload_buffer: block.encode_to_vec(),
+                ..Default::default()
+            };
+            writer.write_message(&message.encode_to_vec()).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let start_block = blocks[0].number - blocks[0].number % BUNDLE_SIZE;
+        storage.insert(&FlatFileBundle::file_name(start_block), bytes);
+    }
+    FlatFileStore::from_storage(Arc::new(storage), "").unwrap()
+}